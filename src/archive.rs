@@ -0,0 +1,306 @@
+//! Serving files straight from a `.tar` or `.zip` archive.
+
+use super::{
+    Handler, HandlerFuture, Request, Response, ResponseBody, ResponseCode, decode_path, mime,
+    response::{byte_range, content_range, file_method_not_allowed},
+};
+use compio::fs::File;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Size of tar blocks.
+const BLOCK: usize = 512;
+/// Signature of the end of central directory record of zip archives.
+const ZIP_END: [u8; 4] = *b"PK\x05\x06";
+/// Signature of central directory headers of zip archives.
+const ZIP_CENTRAL: [u8; 4] = *b"PK\x01\x02";
+/// Signature of local file headers of zip archives.
+const ZIP_LOCAL: [u8; 4] = *b"PK\x03\x04";
+
+/// A [`Handler`] serving the files of a `.tar` or `.zip` archive, without extracting them.
+///
+/// The index of the archive is read once when it is opened, and entries are then read from the
+/// archive as requested, with support for ranges. Entries of zip archives must be stored without
+/// compression; compressed ones are skipped with a warning. `index.html` is served for
+/// directories, requests for missing entries get `404 Not Found`, and methods other than `GET`
+/// and `HEAD` get `405 Method Not Allowed`, so that the archive acts as the document root.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    /// The archive.
+    file: File,
+    /// Offset and size of entries, by absolute path.
+    entries: HashMap<String, (u64, u64)>,
+}
+
+impl Archive {
+    /// Opens an archive and reads its index. Zip archives are recognized by their signature,
+    /// and other files are read as tar archives.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the archive cannot be read or is malformed.
+    pub async fn open(path: impl AsRef<Path>) -> IoResult<Self> {
+        let path = path.as_ref();
+        let mut archive = fs::File::open(path)?;
+        let mut signature = [0; 4];
+        let read = archive.read(&mut signature)?;
+        let entries = if read == 4 && (signature == ZIP_LOCAL || signature == ZIP_END) {
+            zip_index(&mut archive, path)?
+        } else {
+            tar_index(&mut archive)?
+        };
+        println!("Loaded {} entries from {}", entries.len(), path.display());
+        Ok(Self {
+            file: File::open(path).await?,
+            entries,
+        })
+    }
+
+    /// Get the offset and size of the entry for a decoded path relative to the root of the
+    /// archive, trying `index.html` for directories.
+    fn entry(&self, path: &str) -> Option<(u64, u64)> {
+        if path.is_empty() || path.ends_with('/') {
+            self.entries.get(&format!("/{path}index.html")).copied()
+        } else {
+            self.entries.get(&format!("/{path}")).copied()
+        }
+    }
+}
+
+impl Handler for Archive {
+    fn handle<'a>(&'a self, request: &'a Request<'_>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            if !matches!(request.method, "GET" | "HEAD") {
+                return Some(file_method_not_allowed());
+            }
+            let path = request
+                .path
+                .split_once('?')
+                .map_or(request.path, |(path, _)| path);
+            // Entry names are not percent-encoded, unlike request paths
+            let Some(path) = decode_path(path) else {
+                return Some(Response::bad_request("Invalid request path"));
+            };
+            let Some((offset, size)) = self.entry(&path) else {
+                return Some(Response::not_found());
+            };
+            let (code, start, end, headers) = match byte_range(request, size) {
+                Ok(None) => (ResponseCode::Ok, 0, size, Vec::new()),
+                Ok(Some((start, end))) => {
+                    let headers = vec![content_range(start, end, size)];
+                    (ResponseCode::PartialContent, start, end, headers)
+                }
+                Err(response) => return Some(response),
+            };
+            let body = if request.method == "HEAD" {
+                ResponseBody::Omitted { size: end - start }
+            } else {
+                ResponseBody::PartialFile {
+                    file: self.file.clone(),
+                    start: offset + start,
+                    end: offset + end,
+                }
+            };
+            let response = Response {
+                code,
+                headers,
+                body,
+                chunk_size: None,
+            };
+            Some(response.with_header("Content-Type", mime::guess(request.path)))
+        })
+    }
+}
+
+/// Build an error for a malformed archive.
+fn invalid(message: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Normalize an entry name into an absolute path, or `None` for unsafe or empty names.
+fn normalize(name: &str) -> Option<String> {
+    let segments: Vec<_> = name
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect();
+    if segments.is_empty() || segments.contains(&"..") {
+        return None;
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// Get a NUL-terminated string field of a tar header.
+fn tar_field(header: &[u8], start: usize, len: usize) -> &[u8] {
+    let field = &header[start..start + len];
+    field
+        .iter()
+        .position(|&b| b == 0)
+        .map_or(field, |end| &field[..end])
+}
+
+/// Parse a numeric field of a tar header, in octal or base-256.
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|b| b & 0x80 != 0) {
+        // Base-256, for sizes of 8 GiB and more
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |value, &b| {
+                value.checked_mul(256)?.checked_add(u64::from(b))
+            });
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Read `len` bytes of a file at `offset`.
+fn read_at(file: &mut fs::File, offset: u64, len: usize) -> IoResult<Vec<u8>> {
+    let mut buffer = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Read the index of a tar archive, supporting ustar, GNU long names and pax paths.
+fn tar_index(file: &mut fs::File) -> IoResult<HashMap<String, (u64, u64)>> {
+    let archive_len = file.metadata()?.len();
+    let mut entries = HashMap::new();
+    let mut position = 0;
+    let mut long_name = None;
+    while position + BLOCK as u64 <= archive_len {
+        let header = read_at(file, position, BLOCK)?;
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size =
+            tar_number(&header[124..136]).ok_or_else(|| invalid("invalid tar entry size"))?;
+        let start = position + BLOCK as u64;
+        if start + size > archive_len {
+            return Err(invalid("truncated tar archive"));
+        }
+        // Metadata entries are small, unlike files
+        let content = |file: &mut fs::File| {
+            let len = usize::try_from(size).map_err(|_| invalid("tar entry too large"))?;
+            read_at(file, start, len)
+        };
+        match header[156] {
+            // GNU long name of the next entry
+            b'L' => {
+                let content = content(file)?;
+                long_name = Some(
+                    String::from_utf8_lossy(tar_field(&content, 0, content.len())).into_owned(),
+                );
+            }
+            // Pax extended header, of which only the path is of interest
+            b'x' => {
+                long_name = String::from_utf8_lossy(&content(file)?)
+                    .lines()
+                    .find_map(|record| {
+                        let (_, pair) = record.split_once(' ')?;
+                        pair.strip_prefix("path=").map(str::to_string)
+                    });
+            }
+            // Regular files
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let name = String::from_utf8_lossy(tar_field(&header, 0, 100));
+                    let prefix = String::from_utf8_lossy(tar_field(&header, 345, 155));
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{prefix}/{name}")
+                    } else {
+                        name.into_owned()
+                    }
+                });
+                if let Some(path) = normalize(&name) {
+                    entries.insert(path, (start, size));
+                }
+            }
+            // Directories, links and others
+            _ => long_name = None,
+        }
+        position = start + size.div_ceil(BLOCK as u64) * BLOCK as u64;
+    }
+    Ok(entries)
+}
+
+/// Read a little-endian integer of a zip record.
+fn le(data: &[u8], start: usize, len: usize) -> IoResult<u64> {
+    let bytes = data
+        .get(start..start + len)
+        .ok_or_else(|| invalid("truncated zip archive"))?;
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &b| value << 8 | u64::from(b)))
+}
+
+/// Read the index of a zip archive from its central directory.
+fn zip_index(file: &mut fs::File, path: &Path) -> IoResult<HashMap<String, (u64, u64)>> {
+    // The end record is followed by a comment of up to 64 KiB
+    let archive_len = file.metadata()?.len();
+    let tail_start = archive_len.saturating_sub(22 + 65_535);
+    #[allow(clippy::cast_possible_truncation, reason = "At most 64 KiB")]
+    let tail = read_at(file, tail_start, (archive_len - tail_start) as usize)?;
+    let end = tail
+        .windows(4)
+        .rposition(|window| window == ZIP_END)
+        .ok_or_else(|| invalid("missing zip end of central directory"))?;
+    let count = le(&tail, end + 10, 2)?;
+    let directory_len = le(&tail, end + 12, 4)?;
+    let directory_start = le(&tail, end + 16, 4)?;
+    if count == 0xffff || directory_start == 0xffff_ffff {
+        return Err(invalid("zip64 archives are not supported"));
+    }
+    #[allow(clippy::cast_possible_truncation, reason = "At most 4 GiB")]
+    let directory = read_at(file, directory_start, directory_len as usize)?;
+
+    let mut entries = HashMap::new();
+    let mut compressed = 0;
+    let mut position = 0;
+    for _ in 0..count {
+        if directory.get(position..position + 4) != Some(&ZIP_CENTRAL) {
+            return Err(invalid("invalid zip central directory"));
+        }
+        let method = le(&directory, position + 10, 2)?;
+        let size = le(&directory, position + 20, 4)?;
+        #[allow(clippy::cast_possible_truncation, reason = "16-bit lengths")]
+        let [name_len, extra_len, comment_len] =
+            [28, 30, 32].map(|offset| le(&directory, position + offset, 2).unwrap_or(0) as usize);
+        let local = le(&directory, position + 42, 4)?;
+        let name = directory
+            .get(position + 46..position + 46 + name_len)
+            .ok_or_else(|| invalid("truncated zip archive"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        position += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if method != 0 {
+            compressed += 1;
+            continue;
+        }
+        let header = read_at(file, local, 30)?;
+        if header[..4] != ZIP_LOCAL {
+            return Err(invalid("invalid zip local file header"));
+        }
+        let start = local + 30 + le(&header, 26, 2)? + le(&header, 28, 2)?;
+        if let Some(path) = normalize(&name) {
+            entries.insert(path, (start, size));
+        }
+    }
+    if compressed > 0 {
+        eprintln!(
+            "Skipping {compressed} compressed entries of {}: only stored entries are supported",
+            path.display()
+        );
+    }
+    Ok(entries)
+}
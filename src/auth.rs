@@ -0,0 +1,226 @@
+//! HTTP Basic authentication.
+
+mod bcrypt;
+mod blowfish;
+
+use super::{Request, Response, response::ResponseCode};
+use compio::runtime::spawn_blocking;
+use std::{cell::RefCell, collections::HashSet, fmt, fs, io::Error as IoError, path::Path};
+
+/// Maximum number of verified credentials remembered, as bcrypt ignores what follows the first
+/// 72 bytes of passwords, so that a valid one has endless variants.
+const MAX_VERIFIED: usize = 1024;
+
+/// Basic authentication configuration.
+#[derive(Debug, Clone, Default)]
+pub struct BasicAuth {
+    /// Known users and their passwords.
+    credentials: Vec<(String, Password)>,
+    /// Decoded `user:password` credentials that have been verified, so that slow hashes are only
+    /// checked once, whatever the encoding of the header.
+    verified: RefCell<HashSet<String>>,
+}
+
+/// A stored password.
+#[derive(Clone, PartialEq, Eq)]
+enum Password {
+    /// Plaintext password.
+    Plain(String),
+    /// Bcrypt hash (`$2a$`, `$2b$` or `$2y$`).
+    Bcrypt(String),
+}
+
+/// Possible errors when parsing credentials.
+#[derive(Debug)]
+pub enum AuthError {
+    /// An entry is not of the form `user:password`.
+    InvalidEntry(String),
+    /// A hash format that is recognized, but not supported.
+    UnsupportedHash(String),
+    /// A bcrypt hash whose cost is too high to check passwords in reasonable time.
+    CostTooHigh(String),
+    /// IO error while reading a credentials file.
+    Io(IoError),
+}
+
+impl BasicAuth {
+    /// Creates an empty configuration, rejecting everyone.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a `user:password` entry, where the password is either plaintext or a bcrypt hash.
+    ///
+    /// # Errors
+    ///
+    /// See [`AuthError`].
+    pub fn add_entry(&mut self, entry: &str) -> Result<(), AuthError> {
+        let Some((user, password)) = entry.split_once(':') else {
+            return Err(AuthError::InvalidEntry(entry.to_string()));
+        };
+        if user.is_empty() {
+            return Err(AuthError::InvalidEntry(entry.to_string()));
+        }
+        let password = if ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .any(|prefix| password.starts_with(prefix))
+        {
+            if bcrypt::cost(password).is_some_and(|cost| cost > bcrypt::MAX_COST) {
+                return Err(AuthError::CostTooHigh(user.to_string()));
+            }
+            Password::Bcrypt(password.to_string())
+        } else if password.starts_with("$argon2")
+            || password.starts_with("$apr1$")
+            || password.starts_with("{SHA}")
+        {
+            return Err(AuthError::UnsupportedHash(user.to_string()));
+        } else {
+            Password::Plain(password.to_string())
+        };
+        self.credentials.push((user.to_string(), password));
+        Ok(())
+    }
+
+    /// Add all entries from a htpasswd-style file, skipping empty lines and `#` comments.
+    ///
+    /// # Errors
+    ///
+    /// See [`AuthError`].
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<(), AuthError> {
+        let content = fs::read_to_string(path).map_err(AuthError::Io)?;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.add_entry(line)?;
+        }
+        Ok(())
+    }
+
+    /// Check the `Authorization` header of the request, returning a 401 response if unauthorized.
+    pub async fn check(&self, request: &Request<'_>) -> Option<Response> {
+        let header = request
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Authorization"));
+        let authorized = match header {
+            Some((_, value)) => self.verify_header(value).await,
+            None => false,
+        };
+        if authorized {
+            None
+        } else {
+            let response = Response::new(ResponseCode::Unauthorized, "401 Unauthorized")
+                .with_header(
+                    "WWW-Authenticate",
+                    "Basic realm=\"nanoserve\", charset=\"UTF-8\"",
+                );
+            Some(response)
+        }
+    }
+
+    /// Verify the value of an `Authorization` header.
+    async fn verify_header(&self, value: &str) -> bool {
+        let Some((scheme, encoded)) = value.split_once(' ') else {
+            return false;
+        };
+        if !scheme.eq_ignore_ascii_case("Basic") {
+            return false;
+        }
+        let Some(decoded) = decode_base64(encoded.trim()) else {
+            return false;
+        };
+        let Ok(decoded) = String::from_utf8(decoded) else {
+            return false;
+        };
+        if self.verified.borrow().contains(&decoded) {
+            return true;
+        }
+        let Some((user, password)) = decoded.split_once(':') else {
+            return false;
+        };
+        let mut valid = false;
+        for (_, stored) in self.credentials.iter().filter(|(u, _)| u == user) {
+            valid = match stored {
+                Password::Plain(plain) => constant_time_eq(plain.as_bytes(), password.as_bytes()),
+                // Hashing takes milliseconds, which would hold up the other connections
+                Password::Bcrypt(hash) => {
+                    let (password, hash) = (password.to_string(), hash.clone());
+                    spawn_blocking(move || bcrypt::verify(&password, &hash))
+                        .await
+                        .unwrap_or(false)
+                }
+            };
+            if valid {
+                break;
+            }
+        }
+        if valid {
+            let mut verified = self.verified.borrow_mut();
+            if verified.len() >= MAX_VERIFIED {
+                verified.clear();
+            }
+            verified.insert(decoded);
+        }
+        valid
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain(_) => write!(f, "Plain(..)"),
+            Self::Bcrypt(_) => write!(f, "Bcrypt(..)"),
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidEntry(entry) => write!(f, "Invalid credentials entry: {entry}"),
+            Self::UnsupportedHash(user) => write!(
+                f,
+                "Unsupported password hash for user {user}: only plaintext and bcrypt are supported"
+            ),
+            Self::CostTooHigh(user) => write!(
+                f,
+                "Bcrypt cost of user {user} is above {}, which makes checking passwords too slow",
+                bcrypt::MAX_COST
+            ),
+            Self::Io(e) => write!(f, "IO error: {e}"),
+        }
+    }
+}
+
+/// Compare two byte strings in time independent of their content.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Decode standard base64, with optional padding.
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.trim_end_matches('=');
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            #[allow(clippy::cast_possible_truncation, reason = "Intended truncation")]
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
@@ -0,0 +1,171 @@
+//! Minimal bcrypt verification, as used by `htpasswd -B`.
+
+use super::blowfish::{P, S};
+
+/// Highest cost accepted for hashes, as each step doubles the time to check a password, which
+/// takes seconds beyond it.
+pub const MAX_COST: u32 = 16;
+/// Alphabet of the bcrypt flavour of base64.
+const ALPHABET: &[u8; 64] = b"./ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Blowfish cipher state.
+struct Blowfish {
+    p: [u32; 18],
+    s: [[u32; 256]; 4],
+}
+
+impl Blowfish {
+    /// The Blowfish round function.
+    const fn f(&self, word: u32) -> u32 {
+        let [a, b, c, d] = word.to_be_bytes();
+        (self.s[0][a as usize].wrapping_add(self.s[1][b as usize]) ^ self.s[2][c as usize])
+            .wrapping_add(self.s[3][d as usize])
+    }
+
+    /// Encrypt one 64-bit block.
+    const fn encipher(&self, mut l: u32, mut r: u32) -> (u32, u32) {
+        let mut i = 0;
+        while i < 16 {
+            l ^= self.p[i];
+            r ^= self.f(l);
+            r ^= self.p[i + 1];
+            l ^= self.f(r);
+            i += 2;
+        }
+        (r ^ self.p[17], l ^ self.p[16])
+    }
+
+    /// The salted key schedule of bcrypt. An empty salt yields the plain Blowfish key schedule.
+    fn expand_key(&mut self, salt: &[u8], key: &[u8]) {
+        let mut key_pos = 0;
+        for p in &mut self.p {
+            *p ^= next_word(key, &mut key_pos);
+        }
+        let mut salt_pos = 0;
+        let mut block = (0, 0);
+        let mut next_block = |state: &Self, (l, r): (u32, u32)| {
+            if salt.is_empty() {
+                state.encipher(l, r)
+            } else {
+                let l = l ^ next_word(salt, &mut salt_pos);
+                let r = r ^ next_word(salt, &mut salt_pos);
+                state.encipher(l, r)
+            }
+        };
+        for i in (0..18).step_by(2) {
+            block = next_block(self, block);
+            (self.p[i], self.p[i + 1]) = block;
+        }
+        for b in 0..4 {
+            for i in (0..256).step_by(2) {
+                block = next_block(self, block);
+                (self.s[b][i], self.s[b][i + 1]) = block;
+            }
+        }
+    }
+}
+
+/// Read the next big-endian word from `data`, cycling around at the end.
+fn next_word(data: &[u8], pos: &mut usize) -> u32 {
+    let mut word = 0;
+    for _ in 0..4 {
+        word = (word << 8) | u32::from(data[*pos]);
+        *pos = (*pos + 1) % data.len();
+    }
+    word
+}
+
+/// Decode bcrypt base64, ignoring trailing bits.
+fn decode(encoded: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(len);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in encoded {
+        let value = ALPHABET.iter().position(|a| a == c)?;
+        #[allow(clippy::cast_possible_truncation, reason = "Position is less than 64")]
+        let value = value as u32;
+        acc = (acc << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            #[allow(clippy::cast_possible_truncation, reason = "Intended truncation")]
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    bytes.truncate(len);
+    (bytes.len() == len).then_some(bytes)
+}
+
+/// Encode bytes into bcrypt base64, without padding.
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        acc = (acc << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 6 {
+            bits -= 6;
+            encoded.push(ALPHABET[((acc >> bits) & 0x3f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((acc << (6 - bits)) & 0x3f) as usize] as char);
+    }
+    encoded
+}
+
+/// Get the cost of a bcrypt hash like `$2y$10$...`, if it has one.
+pub fn cost(hash: &str) -> Option<u32> {
+    hash.split('$').nth(2)?.parse().ok()
+}
+
+/// Verify `password` against a bcrypt hash like `$2y$10$...`.
+///
+/// Malformed hashes never match.
+#[must_use]
+pub fn verify(password: &str, hash: &str) -> bool {
+    let mut parts = hash.split('$');
+    let (Some(""), Some("2a" | "2b" | "2y"), Some(cost), Some(rest), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return false;
+    };
+    let Ok(cost @ 4..=31) = cost.parse::<u32>() else {
+        return false;
+    };
+    if rest.len() != 53 {
+        return false;
+    }
+    let Some(salt) = decode(&rest.as_bytes()[..22], 16) else {
+        return false;
+    };
+
+    // The key includes the trailing NUL, and is truncated to 72 bytes
+    let mut key = password.as_bytes().to_vec();
+    key.push(0);
+    key.truncate(72);
+
+    let mut state = Blowfish { p: P, s: S };
+    state.expand_key(&salt, &key);
+    for _ in 0..1u64 << cost {
+        state.expand_key(&[], &key);
+        state.expand_key(&[], &salt);
+    }
+
+    let mut ctext = [0u32; 6];
+    for (word, chunk) in ctext.iter_mut().zip(b"OrpheanBeholderScryDoubt".chunks(4)) {
+        *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    for _ in 0..64 {
+        for pair in ctext.chunks_mut(2) {
+            (pair[0], pair[1]) = state.encipher(pair[0], pair[1]);
+        }
+    }
+    let output: Vec<u8> = ctext.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let computed = encode(&output[..23]);
+
+    super::constant_time_eq(computed.as_bytes(), &rest.as_bytes()[22..])
+}
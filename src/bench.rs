@@ -0,0 +1,194 @@
+//! Load testing of a running server, to measure performance without external tools.
+//!
+//! Workers make requests back to back on new connections, as the server closes them after each
+//! response, until the duration is over. Requests still in flight by then are not counted.
+
+use compio::{
+    BufResult,
+    io::{AsyncRead, AsyncWriteExt},
+    net::TcpStream,
+    runtime::spawn,
+    time::timeout,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::Result as IoResult,
+    net::{SocketAddr, ToSocketAddrs},
+    panic::resume_unwind,
+    time::{Duration, Instant},
+};
+
+/// Size of the buffer responses are read in.
+const BUF_LEN: usize = 64 * 1024;
+
+/// Results of the requests made by a worker.
+#[derive(Debug, Default)]
+struct Stats {
+    /// Time taken by each completed request.
+    latencies: Vec<Duration>,
+    /// Number of responses by status code.
+    statuses: BTreeMap<u16, usize>,
+    /// Number of requests that failed, without a response.
+    errors: usize,
+    /// Number of bytes received.
+    bytes: u64,
+}
+
+impl Stats {
+    /// Adds the results of another worker.
+    fn merge(&mut self, other: Self) {
+        self.latencies.extend(other.latencies);
+        for (status, count) in other.statuses {
+            *self.statuses.entry(status).or_default() += count;
+        }
+        self.errors += other.errors;
+        self.bytes += other.bytes;
+    }
+
+    /// Gets the latency under which the given percentage of requests completed.
+    fn percentile(&self, percent: usize) -> Duration {
+        let index = (self.latencies.len().saturating_sub(1)) * percent / 100;
+        self.latencies.get(index).copied().unwrap_or_default()
+    }
+
+    /// Formats a report of the results over the given elapsed time.
+    fn report(mut self, elapsed: Duration) -> String {
+        self.latencies.sort_unstable();
+        let secs = elapsed.as_secs_f64();
+        #[allow(clippy::cast_precision_loss, reason = "Approximate rates are fine")]
+        let (rps, throughput) = (
+            self.latencies.len() as f64 / secs,
+            self.bytes as f64 / secs / 1024.0 / 1024.0,
+        );
+        let mut report = format!(
+            "{} requests in {secs:.2}s, {} errors\nRequests/sec: {rps:.2}\nTransfer/sec: {throughput:.2} MiB\n",
+            self.latencies.len(),
+            self.errors,
+        );
+        let _ = writeln!(
+            report,
+            "Latency: p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+            self.percentile(50),
+            self.percentile(90),
+            self.percentile(99),
+            self.latencies.last().copied().unwrap_or_default(),
+        );
+        for (status, count) in &self.statuses {
+            let _ = writeln!(report, "Status {status}: {count}");
+        }
+        report
+    }
+}
+
+/// Requests `target`, a `http://` URL or a path on the server at `addr`, from `concurrency`
+/// workers for the given duration, returning a report of the results.
+pub async fn run(
+    target: &str,
+    addr: SocketAddr,
+    concurrency: usize,
+    duration: Duration,
+) -> Result<String, String> {
+    let (addr, request) = parse_target(target, addr)?;
+    let start = Instant::now();
+    let deadline = start + duration;
+    let workers: Vec<_> = (0..concurrency.max(1))
+        .map(|_| spawn(worker(addr, request.clone(), deadline)))
+        .collect();
+    let mut stats = Stats::default();
+    for worker in workers {
+        stats.merge(worker.await.unwrap_or_else(|panic| resume_unwind(panic)));
+    }
+    Ok(stats.report(start.elapsed()))
+}
+
+/// Get the address to connect to and the request to send for a target.
+fn parse_target(target: &str, addr: SocketAddr) -> Result<(SocketAddr, Vec<u8>), String> {
+    let (authority, path) = if target.starts_with('/') {
+        (addr.to_string(), target)
+    } else {
+        let rest = target.strip_prefix("http://").ok_or_else(|| {
+            format!("unsupported target {target}, expected a http:// URL or a path")
+        })?;
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, "/"), |index| (&rest[..index], &rest[index..]));
+        (authority.to_string(), path)
+    };
+    let has_port = authority
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+    let resolve = if has_port {
+        authority.clone()
+    } else {
+        format!("{authority}:80")
+    };
+    let addr = resolve
+        .to_socket_addrs()
+        .map_err(|e| format!("failed to resolve {authority}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("failed to resolve {authority}"))?;
+    let request = format!("GET {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\n\r\n");
+    Ok((addr, request.into_bytes()))
+}
+
+/// Makes requests until the deadline.
+async fn worker(addr: SocketAddr, request: Vec<u8>, deadline: Instant) -> Stats {
+    let mut stats = Stats::default();
+    let mut buffer = Vec::with_capacity(BUF_LEN);
+    loop {
+        let start = Instant::now();
+        let Some(remaining) = deadline.checked_duration_since(start) else {
+            break;
+        };
+        let Ok((result, returned)) = timeout(remaining, fetch(addr, request.clone(), buffer)).await
+        else {
+            break;
+        };
+        buffer = returned;
+        match result {
+            Ok((code, bytes)) => {
+                stats.latencies.push(start.elapsed());
+                *stats.statuses.entry(code).or_default() += 1;
+                stats.bytes += bytes;
+            }
+            Err(_) => stats.errors += 1,
+        }
+    }
+    stats
+}
+
+/// Makes a request on a new connection, returning the status and size of the response along
+/// with the buffer it was read in.
+async fn fetch(
+    addr: SocketAddr,
+    request: Vec<u8>,
+    mut buffer: Vec<u8>,
+) -> (IoResult<(u16, u64)>, Vec<u8>) {
+    let mut stream = match TcpStream::connect(addr).await {
+        Ok(stream) => stream,
+        Err(e) => return (Err(e), buffer),
+    };
+    if let Err(e) = stream.write_all(request).await.0 {
+        return (Err(e), buffer);
+    }
+    let mut status = None;
+    let mut bytes = 0;
+    loop {
+        buffer.clear();
+        let BufResult(result, returned) = stream.read(buffer).await;
+        buffer = returned;
+        match result {
+            Ok(0) => break,
+            Ok(read) => {
+                // The status code is at the start of the first chunk, like `HTTP/1.1 200 OK`
+                status =
+                    status.or_else(|| std::str::from_utf8(buffer.get(9..12)?).ok()?.parse().ok());
+                bytes += read as u64;
+            }
+            Err(e) => return (Err(e), buffer),
+        }
+    }
+    let result = status.ok_or_else(|| std::io::Error::other("invalid HTTP response"));
+    (result.map(|status| (status, bytes)), buffer)
+}
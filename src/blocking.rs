@@ -0,0 +1,219 @@
+//! A minimal blocking file server, using std networking and a thread per connection, for build
+//! scripts and test fixtures where an async runtime is overkill.
+//!
+//! Files are served for `GET` and `HEAD` requests, with ranges and entity tags, and each
+//! connection is closed after its response, like with [`HTTPServer`](super::HTTPServer). Its
+//! configuration, handlers and other features are not available.
+
+use super::{
+    MAX_BODY, ParseRequestError, READ_TIMEOUT, Request, Response, ResponseBody, ResponseCode,
+    decode_path,
+    http1::{ParseOptions, Parser, Status},
+    logger::{self, Summary},
+    mime, normalize_path,
+    response::{byte_range, content_range},
+    storage::Metadata,
+};
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Instant,
+};
+
+/// Bytes read at a time while reading a request.
+const READ_SIZE: usize = 16 * 1024;
+
+/// Serves the files under `root` on `addr`, blocking the current thread.
+///
+/// # Errors
+///
+/// Returns an [`IoError`](std::io::Error) if binding to `addr` fails.
+pub fn serve(addr: impl ToSocketAddrs, root: impl Into<PathBuf>) -> IoResult<()> {
+    serve_on(&TcpListener::bind(addr)?, root);
+    Ok(())
+}
+
+/// Serves the files under `root` on a bound listener, blocking the current thread.
+///
+/// This is useful to bind to port 0 first and learn the assigned port, like in test fixtures.
+pub fn serve_on(listener: &TcpListener, root: impl Into<PathBuf>) {
+    let root = Arc::new(root.into());
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let root = Arc::clone(&root);
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().ok();
+                    if let Err(e) = handle_connection(stream, &root) {
+                        let peer = peer.map_or_else(|| "-".to_string(), |peer| peer.to_string());
+                        eprintln!("Error while handling connection from {peer}: {e}");
+                    }
+                });
+            }
+            Err(e) => eprintln!("Error while accepting connection: {e}"),
+        }
+    }
+}
+
+/// Reads a request from a connection and answers it.
+fn handle_connection(mut stream: TcpStream, root: &Path) -> IoResult<()> {
+    let start = Instant::now();
+    let peer = stream.peer_addr()?;
+    let mut buffer = Vec::new();
+    let mut parser = Parser::new(ParseOptions::default(), MAX_BODY);
+    let read = read(&mut stream, &mut buffer, &mut parser)?;
+    let parsed = read.and_then(|()| parser.request(&buffer));
+    let normalized = parsed
+        .as_ref()
+        .ok()
+        .map(|request| normalize_path(request.path, false));
+    let request = parsed.map(|request| Request {
+        path: normalized.as_deref().unwrap_or(request.path),
+        peer: Some(peer),
+        ..request
+    });
+    let (mut response, file) = match &request {
+        Ok(request) => respond(request, root),
+        Err(e) => (
+            Response::new(e.status(), e.description()).with_header("Connection", "close"),
+            None,
+        ),
+    };
+    // Responses to `HEAD` have the head of the response to `GET`, errors included
+    if let Ok(request) = &request
+        && request.method == "HEAD"
+        && let Some(size) = response.body.size()
+    {
+        response.body = ResponseBody::Omitted { size };
+    }
+    let (code, size) = (response.code, response.body.size());
+    response.write_sync(&mut stream)?;
+    if let Some((mut file, len)) = file {
+        io::copy(&mut (&mut file).take(len), &mut stream)?;
+    }
+    stream.flush()?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let (method, path) = request
+        .as_ref()
+        .map_or(("-", "-"), |request| (request.method, request.path));
+    logger::log_request(&Summary {
+        method,
+        path,
+        headers: request.as_ref().map_or(&[], |request| &request.headers),
+        status: code,
+        size,
+        sent: None,
+        elapsed: start.elapsed(),
+        client: Some(peer.ip()),
+    });
+    Ok(())
+}
+
+/// Reads a request into `buffer` with `parser`, until it is complete or the client stops
+/// sending for [`READ_TIMEOUT`].
+fn read(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    parser: &mut Parser,
+) -> IoResult<Result<(), ParseRequestError>> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut chunk = vec![0; READ_SIZE];
+    loop {
+        match parser.parse(buffer) {
+            Ok(Status::Complete(_)) => return Ok(Ok(())),
+            Ok(Status::Partial) => {}
+            Err(e) => return Ok(Err(e)),
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return Ok(parser.finish(buffer).map(|_| ())),
+            Ok(len) => buffer.extend_from_slice(&chunk[..len]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(Err(ParseRequestError::RequestTimeout));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Produces the response to a well-formed request, whose body is omitted when it is a file,
+/// returned along with the length to copy from it.
+fn respond(request: &Request<'_>, root: &Path) -> (Response, Option<(File, u64)>) {
+    if request.version != "1.1" {
+        return (
+            Response::new(ResponseCode::BadRequest, "Unsupported HTTP Version"),
+            None,
+        );
+    }
+    if !matches!(request.method, "GET" | "HEAD") {
+        let response = Response::new(ResponseCode::MethodNotAllowed, "405 Method Not Allowed")
+            .with_header("Allow", "GET, HEAD");
+        return (response, None);
+    }
+    let path = request
+        .path
+        .split_once('?')
+        .map_or(request.path, |(path, _)| path);
+    let Some(relative) = decode_path(path) else {
+        return (Response::bad_request("Invalid request path"), None);
+    };
+    let path = root.join(&*relative);
+    let metadata = match path.metadata() {
+        Ok(metadata) if metadata.is_file() => Metadata {
+            size: metadata.len(),
+            is_dir: false,
+            modified: metadata.modified().ok(),
+        },
+        _ => return (Response::not_found(), None),
+    };
+    let size = metadata.size;
+    let etag = metadata.etag();
+    if let Some(etag) = etag.as_ref().filter(|etag| request.matches_etag(etag)) {
+        let response = Response {
+            code: ResponseCode::NotModified,
+            headers: vec![("ETag".into(), etag.clone())],
+            body: ResponseBody::Omitted { size },
+            chunk_size: None,
+        };
+        return (response, None);
+    }
+    let range = match byte_range(request, size) {
+        Ok(range) => range,
+        Err(response) => return (response, None),
+    };
+    let (code, (start, end)) = range.map_or((ResponseCode::Ok, (0, size)), |range| {
+        (ResponseCode::PartialContent, range)
+    });
+    let file = if request.method == "HEAD" {
+        None
+    } else {
+        let opened = File::open(&path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(start))?;
+            Ok(file)
+        });
+        match opened {
+            Ok(file) => Some((file, end - start)),
+            Err(e) if e.kind() == ErrorKind::NotFound => return (Response::not_found(), None),
+            Err(e) => {
+                eprintln!("Error while opening {}: {e}", path.display());
+                return (Response::internal_server_error(), None);
+            }
+        }
+    };
+    let mut headers: Vec<_> = etag.map(|etag| ("ETag".into(), etag)).into_iter().collect();
+    headers.push(("Content-Type".into(), mime::guess(&relative).into()));
+    if range.is_some() {
+        headers.push(content_range(start, end, size));
+    }
+    let response = Response {
+        code,
+        headers,
+        body: ResponseBody::Omitted { size: end - start },
+        chunk_size: None,
+    };
+    (response, file)
+}
@@ -0,0 +1,73 @@
+//! Reading of requests, which the first read of a connection may only hold the start of.
+//!
+//! Bytes are appended to the connection buffer until the [`Parser`] finds the request complete,
+//! and chunked bodies are decoded in place right after the head, so that
+//! [`Request::body`](super::Request::body) is always a slice of the connection buffer. The whole
+//! request must arrive before a deadline, so that slow clients cannot hold connections open
+//! indefinitely.
+
+use super::{
+    ParseRequestError,
+    http1::{HttpParser, Parser, Status},
+};
+use compio::{BufResult, io::AsyncReadExt, net::TcpStream, time::timeout};
+use std::{
+    io::Result as IoResult,
+    time::{Duration, Instant},
+};
+
+/// Default maximum size of a request body, as sent.
+pub const MAX_BODY: usize = 1 << 20;
+/// Default time allowed to receive a whole request.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Bytes read at a time while reading a head or body.
+const READ_SIZE: usize = 16 * 1024;
+
+/// Reads the rest of a request into `buffer` with `parser`, if its first read did not hold it
+/// all, returning its length.
+///
+/// A request cut short by the client closing the connection is parsed as is, which only
+/// succeeds if its body was unframed, while a request not received before `deadline` is an
+/// error.
+///
+/// # Errors
+///
+/// Returns an [`IoError`](std::io::Error) if reading fails.
+pub async fn read<P: HttpParser>(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    parser: &mut Parser<P>,
+    deadline: Instant,
+) -> IoResult<Result<usize, ParseRequestError>> {
+    loop {
+        match parser.parse(buffer) {
+            Ok(Status::Complete(len)) => return Ok(Ok(len)),
+            Ok(Status::Partial) => {}
+            Err(e) => return Ok(Err(e)),
+        }
+        match read_more(stream, buffer, deadline).await? {
+            Ok(true) => {}
+            Ok(false) => return Ok(parser.finish(buffer)),
+            Err(e) => return Ok(Err(e)),
+        }
+    }
+}
+
+/// Reads more bytes into `buffer`, returning `false` if the client closed the connection, or
+/// [`ParseRequestError::RequestTimeout`] if nothing arrived before `deadline`.
+async fn read_more(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    deadline: Instant,
+) -> IoResult<Result<bool, ParseRequestError>> {
+    buffer.reserve(READ_SIZE);
+    let read = stream.append(std::mem::take(buffer));
+    // The buffer goes with the cancelled read, but the request is failed anyway
+    let Ok(BufResult(result, read)) =
+        timeout(deadline.saturating_duration_since(Instant::now()), read).await
+    else {
+        return Ok(Err(ParseRequestError::RequestTimeout));
+    };
+    *buffer = read;
+    Ok(Ok(result? > 0))
+}
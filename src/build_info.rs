@@ -0,0 +1,70 @@
+//! Information about how nanoserve was built.
+
+use std::fmt;
+
+/// Build information: version, enabled features, target and git commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// Crate version.
+    pub version: &'static str,
+    /// Enabled cargo features.
+    pub features: &'static [&'static str],
+    /// Target triple.
+    pub target: &'static str,
+    /// Short git commit hash, if built from a git checkout.
+    pub git_commit: Option<&'static str>,
+}
+
+/// Enabled cargo features.
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "archive")]
+    "archive",
+    #[cfg(feature = "cli")]
+    "cli",
+    #[cfg(feature = "fastcgi")]
+    "fastcgi",
+    #[cfg(feature = "h2")]
+    "h2",
+    #[cfg(feature = "proxy")]
+    "proxy",
+    #[cfg(feature = "sendfile")]
+    "sendfile",
+    #[cfg(feature = "serde")]
+    "serde",
+    #[cfg(feature = "tracing")]
+    "tracing",
+    #[cfg(feature = "webhook")]
+    "webhook",
+];
+
+/// Get information about how nanoserve was built.
+#[must_use]
+pub const fn build_info() -> BuildInfo {
+    let git_commit = env!("NANOSERVE_GIT_COMMIT");
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES,
+        target: env!("NANOSERVE_TARGET"),
+        git_commit: if git_commit.is_empty() {
+            None
+        } else {
+            Some(git_commit)
+        },
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.version)?;
+        if let Some(commit) = self.git_commit {
+            write!(f, " ({commit})")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "target: {}", self.target)?;
+        if self.features.is_empty() {
+            write!(f, "features: (none)")
+        } else {
+            write!(f, "features: {}", self.features.join(", "))
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Caching forward proxy.
+
+use super::{
+    Handler, HandlerFuture, Proxy, Request, Response, ResponseBody, ResponseCode,
+    proxy::{CHUNK_SIZE, read_body, read_head},
+    response::{byte_range, content_range, unfold},
+};
+use compio::{
+    BufResult,
+    fs::{self, File},
+    io::{AsyncRead, AsyncWriteAtExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    fmt::Write,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Result as IoResult,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Headers of an upstream response.
+type Headers = Vec<(Cow<'static, str>, String)>;
+
+/// A [`Handler`] proxying `GET` requests to an upstream origin and caching the responses on
+/// disk, as a pull-through cache for package mirrors or tile servers.
+///
+/// Successful responses are stored unless `Cache-Control` forbids it, and stay fresh for their
+/// `s-maxage` or `max-age`, or the [default lifetime](Self::default_ttl) otherwise. Stale
+/// entries are revalidated with `If-None-Match` and `If-Modified-Since` when they have an
+/// `ETag` or `Last-Modified` header, and served as is if the upstream server is unreachable.
+/// Cached responses support ranges and carry `X-Cache: HIT`, while responses being fetched are
+/// streamed to the client as they are written to the cache. Other methods and requests with
+/// `Authorization` are forwarded without caching.
+#[derive(Debug, Clone)]
+pub struct CachingProxy {
+    /// The proxy used to forward requests that are not cached.
+    proxy: Proxy,
+    /// Directory of cached responses.
+    dir: PathBuf,
+    /// Lifetime of responses without explicit freshness.
+    default_ttl: Duration,
+    /// Counter for unique names of the files being written.
+    temp: Cell<u64>,
+}
+
+/// A cached response.
+#[derive(Debug)]
+struct Entry {
+    /// When the response was stored or last revalidated, in seconds since the Unix epoch.
+    stored: u64,
+    /// Headers of the response.
+    headers: Headers,
+}
+
+/// State of a response being streamed to the client and written to the cache.
+struct Fill {
+    /// Connection to the upstream server.
+    stream: TcpStream,
+    /// Bytes of the body read along with the head.
+    first: Vec<u8>,
+    /// The file being written, or `None` once committed or if writing failed.
+    file: Option<File>,
+    /// Number of bytes received.
+    written: u64,
+    /// `Content-Length` announced by the upstream server, if any.
+    expected: Option<u64>,
+    /// Path of the file being written.
+    temp: PathBuf,
+    /// Path of the cached body.
+    body: PathBuf,
+    /// The entry to store once the body is complete.
+    entry: Entry,
+    /// Path of the entry.
+    entry_path: PathBuf,
+}
+
+impl CachingProxy {
+    /// Creates a caching proxy to the given upstream, like `localhost:3000` or
+    /// `http://tile.example.org/`, storing responses in `dir`.
+    #[must_use]
+    pub fn new(upstream: &str, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            proxy: Proxy::new(upstream),
+            dir: dir.into(),
+            default_ttl: Duration::ZERO,
+            temp: Cell::new(0),
+        }
+    }
+
+    /// Sets how long responses without `max-age` stay fresh, instead of being revalidated on
+    /// every request.
+    #[must_use]
+    pub const fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Get the paths of the body and entry files for a request path.
+    fn paths(&self, path: &str) -> (PathBuf, PathBuf) {
+        let mut hasher = DefaultHasher::new();
+        (self.proxy.upstream(), path).hash(&mut hasher);
+        let key = format!("{:016x}", hasher.finish());
+        (self.dir.join(&key), self.dir.join(format!("{key}.entry")))
+    }
+
+    /// Answer a `GET` request from the cache, revalidating or fetching it as needed.
+    async fn respond(&self, request: &Request<'_>) -> IoResult<Response> {
+        let (body, entry_path) = self.paths(request.path);
+        let cached = match Entry::load(&entry_path).await {
+            Some(entry) => File::open(&body).await.ok().map(|file| (entry, file)),
+            None => None,
+        };
+        let Some((mut entry, file)) = cached else {
+            return self.fetch(request.path, &[]).await;
+        };
+        let age = now().saturating_sub(entry.stored);
+        if age < entry.max_age(self.default_ttl) {
+            return entry.serve(file, request, "HIT").await;
+        }
+        let validators: Vec<_> = [
+            ("ETag", "If-None-Match"),
+            ("Last-Modified", "If-Modified-Since"),
+        ]
+        .into_iter()
+        .filter_map(|(name, condition)| Some((condition, entry.header(name)?.to_string())))
+        .collect();
+        if validators.is_empty() {
+            return self.fetch(request.path, &[]).await;
+        }
+        let response = match self.fetch(request.path, &validators).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!(
+                    "Error while revalidating {} with {}: {e}",
+                    request.path,
+                    self.proxy.upstream()
+                );
+                return entry.serve(file, request, "STALE").await;
+            }
+        };
+        if response.code != ResponseCode::NotModified {
+            return Ok(response);
+        }
+        // Still valid: refresh the entry with the headers of the `304 Not Modified` response
+        for (name, value) in response.headers {
+            entry
+                .headers
+                .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+            entry.headers.push((name, value));
+        }
+        entry.stored = now();
+        if let Err(e) = entry.save(&entry_path).await {
+            eprintln!("Error while updating {}: {e}", entry_path.display());
+        }
+        entry.serve(file, request, "REVALIDATED").await
+    }
+
+    /// Fetch a path from the upstream server with the given extra headers, writing successful
+    /// responses to the cache as they are streamed.
+    async fn fetch(&self, path: &str, extra: &[(&str, String)]) -> IoResult<Response> {
+        let upstream = self.proxy.upstream();
+        let mut stream = TcpStream::connect(upstream).await?;
+        let mut head = format!("GET {path} HTTP/1.0\r\nHost: {upstream}\r\n");
+        for (name, value) in extra {
+            let _ = write!(head, "{name}: {value}\r\n");
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        stream.write_all(head).await.0?;
+        let (code, headers, first) = read_head(&mut stream).await?;
+
+        let entry = Entry {
+            stored: now(),
+            headers,
+        };
+        let temp = if code == 200 && entry.storable() {
+            self.create_temp().await
+        } else {
+            None
+        };
+        let Some((temp, file)) = temp else {
+            return Ok(Response {
+                code: code.into(),
+                headers: entry.headers,
+                body: ResponseBody::Stream(unfold((stream, first), read_body)),
+                chunk_size: None,
+            });
+        };
+        let mut headers = entry.headers.clone();
+        headers.push(("X-Cache".into(), "MISS".to_string()));
+        let (body, entry_path) = self.paths(path);
+        Ok(Response {
+            code: ResponseCode::Ok,
+            headers,
+            body: ResponseBody::Stream(unfold(
+                Fill {
+                    stream,
+                    first,
+                    file: Some(file),
+                    written: 0,
+                    expected: entry
+                        .header("Content-Length")
+                        .and_then(|len| len.parse().ok()),
+                    temp,
+                    body,
+                    entry,
+                    entry_path,
+                },
+                Fill::next,
+            )),
+            chunk_size: None,
+        })
+    }
+
+    /// Create a file to write a response to, or `None` if the cache directory is not writable.
+    async fn create_temp(&self) -> Option<(PathBuf, File)> {
+        let count = self.temp.get();
+        self.temp.set(count + 1);
+        let temp = self
+            .dir
+            .join(format!(".{}-{count}.tmp", std::process::id()));
+        let result = match fs::create_dir_all(&self.dir).await {
+            Ok(()) => File::create(&temp).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(file) => Some((temp, file)),
+            Err(e) => {
+                eprintln!("Not caching in {}: {e}", self.dir.display());
+                None
+            }
+        }
+    }
+}
+
+impl Handler for CachingProxy {
+    fn handle<'a>(&'a self, request: &'a Request<'_>) -> HandlerFuture<'a> {
+        let bypass = request.method != "GET"
+            || request
+                .headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("Authorization"));
+        if bypass {
+            return self.proxy.handle(request);
+        }
+        Box::pin(async move {
+            Some(self.respond(request).await.unwrap_or_else(|e| {
+                eprintln!("Error while proxying to {}: {e}", self.proxy.upstream());
+                Response::new(ResponseCode::BadGateway, "502 Bad Gateway")
+            }))
+        })
+    }
+}
+
+impl Entry {
+    /// Load an entry, or `None` if it is missing or malformed.
+    async fn load(path: &Path) -> Option<Self> {
+        let text = String::from_utf8(fs::read(path).await.ok()?).ok()?;
+        let mut lines = text.lines();
+        let stored = lines.next()?.strip_prefix("stored ")?.parse().ok()?;
+        let headers = lines
+            .filter_map(|line| line.split_once(": "))
+            .map(|(name, value)| (Cow::Owned(name.to_string()), value.to_string()))
+            .collect();
+        Some(Self { stored, headers })
+    }
+
+    /// Save the entry, replacing the previous one atomically.
+    async fn save(&self, path: &Path) -> IoResult<()> {
+        let mut text = format!("stored {}\n", self.stored);
+        for (name, value) in &self.headers {
+            let _ = writeln!(text, "{name}: {value}");
+        }
+        let temp = path.with_extension("entry.tmp");
+        fs::write(&temp, text).await.0?;
+        fs::rename(&temp, path).await
+    }
+
+    /// Get the value of a header.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the directives of the `Cache-Control` header, in lowercase.
+    fn cache_control(&self) -> impl Iterator<Item = String> {
+        self.header("Cache-Control")
+            .unwrap_or_default()
+            .split(',')
+            .map(|directive| directive.trim().to_ascii_lowercase())
+    }
+
+    /// Whether the response may be stored by a shared cache.
+    fn storable(&self) -> bool {
+        let forbidden = self
+            .cache_control()
+            .any(|directive| directive == "no-store" || directive == "private");
+        !forbidden && self.header("Vary") != Some("*") && self.header("Set-Cookie").is_none()
+    }
+
+    /// Get how long the response stays fresh, in seconds.
+    fn max_age(&self, default: Duration) -> u64 {
+        let mut max_age = None;
+        for directive in self.cache_control() {
+            if directive == "no-cache" {
+                return 0;
+            }
+            if let Some(seconds) = directive.strip_prefix("s-maxage=") {
+                // Takes precedence over `max-age` for shared caches
+                return seconds.parse().unwrap_or(0);
+            }
+            if let Some(seconds) = directive.strip_prefix("max-age=") {
+                max_age = seconds.parse().ok();
+            }
+        }
+        max_age.unwrap_or(default.as_secs())
+    }
+
+    /// Serve the cached body, marked with the given `X-Cache` value.
+    async fn serve(&self, file: File, request: &Request<'_>, status: &str) -> IoResult<Response> {
+        let size = file.metadata().await?.len();
+        let matches = self
+            .header("ETag")
+            .is_some_and(|etag| request.matches_etag(etag));
+        let (code, body, range) = if matches {
+            (
+                ResponseCode::NotModified,
+                ResponseBody::Omitted { size },
+                None,
+            )
+        } else {
+            match byte_range(request, size) {
+                Ok(None) => (ResponseCode::Ok, ResponseBody::File { file, size }, None),
+                Ok(Some((start, end))) => (
+                    ResponseCode::PartialContent,
+                    ResponseBody::PartialFile { file, start, end },
+                    Some(content_range(start, end, size)),
+                ),
+                Err(response) => return Ok(response),
+            }
+        };
+        // The length of partial responses differs from the stored one
+        let mut headers: Headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("Content-Length"))
+            .cloned()
+            .collect();
+        headers.extend(range);
+        headers.push(("X-Cache".into(), status.to_string()));
+        Ok(Response {
+            code,
+            headers,
+            body,
+            chunk_size: None,
+        })
+    }
+}
+
+impl Fill {
+    /// Read the next chunk of the body and write it to the cache, committing the entry at the end.
+    async fn next(mut self) -> Option<(Vec<u8>, Self)> {
+        let chunk = if self.first.is_empty() {
+            let BufResult(result, chunk) = self.stream.read(Vec::with_capacity(CHUNK_SIZE)).await;
+            match result {
+                Ok(0) => {
+                    self.commit().await;
+                    return None;
+                }
+                Ok(_) => chunk,
+                Err(_) => return None,
+            }
+        } else {
+            std::mem::take(&mut self.first)
+        };
+        if let Some(file) = &mut self.file {
+            let result = file.write_all_at(chunk.clone(), self.written).await;
+            if let Err(e) = result.0 {
+                eprintln!("Error while caching {}: {e}", self.body.display());
+                self.discard();
+            }
+        }
+        self.written += chunk.len() as u64;
+        Some((chunk, self))
+    }
+
+    /// Move the complete body in place and store its entry.
+    async fn commit(&mut self) {
+        let Some(file) = self.file.take() else {
+            return;
+        };
+        drop(file);
+        if self
+            .expected
+            .is_some_and(|expected| expected != self.written)
+        {
+            let _ = fs::remove_file(&self.temp).await;
+            return;
+        }
+        let result = match fs::rename(&self.temp, &self.body).await {
+            Ok(()) => self.entry.save(&self.entry_path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("Error while caching {}: {e}", self.body.display());
+            let _ = fs::remove_file(&self.temp).await;
+        }
+    }
+
+    /// Give up on caching the body.
+    fn discard(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+impl Drop for Fill {
+    fn drop(&mut self) {
+        // The client went away or the upstream server failed before the end of the body
+        self.discard();
+    }
+}
+
+/// Get the current time, in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -1,9 +1,15 @@
-use argh::FromArgs;
-use std::net::IpAddr;
+use super::completions::Shell;
+use argh::{ArgsInfo, FromArgs};
+use nanoserve::{
+    AuthError, BasicAuth, Cors, Driver, FastCgi, IpRange, Proxy, RewriteRule, VirtualHost, Webhook,
+    WebhookEvent,
+};
+use std::{net::IpAddr, path::PathBuf};
 
 /// Ground-up implementation of a nano HTTP server from TCP sockets.
-#[derive(FromArgs, Debug)]
+#[derive(FromArgs, ArgsInfo, Debug)]
 #[argh(help_triggers("-h", "--help", "help"))]
+#[allow(clippy::struct_excessive_bools, reason = "Command line switches")]
 pub struct Cli {
     /// IP address to bind the server to
     #[argh(option, default = "IpAddr::from([127, 0, 0, 1])", short = 'a')]
@@ -11,4 +17,371 @@ pub struct Cli {
     /// port to bind the server to
     #[argh(option, default = "8080", short = 'p')]
     pub port: u16,
+    /// send small writes right away by disabling Nagle's algorithm on connections
+    #[argh(switch)]
+    pub nodelay: bool,
+    /// number of tasks accepting connections concurrently, which are pre-posted accepts on Windows (default: 1)
+    #[argh(option, default = "1")]
+    pub acceptors: usize,
+    /// driver performing I/O: auto, io-uring or polling, failing if not available (default: auto, the driver of the platform)
+    #[argh(option, default = "Driver::Auto", from_str_fn(parse_driver))]
+    pub driver: Driver,
+    /// milliseconds the io-uring kernel thread polling for submissions stays awake while idle (default: no such thread)
+    #[argh(option)]
+    pub sqpoll: Option<u64>,
+    /// number of entries of the I/O submission queue (default: 1024)
+    #[argh(option)]
+    pub queue_depth: Option<u32>,
+    /// maximum number of connections waiting to be accepted (default: 128)
+    #[argh(option, default = "128")]
+    pub backlog: i32,
+    /// maximum number of connections handled at once, answering requests beyond it with 503 (default: no limit)
+    #[argh(option)]
+    pub max_connections: Option<usize>,
+    /// seconds clients are asked to wait before retrying when --max-connections is reached (default: 1)
+    #[argh(option, default = "1")]
+    pub retry_after: u64,
+    /// size of the send buffer of connections, in bytes (default: system default)
+    #[argh(option)]
+    pub send_buffer: Option<usize>,
+    /// size of the receive buffer of connections, in bytes (default: system default)
+    #[argh(option)]
+    pub recv_buffer: Option<usize>,
+    /// size of the first chunk files are sent in, in bytes (default: 8192)
+    #[argh(option, default = "8192")]
+    pub chunk_size: usize,
+    /// size chunks grow up to for large files, in bytes (default: 262144)
+    #[argh(option, default = "262144")]
+    pub max_chunk_size: usize,
+    /// maximum rate responses are sent at on each connection, like 5MBps or 512KiB/s
+    #[argh(option, from_str_fn(parse_rate))]
+    pub throttle: Option<u64>,
+    /// maximum rate responses are sent at on all connections together, like 10MBps
+    #[argh(option, from_str_fn(parse_rate))]
+    pub global_throttle: Option<u64>,
+    /// maximum memory held by caches and buffer pools together, like 64MiB (default: no limit)
+    #[argh(option, from_str_fn(parse_size))]
+    pub memory_budget: Option<u64>,
+    /// maximum number of headers of a request (default: 100)
+    #[argh(option, default = "100")]
+    pub max_headers: usize,
+    /// maximum size of a request body, like 8MiB (default: 1MiB)
+    #[argh(option, from_str_fn(parse_size))]
+    pub max_body: Option<u64>,
+    /// seconds allowed to receive a whole request before answering 408 (default: 30)
+    #[argh(option, default = "30")]
+    pub read_timeout: u64,
+    /// accept header values continued on lines starting with whitespace instead of rejecting them
+    #[argh(switch)]
+    pub unfold_headers: bool,
+    /// reject requests with lines ending with a bare CR or LF instead of CRLF
+    #[argh(switch)]
+    pub strict_crlf: bool,
+    /// lowercase request paths, for case-insensitive filesystems
+    #[argh(switch)]
+    pub fold_case: bool,
+    /// record the durations of the phases of requests and print them on shutdown
+    #[argh(switch)]
+    pub timings: bool,
+    /// print version and build information
+    #[argh(switch, short = 'V')]
+    pub version: bool,
+    /// enable permissive CORS (allow any origin)
+    #[argh(switch)]
+    pub cors: bool,
+    /// allowed CORS origin (implies --cors)
+    #[argh(option)]
+    pub cors_origin: Option<String>,
+    /// allowed CORS methods (implies --cors)
+    #[argh(option)]
+    pub cors_methods: Option<String>,
+    /// require Basic authentication with the given user:password (repeatable; password may be a bcrypt hash)
+    #[argh(option)]
+    pub auth: Vec<String>,
+    /// require Basic authentication with users from a htpasswd-style file
+    #[argh(option)]
+    pub auth_file: Option<String>,
+    /// forward requests under a path prefix to an upstream server, as /prefix=host:port (repeatable)
+    #[argh(option, from_str_fn(parse_proxy))]
+    pub proxy: Vec<(String, Proxy)>,
+    /// maximum number of idle connections kept to each --proxy upstream server, 0 to close them after each response (default: 8)
+    #[argh(option, default = "8")]
+    pub proxy_pool: usize,
+    /// seconds that idle connections to --proxy upstream servers are kept (default: 4)
+    #[argh(option, default = "4")]
+    pub proxy_idle_timeout: u64,
+    /// forward requests under a path prefix to an upstream origin and cache responses on disk, as /prefix=host:port (repeatable)
+    #[argh(option, from_str_fn(parse_cache_proxy))]
+    pub cache_proxy: Vec<(String, String)>,
+    /// directory of responses cached by --cache-proxy (default: nanoserve-cache in the temporary directory)
+    #[argh(option)]
+    pub cache_dir: Option<PathBuf>,
+    /// seconds that responses cached by --cache-proxy without max-age stay fresh before being revalidated (default: 0)
+    #[argh(option, default = "0")]
+    pub cache_ttl: u64,
+    /// forward requests for matching scripts to a fastcgi server like php-fpm, as pattern=host:port or pattern=unix:/path (repeatable)
+    #[argh(option, from_str_fn(parse_fastcgi))]
+    pub fastcgi: Vec<FastCgi>,
+    /// serve files from a .tar or .zip archive instead of the current directory
+    #[argh(option)]
+    pub archive: Option<String>,
+    /// milliseconds to cache the metadata of served files for, instead of reading it for every request (default: 0, disabled)
+    #[argh(option, default = "0")]
+    pub metadata_cache: u64,
+    /// number of hot files to keep open instead of reopening them for every request (default: 0, disabled)
+    #[argh(option, default = "0")]
+    pub open_files: usize,
+    /// serve a host name from its own directory, as host=dir (repeatable)
+    #[argh(option, from_str_fn(parse_vhost))]
+    pub vhost: Vec<(String, VirtualHost)>,
+    /// serve another path for requests matching a glob, as "pattern target [status]", e.g. "/* /$1.html" (repeatable)
+    #[argh(option, from_str_fn(parse_rewrite))]
+    pub rewrite: Vec<RewriteRule>,
+    /// redirect requests matching a glob, as "pattern target [status]", e.g. "/old/* /new/$1 302" (repeatable)
+    #[argh(option, from_str_fn(parse_redirect))]
+    pub redirect: Vec<RewriteRule>,
+    /// URL prefix the server is mounted under behind a reverse proxy, like /myapp
+    #[argh(option)]
+    pub base_path: Option<String>,
+    /// trust a reverse proxy address or range like 10.0.0.0/8 to report client addresses (repeatable)
+    #[argh(option)]
+    pub trusted_proxy: Vec<IpRange>,
+    /// post a json payload to a http url on events, as "url [events]" with comma-separated events among started, server-error and not-found (repeatable; default: all events)
+    #[argh(option, from_str_fn(parse_webhook))]
+    pub webhook: Vec<Webhook>,
+    /// number of 404 responses within a minute that triggers not-found webhooks (default: 100)
+    #[argh(option)]
+    pub webhook_not_found: Option<u32>,
+    /// map the port on the local router via nat-pmp or upnp, and print the public url
+    #[argh(switch)]
+    pub public: bool,
+    /// announce the server on the local network via mdns/bonjour
+    #[argh(switch)]
+    pub mdns: bool,
+    /// instance name to announce via mdns (implies --mdns; default: "nanoserve on <hostname>")
+    #[argh(option)]
+    pub mdns_name: Option<String>,
+    /// subcommand to run instead of serving
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands.
+#[derive(FromArgs, ArgsInfo, Debug)]
+#[argh(subcommand)]
+pub enum Command {
+    /// Generate shell completions.
+    Completions(Completions),
+    /// Measure the performance of a running server.
+    Bench(Bench),
+}
+
+/// Print a shell completion script to stdout.
+#[derive(FromArgs, ArgsInfo, Debug)]
+#[argh(subcommand, name = "completions")]
+pub struct Completions {
+    /// the shell to generate completions for (bash, zsh, fish or powershell)
+    #[argh(positional)]
+    pub shell: Shell,
+}
+
+/// Measure the performance of a running server, reporting requests per second, latency
+/// percentiles and errors.
+#[derive(FromArgs, ArgsInfo, Debug)]
+#[argh(subcommand, name = "bench")]
+pub struct Bench {
+    /// URL to request, or path on the server at --address and --port
+    #[argh(positional)]
+    pub target: String,
+    /// number of concurrent connections (default: 16)
+    #[argh(option, short = 'c', default = "16")]
+    pub concurrency: usize,
+    /// duration of the benchmark, in seconds (default: 10)
+    #[argh(option, short = 'd', default = "10")]
+    pub duration: u64,
+}
+
+/// Parse a `/prefix=upstream` proxy route.
+fn parse_proxy(value: &str) -> Result<(String, Proxy), String> {
+    match value.split_once('=') {
+        Some((prefix, upstream)) if prefix.starts_with('/') && !upstream.is_empty() => {
+            Ok((prefix.to_string(), Proxy::new(upstream)))
+        }
+        _ => Err(format!(
+            "invalid proxy route `{value}`, expected /prefix=host:port"
+        )),
+    }
+}
+
+/// Parse a `/prefix=upstream` caching proxy route.
+fn parse_cache_proxy(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((prefix, upstream)) if prefix.starts_with('/') && !upstream.is_empty() => {
+            Ok((prefix.to_string(), upstream.to_string()))
+        }
+        _ => Err(format!(
+            "invalid caching proxy route `{value}`, expected /prefix=host:port"
+        )),
+    }
+}
+
+/// Parse a rate in bytes per second, like `5MBps`, `512KiB/s` or `100000`.
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let size = value
+        .strip_suffix("ps")
+        .or_else(|| value.strip_suffix("/s"))
+        .unwrap_or(value);
+    bytes(size)
+        .ok_or_else(|| format!("invalid rate `{value}`, expected bytes per second like 5MBps"))
+}
+
+/// Parse a driver performing I/O, like `io-uring`.
+fn parse_driver(value: &str) -> Result<Driver, String> {
+    match value {
+        "auto" => Ok(Driver::Auto),
+        "io_uring" | "io-uring" => Ok(Driver::IoUring),
+        "polling" | "poll" => Ok(Driver::Poll),
+        _ => Err(format!(
+            "unknown driver `{value}`, expected one of: auto, io-uring, polling"
+        )),
+    }
+}
+
+/// Parse a size in bytes, like `64MiB`, `100KB` or `4096`.
+fn parse_size(value: &str) -> Result<u64, String> {
+    bytes(value).ok_or_else(|| format!("invalid size `{value}`, expected bytes like 64MiB"))
+}
+
+/// Parse a positive number of bytes with an optional unit, or `None` if invalid. A lowercase
+/// `b` would be bits, which are not supported.
+fn bytes(value: &str) -> Option<u64> {
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        _ if unit.contains('b') => return None,
+        "" | "B" => 1,
+        "KB" => 1000,
+        "MB" => 1000 * 1000,
+        "GB" => 1000 * 1000 * 1000,
+        "KIB" => 1 << 10,
+        "MIB" => 1 << 20,
+        "GIB" => 1 << 30,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|&bytes| bytes > 0)
+}
+
+/// Parse a `pattern=address` `FastCGI` route.
+fn parse_fastcgi(value: &str) -> Result<FastCgi, String> {
+    match value.split_once('=') {
+        Some((pattern, address)) if !pattern.is_empty() && !address.is_empty() => {
+            Ok(FastCgi::new(address, pattern))
+        }
+        _ => Err(format!(
+            "invalid FastCGI route `{value}`, expected pattern=address, like *.php=127.0.0.1:9000"
+        )),
+    }
+}
+
+/// Parse a `host=dir` virtual host.
+fn parse_vhost(value: &str) -> Result<(String, VirtualHost), String> {
+    match value.split_once('=') {
+        Some((host, root)) if !host.is_empty() && !root.is_empty() => {
+            Ok((host.to_string(), VirtualHost::new(root)))
+        }
+        _ => Err(format!("invalid virtual host `{value}`, expected host=dir")),
+    }
+}
+
+/// Parse a `pattern target [status]` rule with the given constructor.
+fn parse_rule(value: &str, rule: fn(&str, &str) -> RewriteRule) -> Result<RewriteRule, String> {
+    let mut parts = value.split_whitespace();
+    let (Some(pattern), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(format!(
+            "invalid rule `{value}`, expected \"pattern target [status]\""
+        ));
+    };
+    let rule = rule(pattern, target);
+    match (parts.next(), parts.next()) {
+        (None, _) => Ok(rule),
+        (Some(code), None) => match code.parse::<u16>() {
+            Ok(code @ 100..=599) => Ok(rule.code(code.into())),
+            _ => Err(format!("invalid status code `{code}` in rule `{value}`")),
+        },
+        (Some(_), Some(_)) => Err(format!("too many fields in rule `{value}`")),
+    }
+}
+
+/// Parse a `pattern target [status]` rewrite rule.
+fn parse_rewrite(value: &str) -> Result<RewriteRule, String> {
+    parse_rule(value, |pattern, target| {
+        RewriteRule::rewrite(pattern, target)
+    })
+}
+
+/// Parse a `pattern target [status]` redirect rule.
+fn parse_redirect(value: &str) -> Result<RewriteRule, String> {
+    parse_rule(value, |pattern, target| {
+        RewriteRule::redirect(pattern, target)
+    })
+}
+
+/// Parse a `url [events]` webhook.
+fn parse_webhook(value: &str) -> Result<Webhook, String> {
+    let mut parts = value.split_whitespace();
+    let webhook = Webhook::new(parts.next().unwrap_or_default())?;
+    match (parts.next(), parts.next()) {
+        (None, _) => Ok(webhook),
+        (Some(events), None) => {
+            let events = events
+                .split(',')
+                .map(|event| match event {
+                    "started" => Ok(WebhookEvent::Started),
+                    "server-error" => Ok(WebhookEvent::ServerError),
+                    "not-found" => Ok(WebhookEvent::NotFound),
+                    _ => Err(format!("unknown webhook event `{event}`")),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(webhook.events(events))
+        }
+        (Some(_), Some(_)) => Err(format!("too many fields in webhook `{value}`")),
+    }
+}
+
+impl Cli {
+    /// Get the CORS configuration specified by the flags, if any.
+    pub fn cors(&self) -> Option<Cors> {
+        if !self.cors && self.cors_origin.is_none() && self.cors_methods.is_none() {
+            return None;
+        }
+        let mut cors = Cors::permissive();
+        if let Some(origin) = &self.cors_origin {
+            cors = cors.origin(origin);
+        }
+        if let Some(methods) = &self.cors_methods {
+            cors = cors.methods(methods);
+        }
+        Some(cors)
+    }
+
+    /// Get the Basic authentication configuration specified by the flags, if any.
+    pub fn auth(&self) -> Result<Option<BasicAuth>, AuthError> {
+        if self.auth.is_empty() && self.auth_file.is_none() {
+            return Ok(None);
+        }
+        let mut auth = BasicAuth::new();
+        for entry in &self.auth {
+            auth.add_entry(entry)?;
+        }
+        if let Some(path) = &self.auth_file {
+            auth.add_file(path)?;
+        }
+        Ok(Some(auth))
+    }
 }
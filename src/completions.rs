@@ -0,0 +1,343 @@
+//! Shell completion script generation.
+
+use argh::{CommandInfoWithArgs, FlagInfo, FlagInfoKind, PositionalInfo};
+use std::{fmt::Write, str::FromStr};
+
+/// Name of the binary to complete.
+const BIN: &str = "nanoserve";
+/// Options whose value is a path, completed with file names.
+const PATH_OPTIONS: [&str; 3] = ["--archive", "--auth-file", "--cache-dir"];
+/// Names of the supported shells.
+const SHELLS: [&str; 4] = ["bash", "zsh", "fish", "powershell"];
+
+/// Supported shells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            "powershell" | "pwsh" => Ok(Self::Powershell),
+            _ => Err(format!(
+                "unknown shell `{s}`, expected one of: {}",
+                SHELLS.join(", ")
+            )),
+        }
+    }
+}
+
+impl Shell {
+    /// Generate the completion script for the given command.
+    pub fn generate(self, info: &CommandInfoWithArgs) -> String {
+        match self {
+            Self::Bash => bash(info),
+            Self::Zsh => zsh(info),
+            Self::Fish => fish(info),
+            Self::Powershell => powershell(info),
+        }
+    }
+}
+
+/// Get the flags of a command that are shown in its help.
+fn visible(info: &CommandInfoWithArgs) -> Vec<&'static FlagInfo<'static>> {
+    info.flags.iter().filter(|flag| !flag.hidden).collect()
+}
+
+/// Get the positional arguments of a command that are shown in its help.
+fn positionals(info: &CommandInfoWithArgs) -> Vec<&'static PositionalInfo<'static>> {
+    info.positionals
+        .iter()
+        .filter(|positional| !positional.hidden)
+        .collect()
+}
+
+/// Whether the flag takes a value.
+const fn takes_value(flag: &FlagInfo) -> bool {
+    matches!(flag.kind, FlagInfoKind::Option { .. })
+}
+
+/// Whether the value of the flag is a path.
+fn takes_path(flag: &FlagInfo) -> bool {
+    takes_value(flag) && PATH_OPTIONS.contains(&flag.long)
+}
+
+/// Get the values a positional argument is completed with, or none if any value goes.
+fn values(positional: &PositionalInfo) -> &'static [&'static str] {
+    match positional.name {
+        "shell" => &SHELLS,
+        _ => &[],
+    }
+}
+
+/// All spellings of a flag, e.g. `["--port", "-p"]`.
+fn spellings(flag: &FlagInfo) -> Vec<String> {
+    let mut names = vec![flag.long.to_string()];
+    if let Some(short) = flag.short {
+        names.push(format!("-{short}"));
+    }
+    names
+}
+
+/// Generate a bash completion script.
+fn bash(info: &CommandInfoWithArgs) -> String {
+    let mut script = String::new();
+    let _ = writeln!(script, "_{BIN}() {{");
+    script.push_str("    local cur prev\n");
+    script.push_str("    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+    script.push_str("    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    if !info.commands.is_empty() {
+        script.push_str("    if ((COMP_CWORD > 1)); then\n");
+        script.push_str("        case \"${COMP_WORDS[1]}\" in\n");
+        for command in &info.commands {
+            let _ = writeln!(script, "            {})", command.name);
+            bash_arguments(&mut script, &command.command, "                ");
+            script.push_str("                ;;\n");
+        }
+        script.push_str("        esac\n    fi\n");
+    }
+    bash_arguments(&mut script, info, "    ");
+    script.push_str("}\n");
+    let _ = writeln!(script, "complete -F _{BIN} {BIN}");
+    script
+}
+
+/// Write the bash completion of the arguments of a command: its flags, the values of its
+/// positional arguments and its subcommands.
+fn bash_arguments(script: &mut String, info: &CommandInfoWithArgs, indent: &str) {
+    let flags = visible(info);
+    let valued = |path: bool| {
+        flags
+            .iter()
+            .filter(|flag| takes_value(flag) && takes_path(flag) == path)
+            .flat_map(|flag| spellings(flag))
+            .collect::<Vec<_>>()
+    };
+    let (paths, others) = (valued(true), valued(false));
+    if !paths.is_empty() || !others.is_empty() {
+        let _ = writeln!(script, "{indent}case \"$prev\" in");
+        if !paths.is_empty() {
+            let _ = writeln!(script, "{indent}    {})", paths.join("|"));
+            let _ = writeln!(
+                script,
+                "{indent}        COMPREPLY=($(compgen -f -- \"$cur\"))"
+            );
+            let _ = writeln!(script, "{indent}        return\n{indent}        ;;");
+        }
+        if !others.is_empty() {
+            let _ = writeln!(script, "{indent}    {})", others.join("|"));
+            let _ = writeln!(script, "{indent}        return\n{indent}        ;;");
+        }
+        let _ = writeln!(script, "{indent}esac");
+    }
+    let words: Vec<_> = flags
+        .iter()
+        .flat_map(|flag| spellings(flag))
+        .chain(
+            positionals(info)
+                .into_iter()
+                .flat_map(|positional| values(positional))
+                .map(ToString::to_string),
+        )
+        .chain(info.commands.iter().map(|c| c.name.to_string()))
+        .collect();
+    let _ = writeln!(
+        script,
+        "{indent}COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        words.join(" ")
+    );
+    let _ = writeln!(script, "{indent}return");
+}
+
+/// Escape a description for zsh.
+fn zsh_escape(s: &str) -> String {
+    s.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// Generate a zsh completion script.
+fn zsh(info: &CommandInfoWithArgs) -> String {
+    let mut script = format!("#compdef {BIN}\n\n_{BIN}() {{\n");
+    let mut specs = zsh_specs(info);
+    if info.commands.is_empty() {
+        zsh_arguments(&mut script, &specs, "    ");
+        script.push_str("}\n\n");
+        let _ = writeln!(script, "_{BIN} \"$@\"");
+        return script;
+    }
+    specs.push("'1: :->command'".to_string());
+    specs.push("'*:: :->args'".to_string());
+    zsh_arguments(&mut script, &specs, "    ");
+    script.push_str("    case $state in\n        command)\n");
+    let commands: Vec<_> = info
+        .commands
+        .iter()
+        .map(|c| format!("'{}:{}'", c.name, zsh_escape(c.command.description)))
+        .collect();
+    let _ = writeln!(
+        script,
+        "            _describe 'command' \"({})\"",
+        commands.join(" ")
+    );
+    script.push_str("            ;;\n        args)\n            case $words[1] in\n");
+    for command in &info.commands {
+        let _ = writeln!(script, "                {})", command.name);
+        zsh_arguments(
+            &mut script,
+            &zsh_specs(&command.command),
+            "                    ",
+        );
+        script.push_str("                    ;;\n");
+    }
+    script.push_str("            esac\n            ;;\n    esac\n}\n\n");
+    let _ = writeln!(script, "_{BIN} \"$@\"");
+    script
+}
+
+/// Get the `_arguments` specifications of the flags and positional arguments of a command.
+fn zsh_specs(info: &CommandInfoWithArgs) -> Vec<String> {
+    let mut specs = Vec::new();
+    for flag in visible(info) {
+        let value = match flag.kind {
+            FlagInfoKind::Option { arg_name } if takes_path(flag) => format!(":{arg_name}:_files"),
+            FlagInfoKind::Option { arg_name } => format!(":{arg_name}: "),
+            FlagInfoKind::Switch => String::new(),
+        };
+        for name in spellings(flag) {
+            specs.push(format!("'{name}[{}]{value}'", zsh_escape(flag.description)));
+        }
+    }
+    for (position, positional) in positionals(info).into_iter().enumerate() {
+        let values = values(positional);
+        let action = if values.is_empty() {
+            " ".to_string()
+        } else {
+            format!("({})", values.join(" "))
+        };
+        specs.push(format!("'{}:{}:{action}'", position + 1, positional.name));
+    }
+    specs
+}
+
+/// Write a call to `_arguments` with the given specifications.
+fn zsh_arguments(script: &mut String, specs: &[String], indent: &str) {
+    let _ = write!(script, "{indent}_arguments");
+    for spec in specs {
+        let _ = write!(script, " \\\n{indent}    {spec}");
+    }
+    script.push('\n');
+}
+
+/// Escape a description for fish.
+fn fish_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// Generate a fish completion script.
+fn fish(info: &CommandInfoWithArgs) -> String {
+    let mut script = String::new();
+    fish_arguments(&mut script, info, "__fish_use_subcommand");
+    for command in &info.commands {
+        let _ = writeln!(
+            script,
+            "complete -c {BIN} -n __fish_use_subcommand -f -a {} -d '{}'",
+            command.name,
+            fish_escape(command.command.description)
+        );
+    }
+    for command in &info.commands {
+        let condition = format!("'__fish_seen_subcommand_from {}'", command.name);
+        fish_arguments(&mut script, &command.command, &condition);
+    }
+    script
+}
+
+/// Write the fish completions of the flags and positional arguments of a command, applying
+/// under `condition`.
+fn fish_arguments(script: &mut String, info: &CommandInfoWithArgs, condition: &str) {
+    for flag in visible(info) {
+        let _ = write!(
+            script,
+            "complete -c {BIN} -n {condition} -l {}",
+            flag.long.trim_start_matches("--")
+        );
+        if let Some(short) = flag.short {
+            let _ = write!(script, " -s {short}");
+        }
+        if takes_path(flag) {
+            script.push_str(" -r -F");
+        } else if takes_value(flag) {
+            script.push_str(" -x");
+        }
+        let _ = writeln!(script, " -d '{}'", fish_escape(flag.description));
+    }
+    for positional in positionals(info) {
+        let values = values(positional);
+        if !values.is_empty() {
+            let _ = writeln!(
+                script,
+                "complete -c {BIN} -n {condition} -f -a '{}'",
+                values.join(" ")
+            );
+        }
+    }
+}
+
+/// Generate a Powershell completion script.
+fn powershell(info: &CommandInfoWithArgs) -> String {
+    let mut script = format!(
+        "Register-ArgumentCompleter -Native -CommandName '{BIN}' -ScriptBlock {{\n    param($wordToComplete, $commandAst, $cursorPosition)\n"
+    );
+    script.push_str("    $command = if ($commandAst.CommandElements.Count -gt 1) { $commandAst.CommandElements[1].Extent.Text }\n");
+    script.push_str("    $candidates = switch ($command) {\n");
+    for command in &info.commands {
+        let _ = writeln!(script, "        '{}' {{", command.name);
+        powershell_candidates(&mut script, &command.command);
+        script.push_str("        }\n");
+    }
+    script.push_str("        default {\n");
+    powershell_candidates(&mut script, info);
+    script.push_str("        }\n    }\n");
+    script.push_str(
+        "    $candidates | Where-Object { $_.Name -like \"$wordToComplete*\" } | ForEach-Object {\n",
+    );
+    script.push_str("        [System.Management.Automation.CompletionResult]::new($_.Name, $_.Name, 'ParameterValue', $_.Description)\n");
+    script.push_str("    }\n}\n");
+    script
+}
+
+/// Write the Powershell completion candidates for the arguments of a command: its flags, the
+/// values of its positional arguments and its subcommands.
+fn powershell_candidates(script: &mut String, info: &CommandInfoWithArgs) {
+    let escape = |s: &str| s.replace('\'', "''");
+    let mut candidate = |name: &str, description: &str| {
+        let _ = writeln!(
+            script,
+            "            @{{ Name = '{name}'; Description = '{}' }}",
+            escape(description)
+        );
+    };
+    for flag in visible(info) {
+        for name in spellings(flag) {
+            candidate(&name, flag.description);
+        }
+    }
+    for positional in positionals(info) {
+        for value in values(positional) {
+            candidate(value, positional.description);
+        }
+    }
+    for command in &info.commands {
+        candidate(command.name, command.command.description);
+    }
+}
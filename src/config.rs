@@ -0,0 +1,502 @@
+//! Server configuration and builder.
+
+#[cfg(feature = "webhook")]
+use super::Webhook;
+use super::{
+    BasicAuth, ChunkSize, Cors, ErrorKind, FileCache, GlobalThrottle, HTTPServer, Handler, IpRange,
+    Metadata, MetadataCache, NanoserveError, RedirectsFiles, Request, ResponseBody, ResponseHook,
+    RewriteRule, Route, Storage, Summary, VirtualHost, error::Context, forwarded, memory,
+    storage::LocalFs, vhost,
+};
+use compio::net::TcpListener;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io::Error as IoError,
+    net::{IpAddr, SocketAddr, TcpListener as StdTcpListener},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+/// Configuration shared by all connections of a [`HTTPServer`].
+#[derive(Debug, Clone, Default)]
+#[allow(clippy::struct_excessive_bools, reason = "Independent options")]
+pub struct ServerConfig {
+    /// CORS configuration, or `None` to disable CORS.
+    pub cors: Option<Cors>,
+    /// Basic authentication configuration, or `None` to disable authentication.
+    pub auth: Option<BasicAuth>,
+    /// Custom handlers, tried in order before serving files.
+    pub routes: Vec<Route>,
+    /// URL prefix the server is mounted under, like `/myapp`, or empty if mounted at the root.
+    pub base_path: String,
+    /// Document root, or empty for the current directory.
+    pub root: PathBuf,
+    /// Virtual hosts by lowercase host name, taking precedence over `root` and `auth`.
+    pub virtual_hosts: Vec<(String, VirtualHost)>,
+    /// Rewrite and redirect rules, of which the first that applies is used.
+    pub rewrites: Vec<RewriteRule>,
+    /// Rules of `_redirects` files in document roots, applied after `rewrites`.
+    pub redirects_files: RedirectsFiles,
+    /// Reverse proxies trusted to report the client address in `Forwarded` or `X-Forwarded-For`.
+    pub trusted_proxies: Vec<IpRange>,
+    /// Webhooks to notify of events.
+    #[cfg(feature = "webhook")]
+    pub webhooks: Vec<Webhook>,
+    /// Callback passed the summary of each handled request, or `None` for none.
+    pub on_response: Option<ResponseHook>,
+    /// Storage files are served from, or `None` for the local filesystem.
+    pub storage: Option<Rc<dyn Storage>>,
+    /// Cache of the metadata of files being served.
+    pub metadata_cache: MetadataCache,
+    /// Cache of open files, used when serving from the local filesystem.
+    pub file_cache: FileCache,
+    /// Whether to disable Nagle's algorithm on connections, sending small writes right away.
+    pub nodelay: bool,
+    /// Number of connections handled at once before shedding load, or `None` for no limit.
+    pub max_connections: Option<usize>,
+    /// Delay clients are asked to wait before retrying when load is shed.
+    pub retry_after: Duration,
+    /// Sizes of the chunks files are read and written in.
+    pub chunk_size: ChunkSize,
+    /// Maximum rate responses are sent at on each connection, in bytes per second, or `None`
+    /// for no limit.
+    pub throttle: Option<u64>,
+    /// Maximum number of headers of a request, or `None` for [`MAX_HEADERS`](crate::MAX_HEADERS).
+    pub max_headers: Option<usize>,
+    /// Maximum size of a request body as sent, or `None` for [`MAX_BODY`](crate::MAX_BODY).
+    pub max_body: Option<usize>,
+    /// Time allowed to receive a whole request, or `None` for
+    /// [`READ_TIMEOUT`](crate::READ_TIMEOUT).
+    pub read_timeout: Option<Duration>,
+    /// Outbound bandwidth shared fairly by all connections, or `None` for no limit.
+    pub global_throttle: Option<Rc<GlobalThrottle>>,
+    /// Whether to record the [`timings`](crate::timings) of the phases of requests.
+    pub timings: bool,
+    /// Whether to accept header values continued on the next line, instead of rejecting them.
+    pub unfold_headers: bool,
+    /// Whether to lowercase request paths, for case-insensitive filesystems.
+    pub fold_case: bool,
+    /// Whether to reject request heads with lines ending with a bare CR or LF.
+    pub strict_crlf: bool,
+}
+
+impl ServerConfig {
+    /// Prepends the base path to an absolute path, for links and redirects sent to clients.
+    #[must_use]
+    pub fn link(&self, path: &str) -> String {
+        format!("{}{path}", self.base_path)
+    }
+
+    /// Get the virtual host matching the `Host` header of the request, if any.
+    #[must_use]
+    pub fn virtual_host(&self, request: &Request<'_>) -> Option<&VirtualHost> {
+        let name = vhost::host_name(request)?;
+        self.virtual_hosts
+            .iter()
+            .find(|(host, _)| *host == name)
+            .map(|(_, host)| host)
+    }
+
+    /// Get the document root for the request, taking virtual hosts into account.
+    #[must_use]
+    pub fn root(&self, request: &Request<'_>) -> &Path {
+        self.virtual_host(request)
+            .map_or(&self.root, |host| &host.root)
+    }
+
+    /// Get the address of the client, as reported by trusted proxies if the request came through them.
+    #[must_use]
+    pub fn client_ip(&self, request: &Request<'_>) -> Option<IpAddr> {
+        forwarded::client_ip(request, &self.trusted_proxies)
+    }
+
+    /// Get the storage files are served from.
+    #[must_use]
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_deref().unwrap_or(&LocalFs)
+    }
+
+    /// Open the bytes `start..end` of a file of the storage as a response body, reusing open
+    /// files of the local filesystem.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the file cannot be opened, or a [`FileChanged`](crate::FileChanged) if a local file
+    /// is no longer the size of its metadata.
+    pub async fn open(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        start: u64,
+        end: u64,
+    ) -> Result<ResponseBody, IoError> {
+        match &self.storage {
+            Some(storage) => storage.open(path, start, end).await,
+            None => self.file_cache.open(path, metadata, start, end).await,
+        }
+    }
+}
+
+/// Options of the listening socket.
+#[derive(Debug, Clone, Copy)]
+struct SocketOptions {
+    /// Maximum number of pending connections.
+    backlog: i32,
+    /// Size of the send buffer of connections, or `None` for the system default.
+    send_buffer_size: Option<usize>,
+    /// Size of the receive buffer of connections, or `None` for the system default.
+    recv_buffer_size: Option<usize>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            backlog: 128,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Binds a listener with these options. Buffer sizes are inherited by accepted connections.
+    ///
+    /// The socket is not inherited by child processes, with close-on-exec on Unix and without
+    /// handle inheritance on Windows.
+    fn bind(self, addr: SocketAddr) -> Result<StdTcpListener, IoError> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        // On Windows, this would let other sockets bind the same port and take its connections
+        #[cfg(not(windows))]
+        socket.set_reuse_address(true)?;
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog)?;
+        Ok(socket.into())
+    }
+}
+
+/// Builder for [`HTTPServer`].
+#[derive(Debug, Clone)]
+pub struct HTTPServerBuilder {
+    /// The address to bind to.
+    addr: SocketAddr,
+    /// Options of the socket bound to `addr`.
+    socket: SocketOptions,
+    /// An already bound listener to use instead of binding to `addr`.
+    listener: Option<Rc<StdTcpListener>>,
+    /// Number of tasks accepting connections concurrently.
+    acceptors: usize,
+    /// Maximum memory held by caches and buffer pools, if limited.
+    memory_budget: Option<usize>,
+    /// The server configuration.
+    config: ServerConfig,
+}
+
+impl HTTPServerBuilder {
+    /// Creates a new builder for a server listening on the given address.
+    #[must_use]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            socket: SocketOptions::default(),
+            listener: None,
+            acceptors: 1,
+            memory_budget: None,
+            config: ServerConfig::default(),
+        }
+    }
+
+    /// Accepts connections on an already bound listener instead of binding to the address, for
+    /// example one inherited from a previous process to restart without downtime. The backlog
+    /// and buffer sizes of the listener are kept as they are.
+    #[must_use]
+    pub fn listener(mut self, listener: StdTcpListener) -> Self {
+        self.listener = Some(Rc::new(listener));
+        self
+    }
+
+    /// Sets the number of tasks accepting connections concurrently, 1 by default. More tasks
+    /// keep bursts of incoming connections from queueing behind a single `accept`. On Windows,
+    /// this is the number of `AcceptEx` calls kept posted to the completion port.
+    #[must_use]
+    pub const fn acceptors(mut self, acceptors: usize) -> Self {
+        self.acceptors = acceptors;
+        self
+    }
+
+    /// Limits the memory held by the metadata cache and buffer pools together to `bytes`, so
+    /// that enabling caches cannot exhaust the memory of small hosts. Idle buffers are freed
+    /// first when the budget runs short, then cache entries are evicted.
+    #[must_use]
+    pub const fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Sets the maximum number of connections waiting to be accepted, 128 by default.
+    #[must_use]
+    pub const fn backlog(mut self, backlog: i32) -> Self {
+        self.socket.backlog = backlog;
+        self
+    }
+
+    /// Sheds load beyond `max` connections handled at once by answering their HTTP/1.1 requests
+    /// with 503 and a `Retry-After` of `retry_after`, rounded up to whole seconds, instead of
+    /// leaving them waiting to be accepted, so that retrying clients and load balancers can back
+    /// off.
+    #[must_use]
+    pub const fn max_connections(mut self, max: usize, retry_after: Duration) -> Self {
+        self.config.max_connections = Some(max);
+        self.config.retry_after = retry_after;
+        self
+    }
+
+    /// Sets the size of the send buffer of connections, instead of the system default.
+    #[must_use]
+    pub const fn send_buffer_size(mut self, size: usize) -> Self {
+        self.socket.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the size of the receive buffer of connections, instead of the system default.
+    #[must_use]
+    pub const fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.socket.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Disables Nagle's algorithm on connections (`TCP_NODELAY`), so that small responses are
+    /// not delayed.
+    #[must_use]
+    pub const fn nodelay(mut self, nodelay: bool) -> Self {
+        self.config.nodelay = nodelay;
+        self
+    }
+
+    /// Reads and writes files in chunks of `initial` bytes, growing up to `max` bytes for large
+    /// transfers, instead of 8 KiB growing up to 256 KiB. Equal sizes disable the growth.
+    #[must_use]
+    pub fn chunk_size(mut self, initial: usize, max: usize) -> Self {
+        self.config.chunk_size = ChunkSize {
+            initial,
+            max: max.max(initial),
+        };
+        self
+    }
+
+    /// Limits each connection to sending `rate` bytes per second, so that a single download
+    /// cannot saturate the uplink.
+    #[must_use]
+    pub const fn throttle(mut self, rate: u64) -> Self {
+        self.config.throttle = Some(rate);
+        self
+    }
+
+    /// Limits all connections together to sending `rate` bytes per second, shared fairly
+    /// between active transfers.
+    #[must_use]
+    pub fn global_throttle(mut self, rate: u64) -> Self {
+        self.config.global_throttle = Some(Rc::new(GlobalThrottle::new(rate)));
+        self
+    }
+
+    /// Rejects requests with more than `max` headers, [`MAX_HEADERS`](crate::MAX_HEADERS) by default.
+    #[must_use]
+    pub const fn max_headers(mut self, max: usize) -> Self {
+        self.config.max_headers = Some(max);
+        self
+    }
+
+    /// Rejects requests with bodies of more than `bytes` as sent with 413, or by resetting their
+    /// stream over HTTP/2, [`MAX_BODY`](crate::MAX_BODY) by default.
+    #[must_use]
+    pub const fn max_body(mut self, bytes: usize) -> Self {
+        self.config.max_body = Some(bytes);
+        self
+    }
+
+    /// Answers requests not received completely within `timeout` with 408, and closes idle
+    /// connections silently, [`READ_TIMEOUT`](crate::READ_TIMEOUT) by default.
+    #[must_use]
+    pub const fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Accepts header values continued on lines starting with whitespace (obsolete line
+    /// folding), joining the lines with spaces, instead of rejecting them with 400.
+    #[must_use]
+    pub const fn unfold_headers(mut self, unfold: bool) -> Self {
+        self.config.unfold_headers = unfold;
+        self
+    }
+
+    /// Rejects requests with lines of their head ending with a bare CR or LF instead of CRLF with
+    /// 400, so that proxies cannot read them differently. Bare LF is accepted otherwise.
+    #[must_use]
+    pub const fn strict_crlf(mut self, strict: bool) -> Self {
+        self.config.strict_crlf = strict;
+        self
+    }
+
+    /// Lowercases the ASCII letters of request paths, so that rewrite rules, handlers and logs
+    /// see the same path for any case of a file on a case-insensitive filesystem.
+    #[must_use]
+    pub const fn fold_case(mut self, fold_case: bool) -> Self {
+        self.config.fold_case = fold_case;
+        self
+    }
+
+    /// Records the durations of the phases of requests, available from
+    /// [`timings`](crate::timings), and emitted as `tracing` events with the `tracing` feature.
+    #[must_use]
+    pub const fn timings(mut self, timings: bool) -> Self {
+        self.config.timings = timings;
+        self
+    }
+
+    /// Enables CORS with the given configuration.
+    #[must_use]
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.config.cors = Some(cors);
+        self
+    }
+
+    /// Requires Basic authentication with the given configuration.
+    #[must_use]
+    pub fn auth(mut self, auth: BasicAuth) -> Self {
+        self.config.auth = Some(auth);
+        self
+    }
+
+    /// Serves files from the given document root instead of the current directory.
+    #[must_use]
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.config.root = root.into();
+        self
+    }
+
+    /// Adds a rewrite or redirect rule, applied before handlers and file serving.
+    #[must_use]
+    pub fn rewrite(mut self, rule: RewriteRule) -> Self {
+        self.config.rewrites.push(rule);
+        self
+    }
+
+    /// Adds a virtual host, used for requests whose `Host` header is `name` (ignoring the port).
+    #[must_use]
+    pub fn virtual_host(mut self, name: &str, host: VirtualHost) -> Self {
+        self.config
+            .virtual_hosts
+            .push((name.trim_end_matches('.').to_ascii_lowercase(), host));
+        self
+    }
+
+    /// Trusts reverse proxies in the given range to report the client address.
+    #[must_use]
+    pub fn trusted_proxy(mut self, range: IpRange) -> Self {
+        self.config.trusted_proxies.push(range);
+        self
+    }
+
+    /// Adds a webhook to notify of events.
+    #[cfg(feature = "webhook")]
+    #[must_use]
+    pub fn webhook(mut self, webhook: Webhook) -> Self {
+        self.config.webhooks.push(webhook);
+        self
+    }
+
+    /// Calls `hook` with the [`Summary`] of each handled request once it is answered, as logged,
+    /// to record metrics or structured logs. The values of headers carrying credentials, like
+    /// `Authorization` and `Cookie`, are redacted.
+    #[must_use]
+    pub fn on_response(mut self, hook: impl Fn(&Summary<'_>) + 'static) -> Self {
+        self.config.on_response = Some(ResponseHook::new(hook));
+        self
+    }
+
+    /// Serves files from the given storage instead of the local filesystem.
+    #[must_use]
+    pub fn storage(mut self, storage: impl Storage + 'static) -> Self {
+        self.config.storage = Some(Rc::new(storage));
+        self
+    }
+
+    /// Caches the metadata of files being served for the given time, instead of reading it
+    /// for every request.
+    #[must_use]
+    pub fn metadata_cache(mut self, ttl: Duration) -> Self {
+        self.config.metadata_cache = MetadataCache::new(ttl);
+        self
+    }
+
+    /// Keeps up to `budget` hot files of the local filesystem open, instead of reopening them
+    /// for every request.
+    #[must_use]
+    pub fn file_cache(mut self, budget: usize) -> Self {
+        self.config.file_cache = FileCache::new(budget);
+        self
+    }
+
+    /// Mounts the server under the given URL prefix, which is stripped from request paths.
+    ///
+    /// This is needed behind a reverse proxy forwarding a subpath like `/myapp` to the server.
+    #[must_use]
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        let base_path = base_path.trim_matches('/');
+        self.config.base_path = if base_path.is_empty() {
+            String::new()
+        } else {
+            format!("/{base_path}")
+        };
+        self
+    }
+
+    /// Mounts a custom handler at the given path prefix. Handlers are tried in the order they are added.
+    #[must_use]
+    pub fn handler(mut self, prefix: &str, handler: impl Handler + 'static) -> Self {
+        self.config.routes.push(Route {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            handler: Rc::new(handler),
+        });
+        self
+    }
+
+    /// Binds to the address and builds the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`NanoserveError`] if the server fails to bind to the address.
+    #[allow(
+        clippy::unused_async,
+        reason = "Binding may become asynchronous again, as it was"
+    )]
+    pub async fn build(self) -> Result<HTTPServer, NanoserveError> {
+        let listener = match &self.listener {
+            Some(listener) => listener.try_clone().and_then(TcpListener::from_std),
+            None => self.socket.bind(self.addr).and_then(TcpListener::from_std),
+        }
+        .context(ErrorKind::Bind)?;
+        if let Some(bytes) = self.memory_budget {
+            memory::set_budget(bytes);
+        }
+        // Report problems with the `_redirects` files at startup
+        self.config.redirects_files.rules(&self.config.root);
+        for (_, host) in &self.config.virtual_hosts {
+            self.config.redirects_files.rules(&host.root);
+        }
+        Ok(HTTPServer {
+            listener,
+            config: Rc::new(self.config),
+            connections: Rc::default(),
+            acceptors: self.acceptors,
+        })
+    }
+}
@@ -0,0 +1,148 @@
+//! Cookies set by responses, with [`Response::set_cookie`](super::Response::set_cookie).
+
+use std::{fmt::Write, time::Duration};
+
+/// Whether browsers send a cookie along with cross-site requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only sent with same-site requests.
+    Strict,
+    /// Also sent when navigating to the site from another one.
+    Lax,
+    /// Sent with all requests, which browsers only allow for `Secure` cookies.
+    None,
+}
+
+impl SameSite {
+    /// Get the value of the `SameSite` attribute.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+/// A cookie to set, serialized as a `Set-Cookie` header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cookie {
+    /// Cookie name.
+    pub name: String,
+    /// Cookie value.
+    pub value: String,
+    /// Path the cookie is sent for, with its subpaths.
+    pub path: Option<String>,
+    /// Time until the cookie expires, or `None` for a session cookie.
+    pub max_age: Option<Duration>,
+    /// Whether to hide the cookie from scripts.
+    pub http_only: bool,
+    /// Whether to only send the cookie over secure connections.
+    pub secure: bool,
+    /// Whether to send the cookie along with cross-site requests.
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Creates a session cookie with the given name and value.
+    #[must_use]
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a cookie removing the cookie `name` set for the same path.
+    #[must_use]
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "").max_age(Duration::ZERO)
+    }
+
+    /// Sets the path.
+    #[must_use]
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets the time until the cookie expires, in whole seconds.
+    #[must_use]
+    pub const fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets whether to hide the cookie from scripts.
+    #[must_use]
+    pub const fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Sets whether to only send the cookie over secure connections.
+    #[must_use]
+    pub const fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Sets whether to send the cookie along with cross-site requests.
+    #[must_use]
+    pub const fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Serializes the cookie as the value of a `Set-Cookie` header.
+    ///
+    /// Characters cookies cannot hold, like spaces, quotes, commas and semicolons, are
+    /// percent-encoded in the name and value, and stripped from the path. `SameSite=None`
+    /// implies `Secure`, without which browsers reject it.
+    #[must_use]
+    pub fn encode(&self) -> String {
+        let mut encoded = String::new();
+        percent_encode(&mut encoded, &self.name, |b| {
+            b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}%".contains(&b)
+        });
+        encoded.push('=');
+        percent_encode(&mut encoded, &self.value, |b| {
+            b.is_ascii_graphic() && !b"\",;\\%".contains(&b)
+        });
+        if let Some(path) = &self.path {
+            let path: String = path
+                .chars()
+                .filter(|&c| c != ';' && !c.is_control())
+                .collect();
+            let _ = write!(encoded, "; Path={path}");
+        }
+        if let Some(max_age) = self.max_age {
+            let _ = write!(encoded, "; Max-Age={}", max_age.as_secs());
+        }
+        if self.http_only {
+            encoded.push_str("; HttpOnly");
+        }
+        if self.secure || self.same_site == Some(SameSite::None) {
+            encoded.push_str("; Secure");
+        }
+        if let Some(same_site) = self.same_site {
+            let _ = write!(encoded, "; SameSite={}", same_site.as_str());
+        }
+        encoded
+    }
+}
+
+/// Append `s` to `encoded`, percent-encoding the bytes not `allowed`.
+fn percent_encode(encoded: &mut String, s: &str, allowed: impl Fn(u8) -> bool) {
+    for c in s.chars() {
+        if c.is_ascii() && allowed(c as u8) {
+            encoded.push(c);
+        } else {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Cross-Origin Resource Sharing (CORS) support.
+
+use super::{Request, Response, response::ResponseCode};
+
+/// CORS configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cors {
+    /// Value of the `Access-Control-Allow-Origin` header.
+    pub origin: String,
+    /// Value of the `Access-Control-Allow-Methods` header.
+    pub methods: String,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::permissive()
+    }
+}
+
+impl Cors {
+    /// Permissive CORS configuration, allowing any origin.
+    #[must_use]
+    pub fn permissive() -> Self {
+        Self {
+            origin: "*".to_string(),
+            methods: "GET, OPTIONS".to_string(),
+        }
+    }
+
+    /// Set the allowed origin.
+    #[must_use]
+    pub fn origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = origin.into();
+        self
+    }
+
+    /// Set the allowed methods.
+    #[must_use]
+    pub fn methods(mut self, methods: impl Into<String>) -> Self {
+        self.methods = methods.into();
+        self
+    }
+
+    /// Answer a preflight request, if the given request is one.
+    #[must_use]
+    pub fn preflight(&self, request: &Request<'_>) -> Option<Response> {
+        let is_preflight = request.method == "OPTIONS"
+            && request
+                .headers
+                .iter()
+                .any(|(key, _)| key.eq_ignore_ascii_case("Access-Control-Request-Method"));
+        if !is_preflight {
+            return None;
+        }
+        let response = Response::new(ResponseCode::NoContent, "")
+            .with_header("Access-Control-Allow-Methods", self.methods.clone())
+            .with_header("Access-Control-Allow-Headers", "*")
+            .with_header("Access-Control-Max-Age", "86400");
+        Some(response)
+    }
+
+    /// Add CORS headers to the given response.
+    pub fn apply(&self, response: &mut Response) {
+        response.add_header("Access-Control-Allow-Origin", self.origin.clone());
+        if self.origin != "*" {
+            response.add_vary("Origin");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Selection and tuning of the compio driver performing I/O, for programs creating the runtime
+//! that servers run on themselves.
+
+use compio::{
+    driver::{DriverType, ProactorBuilder},
+    runtime::{Runtime, RuntimeBuilder},
+};
+use std::{
+    fmt,
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    time::Duration,
+};
+
+/// Driver performing I/O.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Driver {
+    /// The driver of the platform: `io_uring` on Linux, IOCP on Windows, and polling elsewhere.
+    #[default]
+    Auto,
+    /// `io_uring`, on Linux.
+    IoUring,
+    /// Polling for readiness, with `kqueue` on macOS and BSDs.
+    Poll,
+}
+
+impl Driver {
+    /// Get the name of the driver.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::IoUring => "io_uring",
+            Self::Poll => "polling",
+        }
+    }
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Options of the runtime servers run on, built with [`RuntimeOptions::build`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RuntimeOptions {
+    /// Driver performing I/O.
+    driver: Driver,
+    /// Idle time of the `io_uring` submission queue polling thread, or `None` to not use one.
+    sqpoll_idle: Option<Duration>,
+    /// Number of entries of the submission or event queue, or `None` for the default of compio.
+    queue_depth: Option<u32>,
+}
+
+impl RuntimeOptions {
+    /// Creates the default options, with [`Driver::Auto`].
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            driver: Driver::Auto,
+            sqpoll_idle: None,
+            queue_depth: None,
+        }
+    }
+
+    /// Selects the driver performing I/O, [`Driver::Auto`] by default. Other drivers fail to
+    /// build where they are not available, rather than being replaced by another one.
+    #[must_use]
+    pub const fn driver(mut self, driver: Driver) -> Self {
+        self.driver = driver;
+        self
+    }
+
+    /// Submits I/O to `io_uring` from a kernel thread, which sleeps once idle for `idle`, saving
+    /// system calls at the cost of a busy CPU. This is ignored by other drivers.
+    #[must_use]
+    pub const fn sqpoll(mut self, idle: Duration) -> Self {
+        self.sqpoll_idle = Some(idle);
+        self
+    }
+
+    /// Sets the number of entries of the submission queue of `io_uring`, or of the event queue
+    /// of other drivers, 1024 by default.
+    #[must_use]
+    pub const fn queue_depth(mut self, entries: u32) -> Self {
+        self.queue_depth = Some(entries);
+        self
+    }
+
+    /// Builds the runtime, to run servers on with [`Runtime::block_on`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the driver is not available, or cannot be created like
+    /// [`Driver::IoUring`] on kernels without `io_uring`.
+    pub fn build(&self) -> IoResult<Runtime> {
+        let driver_type = match self.driver {
+            Driver::Auto => None,
+            Driver::IoUring => Some(DriverType::IoUring),
+            Driver::Poll => Some(DriverType::Poll),
+        };
+        let mut proactor = ProactorBuilder::new();
+        if let Some(driver_type) = driver_type {
+            proactor.driver_type(driver_type);
+        }
+        if let Some(idle) = self.sqpoll_idle {
+            proactor.sqpoll_idle(idle);
+        }
+        if let Some(entries) = self.queue_depth {
+            proactor.capacity(entries);
+        }
+        let runtime = RuntimeBuilder::new().with_proactor(proactor).build()?;
+        // compio ignores the selection when it is built with a single driver
+        if driver_type.is_some_and(|driver_type| runtime.driver_type() != driver_type) {
+            return Err(IoError::new(
+                ErrorKind::Unsupported,
+                format!("The {} driver is not available", self.driver),
+            ));
+        }
+        Ok(runtime)
+    }
+}
+
+/// Get the name of the driver performing I/O on the current runtime, like `io_uring`, or `None`
+/// outside of a runtime.
+#[must_use]
+pub fn current_driver() -> Option<&'static str> {
+    Runtime::try_with_current(|runtime| match runtime.driver_type() {
+        DriverType::IoUring => Driver::IoUring.name(),
+        DriverType::Poll => Driver::Poll.name(),
+        DriverType::IOCP => "IOCP",
+    })
+    .ok()
+}
@@ -0,0 +1,138 @@
+//! Serving assets embedded in the executable.
+
+use super::{
+    Handler, HandlerFuture, Request, Response, ResponseBody, ResponseCode, mime,
+    response::{byte_range, content_range},
+};
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hasher},
+};
+
+/// An asset and its headers.
+#[derive(Debug, Clone)]
+struct Asset {
+    /// Content of the asset.
+    content: &'static [u8],
+    /// Media type, guessed from the path.
+    content_type: &'static str,
+    /// Strong entity tag, from a hash of the content.
+    etag: String,
+}
+
+/// A [`Handler`] serving assets baked into the executable, so that it can ship with its web UI.
+///
+/// Assets are keyed by their absolute URL path, and `index.html` is served for directories.
+/// `Content-Type` is guessed from the extension, and an `ETag` derived from the content allows
+/// clients to revalidate with `If-None-Match`. `HEAD` is answered from the asset too, and requests
+/// for other paths or with other methods fall through.
+///
+/// ```no_run
+/// use nanoserve::{EmbeddedFiles, HTTPServer};
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let ui = EmbeddedFiles::new([
+///     ("/ui/index.html", &include_bytes!("../README.md")[..]),
+///     ("/ui/app.js", b"console.log('Hello');"),
+/// ]);
+/// let server = HTTPServer::builder("127.0.0.1:8080".parse().unwrap())
+///     .handler("/ui", ui)
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedFiles {
+    /// Assets by path.
+    assets: HashMap<&'static str, Asset>,
+}
+
+impl EmbeddedFiles {
+    /// Creates a handler serving the given `(path, content)` pairs.
+    #[must_use]
+    pub fn new(files: impl IntoIterator<Item = (&'static str, &'static [u8])>) -> Self {
+        let assets = files
+            .into_iter()
+            .map(|(path, content)| {
+                let mut hasher = DefaultHasher::new();
+                hasher.write(content);
+                let asset = Asset {
+                    content,
+                    content_type: mime::guess(path),
+                    etag: format!("\"{:016x}\"", hasher.finish()),
+                };
+                (path, asset)
+            })
+            .collect();
+        Self { assets }
+    }
+
+    /// Get the asset for a request path, trying `index.html` for directories.
+    fn asset(&self, path: &str) -> Option<&Asset> {
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        if path.ends_with('/') {
+            self.assets.get(format!("{path}index.html").as_str())
+        } else {
+            self.assets.get(path)
+        }
+    }
+
+    /// Builds the response for an asset.
+    fn respond(asset: &Asset, request: &Request<'_>) -> Response {
+        let content = asset.content;
+        if request.matches_etag(&asset.etag) {
+            let body = ResponseBody::Omitted {
+                size: content.len() as u64,
+            };
+            return Response {
+                code: ResponseCode::NotModified,
+                headers: vec![("ETag".into(), asset.etag.clone())],
+                body,
+                chunk_size: None,
+            };
+        }
+        let size = content.len() as u64;
+        let (code, body, headers) = match byte_range(request, size) {
+            Ok(None) => (ResponseCode::Ok, content, Vec::new()),
+            Ok(Some((start, end))) => {
+                #[allow(
+                    clippy::cast_possible_truncation,
+                    reason = "Within the length of a slice"
+                )]
+                let range = start as usize..end as usize;
+                let headers = vec![content_range(start, end, size)];
+                (ResponseCode::PartialContent, &content[range], headers)
+            }
+            Err(response) => return response,
+        };
+        // The head describes the same response, without sending the asset
+        let body = if request.method == "HEAD" {
+            ResponseBody::Omitted {
+                size: body.len() as u64,
+            }
+        } else {
+            ResponseBody::Bytes(body)
+        };
+        Response {
+            code,
+            headers,
+            body,
+            chunk_size: None,
+        }
+        .with_header("Content-Type", asset.content_type)
+        .with_header("ETag", asset.etag.clone())
+    }
+}
+
+impl Handler for EmbeddedFiles {
+    fn handle<'a>(&'a self, request: &'a Request<'_>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            if !matches!(request.method, "GET" | "HEAD") {
+                return None;
+            }
+            let asset = self.asset(request.path)?;
+            Some(Self::respond(asset, request))
+        })
+    }
+}
@@ -1,34 +1,165 @@
 //! Errors for nanoserve.
 
-use super::ParseRequestError;
-use std::{fmt, io::Error as IoError};
+use super::{ParseRequestError, ResponseCode};
+use std::{
+    error::Error,
+    fmt,
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+};
 
-/// Possible errors in nanoserve.
+/// What nanoserve was doing when a [`NanoserveError`] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Binding the listening socket.
+    Bind,
+    /// Accepting a connection.
+    Accept,
+    /// Reading a request from a connection.
+    Read,
+    /// Parsing a request.
+    Parse,
+    /// Opening or reading a file to serve.
+    FileIo,
+    /// Writing a response to a connection.
+    Write,
+}
+
+impl ErrorKind {
+    /// Describe what failed.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Bind => "Failed to bind",
+            Self::Accept => "Failed to accept connection",
+            Self::Read => "Failed to read request",
+            Self::Parse => "Failed to parse request",
+            Self::FileIo => "Failed to read file",
+            Self::Write => "Failed to write response",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// An error in nanoserve, with what it was doing when it happened and the underlying error.
 #[derive(Debug)]
-pub enum NanoserveError {
+pub struct NanoserveError {
+    /// What failed.
+    kind: ErrorKind,
+    /// The underlying error.
+    source: Source,
+}
+
+/// The underlying error of a [`NanoserveError`].
+#[derive(Debug)]
+enum Source {
     /// IO error.
     Io(IoError),
     /// Error parsing request.
     ParseRequest(ParseRequestError),
 }
 
-impl From<IoError> for NanoserveError {
-    fn from(error: IoError) -> Self {
-        Self::Io(error)
+impl NanoserveError {
+    /// Creates an error for an IO error that happened while doing `kind`.
+    #[must_use]
+    pub const fn io(kind: ErrorKind, error: IoError) -> Self {
+        Self {
+            kind,
+            source: Source::Io(error),
+        }
+    }
+
+    /// Get what failed.
+    #[must_use]
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Get the underlying IO error, if any.
+    #[must_use]
+    pub const fn io_error(&self) -> Option<&IoError> {
+        match &self.source {
+            Source::Io(e) => Some(e),
+            Source::ParseRequest(_) => None,
+        }
+    }
+
+    /// Get the underlying request parsing error, if any.
+    #[must_use]
+    pub const fn parse_error(&self) -> Option<ParseRequestError> {
+        match self.source {
+            Source::ParseRequest(e) => Some(e),
+            Source::Io(_) => None,
+        }
+    }
+
+    /// Get the status of the response answering a request that failed with this error, or
+    /// `None` if no response can be sent, like when the connection itself failed.
+    #[must_use]
+    pub fn status(&self) -> Option<ResponseCode> {
+        match (self.kind, &self.source) {
+            (_, Source::ParseRequest(e)) => Some(e.status()),
+            (ErrorKind::Read, Source::Io(e)) if e.kind() == IoErrorKind::TimedOut => {
+                Some(ResponseCode::RequestTimeout)
+            }
+            (ErrorKind::FileIo, Source::Io(e)) if e.kind() == IoErrorKind::NotFound => {
+                Some(ResponseCode::NotFound)
+            }
+            (ErrorKind::FileIo, _) => Some(ResponseCode::InternalServerError),
+            _ => None,
+        }
     }
 }
 
 impl From<ParseRequestError> for NanoserveError {
     fn from(error: ParseRequestError) -> Self {
-        Self::ParseRequest(error)
+        Self {
+            kind: ErrorKind::Parse,
+            source: Source::ParseRequest(error),
+        }
+    }
+}
+
+impl From<NanoserveError> for IoError {
+    fn from(error: NanoserveError) -> Self {
+        match error.source {
+            Source::Io(e) => e,
+            Source::ParseRequest(e) => Self::new(IoErrorKind::InvalidData, e),
+        }
     }
 }
 
 impl fmt::Display for NanoserveError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Io(e) => write!(f, "IO error: {e}"),
-            Self::ParseRequest(e) => write!(f, "Parse request error: {e}"),
+        match &self.source {
+            Source::Io(e) => write!(f, "{}: {e}", self.kind),
+            Source::ParseRequest(e) => write!(f, "{}: {e}", self.kind),
+        }
+    }
+}
+
+impl Error for NanoserveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.source {
+            Source::Io(e) => Some(e),
+            Source::ParseRequest(e) => Some(e),
         }
     }
 }
+
+/// Adds what failed to IO errors.
+pub trait Context<T> {
+    /// Wrap the error in a [`NanoserveError`] of `kind`.
+    fn context(self, kind: ErrorKind) -> Result<T, NanoserveError>;
+}
+
+impl<T> Context<T> for Result<T, IoError> {
+    fn context(self, kind: ErrorKind) -> Result<T, NanoserveError> {
+        self.map_err(|e| NanoserveError::io(kind, e))
+    }
+}
@@ -0,0 +1,355 @@
+//! `FastCGI` upstream handler, e.g. for PHP-FPM.
+
+use super::{
+    Handler, HandlerFuture, Request, Response, ResponseBody, ResponseCode, glob, response::unfold,
+};
+use compio::{
+    BufResult,
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+};
+use std::{
+    borrow::Cow,
+    env::current_dir,
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    path::{Component, Path, PathBuf},
+};
+
+/// Protocol version.
+const VERSION: u8 = 1;
+/// Identifier of the only request sent on each connection.
+const REQUEST_ID: u16 = 1;
+/// The `FCGI_RESPONDER` role.
+const RESPONDER: u16 = 1;
+/// Maximum content length of a record.
+const MAX_CONTENT: usize = 65_535;
+/// Maximum size of the CGI response head.
+const MAX_HEAD: usize = 64 * 1024;
+/// Size of each chunk read from the `FastCGI` server.
+const CHUNK_SIZE: usize = 8192;
+
+/// Record types.
+mod record {
+    pub const BEGIN_REQUEST: u8 = 1;
+    pub const END_REQUEST: u8 = 3;
+    pub const PARAMS: u8 = 4;
+    pub const STDIN: u8 = 5;
+    pub const STDOUT: u8 = 6;
+    pub const STDERR: u8 = 7;
+}
+
+/// A [`Handler`] forwarding requests whose path matches a glob pattern to a `FastCGI` server.
+///
+/// Requests for scripts that do not exist fall through to file serving. The response is
+/// streamed back as the `FastCGI` server produces it.
+#[derive(Debug, Clone)]
+pub struct FastCgi {
+    /// Address of the `FastCGI` server: `host:port`, or the path of a Unix socket.
+    address: String,
+    /// Glob pattern of request paths to forward, like `*.php`.
+    pattern: String,
+    /// Document root, used to resolve script paths.
+    root: PathBuf,
+}
+
+impl FastCgi {
+    /// Creates a handler forwarding paths matching `pattern` to the `FastCGI` server at `address`.
+    ///
+    /// The address is either `host:port`, or a Unix socket path like `unix:/run/php/php-fpm.sock`
+    /// (the `unix:` prefix is optional for paths containing `/`). In the pattern, `*` matches any
+    /// sequence of characters and `?` any single character. Scripts are resolved relative to the
+    /// current directory, like served files.
+    #[must_use]
+    pub fn new(address: &str, pattern: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            pattern: pattern.to_string(),
+            root: current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        }
+    }
+
+    /// Sets the document root used to resolve script paths.
+    #[must_use]
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// Resolve the script file for a request path, if it matches and exists.
+    fn script(&self, script_name: &str) -> Option<PathBuf> {
+        glob::captures(&self.pattern, script_name)?;
+        let relative = Path::new(script_name.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        let path = self.root.join(relative);
+        path.is_file().then_some(path)
+    }
+
+    /// Encode the CGI parameters of the request.
+    fn params(&self, request: &Request<'_>, script_name: &str, filename: &Path) -> Vec<u8> {
+        let query = request.path.split_once('?').map_or("", |(_, query)| query);
+        let mut params = Vec::new();
+        let mut add = |name: &str, value: &str| encode_param(&mut params, name, value);
+        add("GATEWAY_INTERFACE", "CGI/1.1");
+        add(
+            "SERVER_SOFTWARE",
+            concat!("nanoserve/", env!("CARGO_PKG_VERSION")),
+        );
+        add("SERVER_PROTOCOL", &format!("HTTP/{}", request.version));
+        add("REQUEST_METHOD", request.method);
+        add("REQUEST_URI", request.path);
+        add("SCRIPT_NAME", script_name);
+        add("SCRIPT_FILENAME", &filename.to_string_lossy());
+        add("DOCUMENT_ROOT", &self.root.to_string_lossy());
+        add("QUERY_STRING", query);
+        // Required by PHP when built with `--enable-force-cgi-redirect`
+        add("REDIRECT_STATUS", "200");
+        if let Some(peer) = request.peer {
+            add("REMOTE_ADDR", &peer.ip().to_string());
+            add("REMOTE_PORT", &peer.port().to_string());
+        }
+        add("CONTENT_LENGTH", &request.body.len().to_string());
+        for &(name, value) in &request.headers {
+            // `Proxy` would become `HTTP_PROXY`, which applications take for their proxy
+            // (httpoxy), and names with underscores would be confused with those with dashes
+            if name.eq_ignore_ascii_case("Content-Length")
+                || name.eq_ignore_ascii_case("Proxy")
+                || name.contains('_')
+            {
+                continue;
+            }
+            if name.eq_ignore_ascii_case("Content-Type") {
+                add("CONTENT_TYPE", value);
+                continue;
+            }
+            if name.eq_ignore_ascii_case("Host") {
+                let host = value.rsplit_once(':').map_or(value, |(host, _)| host);
+                add("SERVER_NAME", host);
+            }
+            let name = format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_"));
+            add(&name, value);
+        }
+        params
+    }
+
+    /// Forward the request and read the response head.
+    async fn forward(
+        &self,
+        request: &Request<'_>,
+        script_name: &str,
+        filename: &Path,
+    ) -> IoResult<Response> {
+        let mut message = Vec::new();
+        let mut begin = Vec::with_capacity(8);
+        begin.extend_from_slice(&RESPONDER.to_be_bytes());
+        begin.extend_from_slice(&[0; 6]);
+        encode_records(&mut message, record::BEGIN_REQUEST, &begin);
+        encode_records(
+            &mut message,
+            record::PARAMS,
+            &self.params(request, script_name, filename),
+        );
+        encode_records(&mut message, record::PARAMS, &[]);
+        encode_records(&mut message, record::STDIN, request.body);
+        encode_records(&mut message, record::STDIN, &[]);
+
+        let socket = self.address.strip_prefix("unix:");
+        match socket {
+            Some(path) => exchange(UnixStream::connect(path).await?, message).await,
+            None if self.address.contains('/') => {
+                exchange(UnixStream::connect(&self.address).await?, message).await
+            }
+            None => exchange(TcpStream::connect(self.address.as_str()).await?, message).await,
+        }
+    }
+}
+
+impl Handler for FastCgi {
+    fn handle<'a>(&'a self, request: &'a Request<'_>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let script_name = request
+                .path
+                .split_once('?')
+                .map_or(request.path, |(path, _)| path);
+            let filename = self.script(script_name)?;
+            Some(
+                self.forward(request, script_name, &filename)
+                    .await
+                    .unwrap_or_else(|e| {
+                        eprintln!(
+                            "Error while forwarding to FastCGI server {}: {e}",
+                            self.address
+                        );
+                        Response::new(ResponseCode::BadGateway, "502 Bad Gateway")
+                    }),
+            )
+        })
+    }
+}
+
+/// Send the request message, and read the response head.
+async fn exchange<S>(mut stream: S, message: Vec<u8>) -> IoResult<Response>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    stream.write_all(message).await.0?;
+    let mut records = Records {
+        stream,
+        buffer: Vec::new(),
+    };
+
+    // Read the CGI response head
+    let mut head = Vec::new();
+    let (end, separator) = loop {
+        // Whichever line ending the script uses, the first empty line ends the head, as the
+        // body may contain the other one
+        let crlf = head.windows(4).position(|w| w == b"\r\n\r\n");
+        let lf = head.windows(2).position(|w| w == b"\n\n");
+        match (crlf, lf) {
+            (Some(crlf), Some(lf)) if lf < crlf => break (lf, 2),
+            (Some(crlf), _) => break (crlf, 4),
+            (None, Some(lf)) => break (lf, 2),
+            (None, None) => {}
+        }
+        if head.len() > MAX_HEAD {
+            return Err(invalid("response head too large"));
+        }
+        let Some(chunk) = records.stdout().await? else {
+            return Err(invalid("request ended before the response head"));
+        };
+        head.extend_from_slice(&chunk);
+    };
+    let rest = head.split_off(end + separator);
+    head.truncate(end);
+    let head = String::from_utf8(head).map_err(|_| invalid("response head is not UTF-8"))?;
+
+    let mut code = ResponseCode::Ok;
+    let mut headers = Vec::new();
+    for (name, value) in head.lines().filter_map(|line| line.split_once(':')) {
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("Status") {
+            let status = value
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse::<u16>().ok());
+            code = status
+                .ok_or_else(|| invalid("invalid Status header"))?
+                .into();
+        } else {
+            if name.eq_ignore_ascii_case("Location") && code == ResponseCode::Ok {
+                code = ResponseCode::Found;
+            }
+            headers.push((Cow::Owned(name.to_string()), value.to_string()));
+        }
+    }
+
+    Ok(Response {
+        code,
+        headers,
+        body: ResponseBody::Stream(unfold((records, rest), read_body)),
+        chunk_size: None,
+    })
+}
+
+/// Read the next chunk of the response body, starting with the bytes read along with the head.
+async fn read_body<S: AsyncRead>(
+    (mut records, first): (Records<S>, Vec<u8>),
+) -> Option<(Vec<u8>, (Records<S>, Vec<u8>))> {
+    if !first.is_empty() {
+        return Some((first, (records, Vec::new())));
+    }
+    let chunk = records.stdout().await.ok()??;
+    Some((chunk, (records, Vec::new())))
+}
+
+/// Reads records from the `FastCGI` server.
+struct Records<S> {
+    stream: S,
+    /// Bytes read but not yet parsed.
+    buffer: Vec<u8>,
+}
+
+impl<S: AsyncRead> Records<S> {
+    /// Read the next record, returning its type and content, or `None` at the end of the stream.
+    async fn next(&mut self) -> IoResult<Option<(u8, Vec<u8>)>> {
+        loop {
+            if let [_, kind, _, _, len_high, len_low, padding, _, ..] = self.buffer[..] {
+                let len = usize::from(u16::from_be_bytes([len_high, len_low]));
+                let end = 8 + len + usize::from(padding);
+                if self.buffer.len() >= end {
+                    let content = self.buffer[8..8 + len].to_vec();
+                    self.buffer.drain(..end);
+                    return Ok(Some((kind, content)));
+                }
+            }
+            let BufResult(result, chunk) = self.stream.read(Vec::with_capacity(CHUNK_SIZE)).await;
+            if result? == 0 {
+                return Ok(None);
+            }
+            self.buffer.extend_from_slice(&chunk);
+        }
+    }
+
+    /// Read the next chunk of standard output, or `None` once the request has ended.
+    ///
+    /// Standard error output is printed to the console.
+    async fn stdout(&mut self) -> IoResult<Option<Vec<u8>>> {
+        loop {
+            match self.next().await? {
+                Some((record::STDOUT, content)) if !content.is_empty() => return Ok(Some(content)),
+                Some((record::STDERR, content)) => {
+                    eprint!("{}", String::from_utf8_lossy(&content));
+                }
+                Some((record::END_REQUEST, _)) | None => return Ok(None),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Encode `content` into records of the given type, or a single empty record if it is empty.
+fn encode_records(dest: &mut Vec<u8>, kind: u8, content: &[u8]) {
+    let mut chunks = content.chunks(MAX_CONTENT).peekable();
+    if chunks.peek().is_none() {
+        dest.extend_from_slice(&header(kind, 0));
+    }
+    for chunk in chunks {
+        dest.extend_from_slice(&header(kind, chunk.len()));
+        dest.extend_from_slice(chunk);
+    }
+}
+
+/// Encode a record header without padding.
+const fn header(kind: u8, len: usize) -> [u8; 8] {
+    let [id_high, id_low] = REQUEST_ID.to_be_bytes();
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "Records are at most 65535 bytes"
+    )]
+    let [len_high, len_low] = (len as u16).to_be_bytes();
+    [VERSION, kind, id_high, id_low, len_high, len_low, 0, 0]
+}
+
+/// Encode a name-value pair.
+fn encode_param(dest: &mut Vec<u8>, name: &str, value: &str) {
+    for len in [name.len(), value.len()] {
+        if len < 0x80 {
+            #[allow(clippy::cast_possible_truncation, reason = "Length is less than 0x80")]
+            dest.push(len as u8);
+        } else {
+            #[allow(clippy::cast_possible_truncation, reason = "Parameters are small")]
+            dest.extend_from_slice(&(len as u32 | 0x8000_0000).to_be_bytes());
+        }
+    }
+    dest.extend_from_slice(name.as_bytes());
+    dest.extend_from_slice(value.as_bytes());
+}
+
+/// Build an error for a malformed response of the `FastCGI` server.
+fn invalid(message: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("FastCGI: {message}"))
+}
@@ -0,0 +1,142 @@
+//! Client address resolution behind trusted reverse proxies.
+
+use super::Request;
+use std::{
+    fmt,
+    net::{AddrParseError, IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+/// A range of IP addresses in CIDR notation, like `10.0.0.0/8`, or a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    /// The network address.
+    addr: IpAddr,
+    /// Length of the network prefix, in bits.
+    prefix: u8,
+}
+
+/// Error parsing an [`IpRange`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseIpRangeError {
+    /// The address is invalid.
+    Addr(AddrParseError),
+    /// The prefix length is invalid.
+    Prefix,
+}
+
+impl fmt::Display for ParseIpRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Addr(e) => write!(f, "invalid address: {e}"),
+            Self::Prefix => write!(f, "invalid prefix length"),
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = ParseIpRangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(a, p)| (a, Some(p)));
+        let addr: IpAddr = addr.parse().map_err(ParseIpRangeError::Addr)?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            None => max,
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or(ParseIpRangeError::Prefix)?,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl IpRange {
+    /// Whether the range contains the given address. IPv4-mapped IPv6 addresses count as IPv4.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Shifting by the full width is an overflow, for a prefix length of zero
+        let prefix = u32::from(self.prefix);
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Get the address of the client, looking through `Forwarded` or `X-Forwarded-For` as long as
+/// the hops are trusted proxies.
+///
+/// Headers are ignored unless the peer itself is trusted, so that clients cannot spoof their
+/// address. The rightmost untrusted hop is used, since only trusted proxies are known to append
+/// honestly; if every hop is trusted, the leftmost one is used.
+#[must_use]
+pub fn client_ip(request: &Request<'_>, trusted: &[IpRange]) -> Option<IpAddr> {
+    let mut ip = request.peer?.ip().to_canonical();
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|range| range.contains(ip));
+    if !is_trusted(ip) {
+        return Some(ip);
+    }
+    let header = |name: &str| {
+        request
+            .headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .collect::<Vec<_>>()
+    };
+    // `Forwarded` takes precedence over the de-facto standard
+    let forwarded = header("Forwarded");
+    let hops: Vec<_> = if forwarded.is_empty() {
+        header("X-Forwarded-For")
+            .into_iter()
+            .map(|hop| parse_node(hop.trim()))
+            .collect()
+    } else {
+        forwarded.into_iter().map(forwarded_for).collect()
+    };
+    for hop in hops.into_iter().rev() {
+        let Some(hop) = hop else {
+            // Unknown or obfuscated, so the last trusted hop is the best we know
+            break;
+        };
+        ip = hop.to_canonical();
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    Some(ip)
+}
+
+/// Get the `for` parameter of a `Forwarded` element, like `for="[2001:db8::1]:4711";proto=http`.
+fn forwarded_for(element: &str) -> Option<IpAddr> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| parse_node(value.trim().trim_matches('"')))?
+    })
+}
+
+/// Parse a node like `192.0.2.1`, `192.0.2.1:80`, `2001:db8::1` or `[2001:db8::1]:80`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| node.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
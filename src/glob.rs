@@ -0,0 +1,78 @@
+//! Glob patterns for request paths.
+
+/// Match `text` against the glob `pattern`, where `*` matches any sequence of characters and `?`
+/// any single character. Returns the text matched by each `*`, in order.
+///
+/// A `*` matches as much as possible, so `/*/*` matches `/a/b/c` with captures `a/b` and `c`.
+#[must_use]
+pub fn captures<'t>(pattern: &str, text: &'t str) -> Option<Vec<&'t str>> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut captures = Vec::new();
+    matches(&pattern, text, &mut captures).then_some(captures)
+}
+
+/// Backtracking matcher, pushing captures as it goes.
+fn matches<'t>(pattern: &[char], text: &'t str, captures: &mut Vec<&'t str>) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => {
+            for end in (0..=text.len()).rev() {
+                if !text.is_char_boundary(end) {
+                    continue;
+                }
+                captures.push(&text[..end]);
+                if matches(rest, &text[end..], captures) {
+                    return true;
+                }
+                captures.pop();
+            }
+            false
+        }
+        Some(('?', rest)) => {
+            let mut chars = text.chars();
+            chars.next().is_some() && matches(rest, chars.as_str(), captures)
+        }
+        Some((&c, rest)) => text
+            .strip_prefix(c)
+            .is_some_and(|text| matches(rest, text, captures)),
+    }
+}
+
+/// Replace `$1`, `$2`, ... in `template` with the corresponding captures, and `$$` with `$`.
+///
+/// Braces delimit the index from following digits, as in `${1}0`.
+///
+/// References to missing captures are replaced with nothing.
+#[must_use]
+pub fn substitute(template: &str, captures: &[&str]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(position) = rest.find('$') {
+        result.push_str(&rest[..position]);
+        rest = &rest[position + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+        let (digits, skip) =
+            if let Some((digits, _)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+                (digits, digits.len() + 2)
+            } else {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                (&rest[..len], len)
+            };
+        match digits.parse::<usize>() {
+            Ok(index) => {
+                let capture = index.checked_sub(1).and_then(|i| captures.get(i));
+                result.push_str(capture.copied().unwrap_or_default());
+                rest = &rest[skip..];
+            }
+            Err(_) => result.push('$'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,1015 @@
+//! HTTP/2 support (RFC 9113).
+//!
+//! Only cleartext connections with prior knowledge (h2c) are supported, since nanoserve has no
+//! TLS yet. Streams are multiplexed: requests are handled concurrently as soon as they are
+//! complete, and the bodies of all open responses are interleaved frame by frame, subject to flow
+//! control.
+
+mod frame;
+mod hpack;
+mod huffman;
+
+use super::{
+    ErrorKind, HTTPServer, MAX_BODY, NanoserveError, ParseRequestError, Request, RequestHeaders,
+    Response, ServerConfig,
+    error::Context,
+    http1::MAX_HEAD,
+    logger::{self, Summary},
+    normalize_path, pool,
+    response::ResponseBody,
+    throttle::Throttled,
+};
+use compio::{
+    BufResult,
+    io::{AsyncRead, AsyncWriteExt},
+    net::{OwnedReadHalf, OwnedWriteHalf, TcpStream},
+    runtime::{JoinHandle, spawn},
+};
+use frame::{FrameHeader, HEADER_LEN, error, flag, kind, setting};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    future::poll_fn,
+    io::Result as IoResult,
+    net::SocketAddr,
+    rc::Rc,
+    task::{Poll, Waker},
+    time::Instant,
+};
+
+/// The client connection preface.
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Our `SETTINGS_MAX_FRAME_SIZE`, which is the protocol default.
+const MAX_FRAME_SIZE: usize = 16_384;
+/// Our `SETTINGS_HEADER_TABLE_SIZE`, which is the protocol default.
+const HEADER_TABLE_SIZE: usize = 4096;
+/// Our `SETTINGS_MAX_CONCURRENT_STREAMS`.
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+/// Initial flow-control window size.
+const DEFAULT_WINDOW: i64 = 65_535;
+/// Maximum flow-control window size.
+const MAX_WINDOW: i64 = (1 << 31) - 1;
+/// Maximum number of bytes of a streaming response body buffered before they can be sent.
+const MAX_BACKLOG: usize = 4 * MAX_FRAME_SIZE;
+/// Headers that are specific to HTTP/1.1 connections, and must not be sent over HTTP/2.
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Serves an HTTP/2 connection, whose preface has been received at the start of `buffer`.
+pub async fn serve(
+    stream: TcpStream,
+    mut buffer: Vec<u8>,
+    peer: SocketAddr,
+    config: Rc<ServerConfig>,
+) -> Result<(), NanoserveError> {
+    buffer.drain(..PREFACE.len());
+    let (reader, writer) = stream.into_split();
+    let events = Rc::new(Events::default());
+    let max_body = config.max_body.unwrap_or(MAX_BODY);
+    let reader_task = spawn(Reader::new(Rc::clone(&events), max_body).run(reader, buffer));
+    let connection = Connection {
+        writer: Throttled::new(writer, &config),
+        config,
+        peer,
+        events,
+        responding: HashMap::new(),
+        windows: HashMap::new(),
+        send_window: DEFAULT_WINDOW,
+        initial_window: DEFAULT_WINDOW,
+        max_frame_size: MAX_FRAME_SIZE,
+        last_stream_id: 0,
+        streams: VecDeque::new(),
+        draining: false,
+    };
+    let result = connection.run().await;
+    drop(reader_task);
+    result.context(ErrorKind::Write)
+}
+
+/// Events sent from the reading half of a connection to the writing half.
+enum Event {
+    /// A stream was opened by a `HEADERS` frame.
+    Opened(u32),
+    /// A complete request.
+    Request {
+        stream_id: u32,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        received: Instant,
+    },
+    /// The response to a request, with its encoded head.
+    Response {
+        stream_id: u32,
+        block: Vec<u8>,
+        body: ResponseBody,
+    },
+    /// The peer's settings.
+    Settings(Vec<(u16, u32)>),
+    /// A ping to be acknowledged.
+    Ping([u8; 8]),
+    /// A flow-control window update.
+    WindowUpdate { stream_id: u32, increment: u32 },
+    /// The peer cancelled a stream.
+    Reset(u32),
+    /// A stream to reset with `RST_STREAM` and the given error code.
+    Abort { stream_id: u32, code: u32 },
+    /// Request body bytes were consumed, and the windows should be replenished: the one of the
+    /// connection, and the one of the stream if it is still receiving its body.
+    Consumed { stream_id: Option<u32>, len: usize },
+    /// A chunk of a streaming response body, or `None` once it has ended.
+    Chunk {
+        stream_id: u32,
+        chunk: Option<Vec<u8>>,
+    },
+    /// The peer sent `GOAWAY`: finish the open streams, then close.
+    GoAway,
+    /// A connection error: send `GOAWAY` with the given error code and close.
+    Error(u32),
+    /// The peer closed the connection.
+    Closed,
+}
+
+/// Single-threaded event queue.
+#[derive(Default)]
+struct Events {
+    /// Pending events.
+    queue: RefCell<VecDeque<Event>>,
+    /// Waker of the task waiting for an event.
+    waker: Cell<Option<Waker>>,
+}
+
+impl Events {
+    /// Push an event, waking the waiting task.
+    fn push(&self, event: Event) {
+        self.queue.borrow_mut().push_back(event);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Pop an event, if any is pending.
+    fn try_next(&self) -> Option<Event> {
+        self.queue.borrow_mut().pop_front()
+    }
+
+    /// Wait for the next event.
+    async fn next(&self) -> Event {
+        poll_fn(|cx| {
+            self.try_next().map_or_else(
+                || {
+                    self.waker.set(Some(cx.waker().clone()));
+                    Poll::Pending
+                },
+                Poll::Ready,
+            )
+        })
+        .await
+    }
+}
+
+/// A request whose headers were received, waiting for the rest of its body.
+struct PendingRequest {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    received: Instant,
+}
+
+/// A header block split across `HEADERS` and `CONTINUATION` frames.
+struct PartialBlock {
+    stream_id: u32,
+    block: Vec<u8>,
+    end_stream: bool,
+}
+
+/// The reading half of a connection: parses frames into [`Event`]s.
+struct Reader {
+    events: Rc<Events>,
+    decoder: hpack::Decoder,
+    partial: Option<PartialBlock>,
+    pending: HashMap<u32, PendingRequest>,
+    last_stream_id: u32,
+    /// Maximum size of a buffered request body.
+    max_body: usize,
+    /// Whether the peer sent `GOAWAY`, after which new streams are refused.
+    going_away: bool,
+}
+
+impl Reader {
+    /// Create a reader sending events to the given queue, and resetting streams whose body is
+    /// longer than `max_body`.
+    fn new(events: Rc<Events>, max_body: usize) -> Self {
+        Self {
+            events,
+            decoder: hpack::Decoder::new(HEADER_TABLE_SIZE, MAX_HEAD),
+            partial: None,
+            pending: HashMap::new(),
+            last_stream_id: 0,
+            max_body,
+            going_away: false,
+        }
+    }
+
+    /// Read frames until the connection is closed or fails.
+    async fn run(mut self, mut reader: OwnedReadHalf<TcpStream>, mut buffer: Vec<u8>) {
+        let mut chunk = pool::take(HEADER_LEN + MAX_FRAME_SIZE);
+        let event = loop {
+            if let Err(code) = self.process(&mut buffer) {
+                break Event::Error(code);
+            }
+            let BufResult(result, read) = reader.read(chunk).await;
+            chunk = read;
+            match result {
+                Ok(0) | Err(_) => break Event::Closed,
+                Ok(_) => {
+                    buffer.extend_from_slice(&chunk);
+                    chunk.clear();
+                }
+            }
+        };
+        pool::give(chunk);
+        self.events.push(event);
+    }
+
+    /// Process all complete frames in `buffer`, removing them.
+    fn process(&mut self, buffer: &mut Vec<u8>) -> Result<(), u32> {
+        let mut consumed = 0;
+        while buffer.len() - consumed >= HEADER_LEN {
+            let header = FrameHeader::parse(&buffer[consumed..]);
+            if header.len > MAX_FRAME_SIZE {
+                return Err(error::FRAME_SIZE_ERROR);
+            }
+            let end = consumed + HEADER_LEN + header.len;
+            if buffer.len() < end {
+                break;
+            }
+            self.on_frame(header, &buffer[consumed + HEADER_LEN..end])?;
+            consumed = end;
+        }
+        buffer.drain(..consumed);
+        Ok(())
+    }
+
+    /// Handle a single frame.
+    fn on_frame(&mut self, header: FrameHeader, payload: &[u8]) -> Result<(), u32> {
+        if self.partial.is_some() && header.kind != kind::CONTINUATION {
+            return Err(error::PROTOCOL_ERROR);
+        }
+        match header.kind {
+            kind::DATA => self.on_data(header, payload),
+            kind::HEADERS => self.on_headers(header, payload),
+            kind::CONTINUATION => {
+                let Some(partial) = &mut self.partial else {
+                    return Err(error::PROTOCOL_ERROR);
+                };
+                if partial.stream_id != header.stream_id {
+                    return Err(error::PROTOCOL_ERROR);
+                }
+                // Header blocks are only decoded once complete, so they must not grow forever
+                if partial.block.len() + payload.len() > MAX_HEAD {
+                    return Err(error::ENHANCE_YOUR_CALM);
+                }
+                partial.block.extend_from_slice(payload);
+                if header.has(flag::END_HEADERS) {
+                    let partial = self.partial.take().ok_or(error::PROTOCOL_ERROR)?;
+                    self.on_header_block(partial.stream_id, &partial.block, partial.end_stream)?;
+                }
+                Ok(())
+            }
+            kind::SETTINGS => {
+                if header.stream_id != 0 {
+                    return Err(error::PROTOCOL_ERROR);
+                }
+                if header.has(flag::ACK) {
+                    return Ok(());
+                }
+                if !payload.len().is_multiple_of(6) {
+                    return Err(error::FRAME_SIZE_ERROR);
+                }
+                let settings = payload
+                    .chunks(6)
+                    .map(|c| {
+                        let id = u16::from_be_bytes([c[0], c[1]]);
+                        let value = u32::from_be_bytes([c[2], c[3], c[4], c[5]]);
+                        (id, value)
+                    })
+                    .collect();
+                self.events.push(Event::Settings(settings));
+                Ok(())
+            }
+            kind::PING => {
+                let data: [u8; 8] = payload.try_into().map_err(|_| error::FRAME_SIZE_ERROR)?;
+                if header.stream_id != 0 {
+                    return Err(error::PROTOCOL_ERROR);
+                }
+                if !header.has(flag::ACK) {
+                    self.events.push(Event::Ping(data));
+                }
+                Ok(())
+            }
+            kind::WINDOW_UPDATE => {
+                let data: [u8; 4] = payload.try_into().map_err(|_| error::FRAME_SIZE_ERROR)?;
+                let increment = u32::from_be_bytes(data) & 0x7fff_ffff;
+                if increment == 0 {
+                    return Err(error::PROTOCOL_ERROR);
+                }
+                self.events.push(Event::WindowUpdate {
+                    stream_id: header.stream_id,
+                    increment,
+                });
+                Ok(())
+            }
+            kind::RST_STREAM => {
+                if header.stream_id == 0 {
+                    return Err(error::PROTOCOL_ERROR);
+                }
+                self.pending.remove(&header.stream_id);
+                self.events.push(Event::Reset(header.stream_id));
+                Ok(())
+            }
+            kind::GOAWAY => {
+                if !self.going_away {
+                    self.going_away = true;
+                    self.events.push(Event::GoAway);
+                }
+                Ok(())
+            }
+            kind::PUSH_PROMISE => Err(error::PROTOCOL_ERROR),
+            // PRIORITY and unknown frame types are ignored
+            _ => Ok(()),
+        }
+    }
+
+    /// Handle a `DATA` frame.
+    fn on_data(&mut self, header: FrameHeader, payload: &[u8]) -> Result<(), u32> {
+        if header.stream_id == 0 {
+            return Err(error::PROTOCOL_ERROR);
+        }
+        let data = frame::unpad(&header, payload).ok_or(error::PROTOCOL_ERROR)?;
+        let receiving = self.on_body(header, data);
+        // Padding counts towards flow control as well
+        self.events.push(Event::Consumed {
+            stream_id: receiving.then_some(header.stream_id),
+            len: payload.len(),
+        });
+        Ok(())
+    }
+
+    /// Add the data of a `DATA` frame to the body of its request, returning whether the stream
+    /// is still receiving it.
+    fn on_body(&mut self, header: FrameHeader, data: &[u8]) -> bool {
+        let Some(pending) = self.pending.get_mut(&header.stream_id) else {
+            // The stream is closed or was reset, so the data is discarded
+            return false;
+        };
+        if pending.body.len() + data.len() > self.max_body {
+            // Answered like over HTTP/1.1, and the rest of the body is discarded
+            self.pending.remove(&header.stream_id);
+            let e = ParseRequestError::BodyTooLarge;
+            let response = Response::new(e.status(), e.description());
+            self.events.push(Event::Response {
+                stream_id: header.stream_id,
+                block: head_block(&response),
+                body: response.body,
+            });
+            return false;
+        }
+        pending.body.extend_from_slice(data);
+        if header.has(flag::END_STREAM) {
+            self.complete(header.stream_id);
+            return false;
+        }
+        true
+    }
+
+    /// Handle a `HEADERS` frame.
+    fn on_headers(&mut self, header: FrameHeader, payload: &[u8]) -> Result<(), u32> {
+        let mut block = frame::unpad(&header, payload).ok_or(error::PROTOCOL_ERROR)?;
+        if header.has(flag::PRIORITY) {
+            block = block.get(5..).ok_or(error::PROTOCOL_ERROR)?;
+        }
+        let end_stream = header.has(flag::END_STREAM);
+        if header.has(flag::END_HEADERS) {
+            self.on_header_block(header.stream_id, block, end_stream)
+        } else {
+            self.partial = Some(PartialBlock {
+                stream_id: header.stream_id,
+                block: block.to_vec(),
+                end_stream,
+            });
+            Ok(())
+        }
+    }
+
+    /// Handle a complete header block, which opens a stream or carries trailers.
+    fn on_header_block(
+        &mut self,
+        stream_id: u32,
+        block: &[u8],
+        end_stream: bool,
+    ) -> Result<(), u32> {
+        // Always decode, to keep the decoder state in sync, within the limit we advertised as
+        // `SETTINGS_MAX_HEADER_LIST_SIZE`
+        let headers = self
+            .decoder
+            .decode(block)
+            .map_err(|_| error::COMPRESSION_ERROR)?;
+        if self.pending.contains_key(&stream_id) {
+            // Trailers, which are ignored
+            if !end_stream {
+                return Err(error::PROTOCOL_ERROR);
+            }
+            self.complete(stream_id);
+            return Ok(());
+        }
+        if stream_id.is_multiple_of(2) || stream_id <= self.last_stream_id {
+            return Err(error::PROTOCOL_ERROR);
+        }
+        self.last_stream_id = stream_id;
+        if self.going_away || self.pending.len() >= MAX_CONCURRENT_STREAMS as usize {
+            // The peer is going away, or ignored our limit
+            self.events.push(Event::Abort {
+                stream_id,
+                code: error::REFUSED_STREAM,
+            });
+            return Ok(());
+        }
+        self.pending.insert(
+            stream_id,
+            PendingRequest {
+                headers,
+                body: Vec::new(),
+                received: Instant::now(),
+            },
+        );
+        self.events.push(Event::Opened(stream_id));
+        if end_stream {
+            self.complete(stream_id);
+        }
+        Ok(())
+    }
+
+    /// Emit the request on the given stream, which has been fully received.
+    fn complete(&mut self, stream_id: u32) {
+        if let Some(pending) = self.pending.remove(&stream_id) {
+            self.events.push(Event::Request {
+                stream_id,
+                headers: pending.headers,
+                body: pending.body,
+                received: pending.received,
+            });
+        }
+    }
+}
+
+/// A response body being sent on a stream.
+struct Outgoing {
+    stream_id: u32,
+    window: i64,
+    source: Source,
+}
+
+/// Where the data of an outgoing response body comes from.
+enum Source {
+    /// A body of known size, read on demand.
+    Ranged {
+        body: ResponseBody,
+        position: u64,
+        end: u64,
+    },
+    /// A streaming body, whose chunks are pushed as [`Event::Chunk`] by a task.
+    Streamed {
+        buffer: Vec<u8>,
+        finished: bool,
+        backlog: Rc<Backlog>,
+        _task: JoinHandle<()>,
+    },
+}
+
+/// Bytes of a streaming body that were produced but not sent yet, so that its producer waits
+/// for them to be sent rather than buffering a body the peer is not reading.
+#[derive(Default)]
+struct Backlog {
+    /// Number of bytes buffered.
+    len: Cell<usize>,
+    /// Waker of the producer waiting for room.
+    waker: Cell<Option<Waker>>,
+}
+
+impl Backlog {
+    /// Wait until fewer than [`MAX_BACKLOG`] bytes are buffered.
+    async fn room(&self) {
+        poll_fn(|cx| {
+            if self.len.get() < MAX_BACKLOG {
+                Poll::Ready(())
+            } else {
+                self.waker.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+
+    /// Record that `len` bytes were produced.
+    fn produced(&self, len: usize) {
+        self.len.set(self.len.get() + len);
+    }
+
+    /// Record that `len` bytes were sent, waking the producer.
+    fn sent(&self, len: usize) {
+        self.len.set(self.len.get().saturating_sub(len));
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Outgoing {
+    /// Whether a `DATA` frame can be sent right now, given the connection-level send window.
+    const fn ready(&self, send_window: i64) -> bool {
+        match &self.source {
+            // The final empty frame does not take up any window
+            Source::Streamed {
+                buffer, finished, ..
+            } if buffer.is_empty() => *finished,
+            _ => self.window > 0 && send_window > 0,
+        }
+    }
+}
+
+/// The writing half of a connection: handles requests and sends responses.
+struct Connection {
+    writer: Throttled<OwnedWriteHalf<TcpStream>>,
+    config: Rc<ServerConfig>,
+    /// The address of the client.
+    peer: SocketAddr,
+    events: Rc<Events>,
+    /// Tasks producing the responses to requests, by stream.
+    responding: HashMap<u32, JoinHandle<()>>,
+    /// Send windows of the open streams that are not in `streams` yet.
+    windows: HashMap<u32, i64>,
+    /// Connection-level send window.
+    send_window: i64,
+    /// The peer's `SETTINGS_INITIAL_WINDOW_SIZE`.
+    initial_window: i64,
+    /// The peer's `SETTINGS_MAX_FRAME_SIZE`.
+    max_frame_size: usize,
+    /// Identifier of the last stream that was handled.
+    last_stream_id: u32,
+    /// Streams with body data left to send, in round-robin order.
+    streams: VecDeque<Outgoing>,
+    /// Whether the peer sent `GOAWAY`, so that the connection closes once the open streams
+    /// are done.
+    draining: bool,
+}
+
+impl Connection {
+    /// Run the connection until it is closed.
+    async fn run(mut self) -> IoResult<()> {
+        let mut settings = Vec::with_capacity(18);
+        settings.extend_from_slice(&setting::MAX_CONCURRENT_STREAMS.to_be_bytes());
+        settings.extend_from_slice(&MAX_CONCURRENT_STREAMS.to_be_bytes());
+        settings.extend_from_slice(&setting::HEADER_TABLE_SIZE.to_be_bytes());
+        #[allow(clippy::cast_possible_truncation, reason = "Constant fits in u32")]
+        settings.extend_from_slice(&(HEADER_TABLE_SIZE as u32).to_be_bytes());
+        settings.extend_from_slice(&setting::MAX_HEADER_LIST_SIZE.to_be_bytes());
+        #[allow(clippy::cast_possible_truncation, reason = "Constant fits in u32")]
+        settings.extend_from_slice(&(MAX_HEAD as u32).to_be_bytes());
+        self.send(kind::SETTINGS, 0, 0, &settings).await?;
+
+        loop {
+            while let Some(event) = self.events.try_next() {
+                if !self.on_event(event).await? {
+                    return Ok(());
+                }
+            }
+            if self.draining
+                && self.windows.is_empty()
+                && self.responding.is_empty()
+                && self.streams.is_empty()
+            {
+                return self.go_away(error::NO_ERROR).await;
+            }
+            if !self.send_data().await? {
+                let event = self.events.next().await;
+                if !self.on_event(event).await? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Write a frame.
+    async fn send(&mut self, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> IoResult<()> {
+        let frame = frame::encode(kind, flags, stream_id, payload);
+        self.writer.write_all(frame).await.0
+    }
+
+    /// Write a header block in a `HEADERS` frame and as many `CONTINUATION` frames as needed.
+    async fn send_headers(
+        &mut self,
+        stream_id: u32,
+        block: &[u8],
+        end_stream: bool,
+    ) -> IoResult<()> {
+        let mut chunks = block.chunks(self.max_frame_size).peekable();
+        let mut frame_kind = kind::HEADERS;
+        let mut flags = if end_stream { flag::END_STREAM } else { 0 };
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_none() {
+                flags |= flag::END_HEADERS;
+            }
+            self.send(frame_kind, flags, stream_id, chunk).await?;
+            (frame_kind, flags) = (kind::CONTINUATION, 0);
+        }
+        Ok(())
+    }
+
+    /// Send `GOAWAY` with the given error code.
+    async fn go_away(&mut self, code: u32) -> IoResult<()> {
+        let mut payload = Vec::with_capacity(8);
+        payload.extend_from_slice(&self.last_stream_id.to_be_bytes());
+        payload.extend_from_slice(&code.to_be_bytes());
+        self.send(kind::GOAWAY, 0, 0, &payload).await
+    }
+
+    /// Handle an event, returning whether the connection should stay open.
+    async fn on_event(&mut self, event: Event) -> IoResult<bool> {
+        match event {
+            Event::Opened(stream_id) => {
+                self.windows.insert(stream_id, self.initial_window);
+            }
+            Event::Request {
+                stream_id,
+                headers,
+                body,
+                received,
+            } => {
+                self.last_stream_id = stream_id;
+                if self.responding.len() + self.streams.len() >= MAX_CONCURRENT_STREAMS as usize {
+                    self.windows.remove(&stream_id);
+                    let code = error::REFUSED_STREAM.to_be_bytes();
+                    self.send(kind::RST_STREAM, 0, stream_id, &code).await?;
+                } else {
+                    self.on_request(stream_id, headers, body, received);
+                }
+            }
+            Event::Response {
+                stream_id,
+                block,
+                body,
+            } => self.on_response(stream_id, &block, body).await?,
+            Event::Settings(settings) => return self.on_settings(settings).await,
+            Event::Ping(data) => self.send(kind::PING, flag::ACK, 0, &data).await?,
+            Event::WindowUpdate {
+                stream_id,
+                increment,
+            } => {
+                let window = if stream_id == 0 {
+                    Some(&mut self.send_window)
+                } else {
+                    self.streams
+                        .iter_mut()
+                        .find(|stream| stream.stream_id == stream_id)
+                        .map(|stream| &mut stream.window)
+                        .or_else(|| self.windows.get_mut(&stream_id))
+                };
+                if let Some(window) = window {
+                    *window += i64::from(increment);
+                    if *window > MAX_WINDOW {
+                        self.go_away(error::FLOW_CONTROL_ERROR).await?;
+                        return Ok(false);
+                    }
+                }
+            }
+            Event::Reset(stream_id) => {
+                // Dropping the task cancels the handling of the request
+                self.responding.remove(&stream_id);
+                self.windows.remove(&stream_id);
+                self.streams.retain(|stream| stream.stream_id != stream_id);
+            }
+            Event::Abort { stream_id, code } => {
+                self.responding.remove(&stream_id);
+                self.windows.remove(&stream_id);
+                self.send(kind::RST_STREAM, 0, stream_id, &code.to_be_bytes())
+                    .await?;
+            }
+            Event::Chunk { stream_id, chunk } => {
+                let source = self
+                    .streams
+                    .iter_mut()
+                    .find(|stream| stream.stream_id == stream_id)
+                    .map(|stream| &mut stream.source);
+                if let Some(Source::Streamed {
+                    buffer, finished, ..
+                }) = source
+                {
+                    match chunk {
+                        Some(chunk) => buffer.extend_from_slice(&chunk),
+                        None => *finished = true,
+                    }
+                }
+            }
+            Event::Consumed { stream_id, len } => {
+                if len > 0 {
+                    #[allow(clippy::cast_possible_truncation, reason = "Frames are small")]
+                    let increment = (len as u32).to_be_bytes();
+                    self.send(kind::WINDOW_UPDATE, 0, 0, &increment).await?;
+                    if let Some(stream_id) = stream_id {
+                        self.send(kind::WINDOW_UPDATE, 0, stream_id, &increment)
+                            .await?;
+                    }
+                }
+            }
+            Event::GoAway => self.draining = true,
+            Event::Error(code) => {
+                self.go_away(code).await?;
+                return Ok(false);
+            }
+            Event::Closed => {
+                self.go_away(error::NO_ERROR).await?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Apply the peer's settings, returning whether the connection should stay open.
+    async fn on_settings(&mut self, settings: Vec<(u16, u32)>) -> IoResult<bool> {
+        for (id, value) in settings {
+            match id {
+                setting::INITIAL_WINDOW_SIZE => {
+                    let value = i64::from(value);
+                    if value > MAX_WINDOW {
+                        self.go_away(error::FLOW_CONTROL_ERROR).await?;
+                        return Ok(false);
+                    }
+                    let delta = value - self.initial_window;
+                    self.initial_window = value;
+                    let windows = self.streams.iter_mut().map(|stream| &mut stream.window);
+                    for window in windows.chain(self.windows.values_mut()) {
+                        *window += delta;
+                    }
+                }
+                setting::MAX_FRAME_SIZE => {
+                    if !(16_384..=16_777_215).contains(&value) {
+                        self.go_away(error::PROTOCOL_ERROR).await?;
+                        return Ok(false);
+                    }
+                    self.max_frame_size = value as usize;
+                }
+                _ => {}
+            }
+        }
+        self.send(kind::SETTINGS, flag::ACK, 0, &[]).await?;
+        Ok(true)
+    }
+
+    /// Handle a complete request on a task, which pushes its response as [`Event::Response`],
+    /// so that slow requests do not hold up the other streams.
+    fn on_request(
+        &mut self,
+        stream_id: u32,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        received: Instant,
+    ) {
+        let config = Rc::clone(&self.config);
+        let events = Rc::clone(&self.events);
+        let peer = self.peer;
+        let task = spawn(async move {
+            let event = respond(stream_id, &headers, &body, received, peer, &config).await;
+            events.push(event);
+        });
+        self.responding.insert(stream_id, task);
+    }
+
+    /// Send the head of a response and queue its body.
+    async fn on_response(
+        &mut self,
+        stream_id: u32,
+        block: &[u8],
+        body: ResponseBody,
+    ) -> IoResult<()> {
+        self.responding.remove(&stream_id);
+        let window = self
+            .windows
+            .remove(&stream_id)
+            .unwrap_or(self.initial_window);
+        let empty = body.is_empty();
+        self.send_headers(stream_id, block, empty).await?;
+
+        // Response body, sent by `send_data`
+        let source = match body {
+            _ if empty => return Ok(()),
+            ResponseBody::Stream(mut stream) => {
+                let events = Rc::clone(&self.events);
+                let backlog = Rc::new(Backlog::default());
+                let producer = Rc::clone(&backlog);
+                let task = spawn(async move {
+                    loop {
+                        producer.room().await;
+                        let Some(chunk) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await else {
+                            break;
+                        };
+                        producer.produced(chunk.len());
+                        events.push(Event::Chunk {
+                            stream_id,
+                            chunk: Some(chunk),
+                        });
+                    }
+                    events.push(Event::Chunk {
+                        stream_id,
+                        chunk: None,
+                    });
+                });
+                Source::Streamed {
+                    buffer: Vec::new(),
+                    finished: false,
+                    backlog,
+                    _task: task,
+                }
+            }
+            body => {
+                let Some((position, end)) = body.range() else {
+                    return Ok(());
+                };
+                Source::Ranged {
+                    body,
+                    position,
+                    end,
+                }
+            }
+        };
+        self.streams.push_back(Outgoing {
+            stream_id,
+            window,
+            source,
+        });
+        Ok(())
+    }
+
+    /// Send one `DATA` frame on the next stream that may send, returning whether one was sent.
+    async fn send_data(&mut self) -> IoResult<bool> {
+        let send_window = self.send_window;
+        let Some(index) = self
+            .streams
+            .iter()
+            .position(|stream| stream.ready(send_window))
+        else {
+            return Ok(false);
+        };
+        let Some(mut stream) = self.streams.remove(index) else {
+            return Ok(false);
+        };
+        #[allow(clippy::cast_sign_loss, reason = "Windows are non-negative here")]
+        let limit = (self.max_frame_size as u64)
+            .min(stream.window.max(0) as u64)
+            .min(self.send_window.max(0) as u64);
+        let (chunk, finished) = match &mut stream.source {
+            Source::Ranged {
+                body,
+                position,
+                end,
+            } => {
+                #[allow(clippy::cast_possible_truncation, reason = "Bounded by the frame size")]
+                let len = (*end - *position).min(limit) as usize;
+                let chunk = body.read_at(*position, len).await?;
+                if chunk.is_empty() {
+                    // The file shrank, so the response cannot be completed
+                    let code = error::PROTOCOL_ERROR.to_be_bytes();
+                    self.send(kind::RST_STREAM, 0, stream.stream_id, &code)
+                        .await?;
+                    return Ok(true);
+                }
+                *position += chunk.len() as u64;
+                (chunk, *position >= *end)
+            }
+            Source::Streamed {
+                buffer,
+                finished,
+                backlog,
+                ..
+            } => {
+                #[allow(clippy::cast_possible_truncation, reason = "Bounded by the frame size")]
+                let len = buffer.len().min(limit as usize);
+                let chunk: Vec<u8> = buffer.drain(..len).collect();
+                backlog.sent(len);
+                (chunk, *finished && buffer.is_empty())
+            }
+        };
+        #[allow(clippy::cast_possible_wrap, reason = "Bounded by the frame size")]
+        let sent = chunk.len() as i64;
+        stream.window -= sent;
+        self.send_window -= sent;
+        let flags = if finished { flag::END_STREAM } else { 0 };
+        self.send(kind::DATA, flags, stream.stream_id, &chunk)
+            .await?;
+        if !finished {
+            self.streams.push_back(stream);
+        }
+        Ok(true)
+    }
+}
+
+/// Produce the response to a complete request, as the [`Event`] sending it.
+async fn respond(
+    stream_id: u32,
+    headers: &[(String, String)],
+    body: &[u8],
+    received: Instant,
+    peer: SocketAddr,
+    config: &ServerConfig,
+) -> Event {
+    let pseudo = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let (Some(method), Some(path)) = (pseudo(":method"), pseudo(":path")) else {
+        // Malformed request
+        return Event::Abort {
+            stream_id,
+            code: error::PROTOCOL_ERROR,
+        };
+    };
+    let request_headers = request_headers(headers);
+    let path = normalize_path(path, config.fold_case);
+    let request = Request {
+        method,
+        path: &path,
+        version: "2",
+        headers: request_headers,
+        body,
+        peer: Some(peer),
+    };
+    let response = HTTPServer::respond(&request, config).await;
+    #[cfg(feature = "webhook")]
+    for webhook in &config.webhooks {
+        webhook.responded(&request, response.code);
+    }
+    let client = config.client_ip(&request);
+    let (code, size) = (response.code, response.body.size());
+    let block = head_block(&response);
+    let summary = Summary {
+        method,
+        path: &path,
+        headers: &request.headers,
+        status: code,
+        size,
+        sent: None,
+        elapsed: received.elapsed(),
+        client,
+    };
+    logger::report(&summary, config);
+    Event::Response {
+        stream_id,
+        block,
+        body: response.body,
+    }
+}
+
+/// Encode the head of a response into a header block.
+fn head_block(response: &Response) -> Vec<u8> {
+    let mut block = Vec::new();
+    hpack::encode(&mut block, ":status", &response.code.as_u16().to_string());
+    if let Some(size) = response
+        .body
+        .size()
+        .filter(|_| response.code.allows_content())
+    {
+        hpack::encode(&mut block, "content-length", &size.to_string());
+    }
+    for (name, value) in response.head().fields() {
+        let name = name.to_ascii_lowercase();
+        if !CONNECTION_HEADERS.contains(&name.as_str()) {
+            hpack::encode(&mut block, &name, value);
+        }
+    }
+    block
+}
+
+/// Get the headers of a request from its decoded fields, with `:authority` replacing any `Host`
+/// header so that requests have a single host.
+fn request_headers(fields: &[(String, String)]) -> RequestHeaders<'_> {
+    let authority = fields
+        .iter()
+        .find(|(key, _)| key == ":authority")
+        .map(|(_, value)| value.as_str());
+    authority
+        .map(|authority| ("Host", authority))
+        .into_iter()
+        .chain(
+            fields
+                .iter()
+                .filter(|(key, _)| !key.starts_with(':') && (authority.is_none() || key != "host"))
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+        .collect()
+}
@@ -0,0 +1,105 @@
+//! HTTP/2 frame layout (RFC 9113, Section 4).
+
+/// Length of the frame header.
+pub const HEADER_LEN: usize = 9;
+
+/// Frame types.
+pub mod kind {
+    pub const DATA: u8 = 0x0;
+    pub const HEADERS: u8 = 0x1;
+    pub const RST_STREAM: u8 = 0x3;
+    pub const SETTINGS: u8 = 0x4;
+    pub const PUSH_PROMISE: u8 = 0x5;
+    pub const PING: u8 = 0x6;
+    pub const GOAWAY: u8 = 0x7;
+    pub const WINDOW_UPDATE: u8 = 0x8;
+    pub const CONTINUATION: u8 = 0x9;
+}
+
+/// Frame flags.
+pub mod flag {
+    pub const END_STREAM: u8 = 0x1;
+    pub const ACK: u8 = 0x1;
+    pub const END_HEADERS: u8 = 0x4;
+    pub const PADDED: u8 = 0x8;
+    pub const PRIORITY: u8 = 0x20;
+}
+
+/// Settings identifiers.
+pub mod setting {
+    pub const HEADER_TABLE_SIZE: u16 = 0x1;
+    pub const MAX_CONCURRENT_STREAMS: u16 = 0x3;
+    pub const INITIAL_WINDOW_SIZE: u16 = 0x4;
+    pub const MAX_FRAME_SIZE: u16 = 0x5;
+    pub const MAX_HEADER_LIST_SIZE: u16 = 0x6;
+}
+
+/// Error codes.
+pub mod error {
+    pub const NO_ERROR: u32 = 0x0;
+    pub const PROTOCOL_ERROR: u32 = 0x1;
+    pub const FLOW_CONTROL_ERROR: u32 = 0x3;
+    pub const FRAME_SIZE_ERROR: u32 = 0x6;
+    pub const REFUSED_STREAM: u32 = 0x7;
+    pub const COMPRESSION_ERROR: u32 = 0x9;
+    pub const ENHANCE_YOUR_CALM: u32 = 0xb;
+}
+
+/// A frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Length of the payload.
+    pub len: usize,
+    /// Frame type.
+    pub kind: u8,
+    /// Frame flags.
+    pub flags: u8,
+    /// Stream identifier.
+    pub stream_id: u32,
+}
+
+impl FrameHeader {
+    /// Parse a frame header from the first [`HEADER_LEN`] bytes.
+    pub fn parse(bytes: &[u8]) -> Self {
+        let len = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize;
+        let stream_id = u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) & 0x7fff_ffff;
+        Self {
+            len,
+            kind: bytes[3],
+            flags: bytes[4],
+            stream_id,
+        }
+    }
+
+    /// Whether the given flag is set.
+    pub const fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// Serialize a complete frame.
+pub fn encode(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "Payloads are bounded by the frame size"
+    )]
+    let len = payload.len() as u32;
+    frame.extend_from_slice(&len.to_be_bytes()[1..]);
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend_from_slice(&stream_id.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Strip padding from the payload of a `DATA` or `HEADERS` frame.
+pub fn unpad<'a>(header: &FrameHeader, payload: &'a [u8]) -> Option<&'a [u8]> {
+    if !header.has(flag::PADDED) {
+        return Some(payload);
+    }
+    let (&pad, rest) = payload.split_first()?;
+    rest.len()
+        .checked_sub(usize::from(pad))
+        .map(|len| &rest[..len])
+}
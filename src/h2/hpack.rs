@@ -0,0 +1,268 @@
+//! HPACK header compression (RFC 7541).
+//!
+//! The decoder is complete. The encoder never indexes and never uses Huffman coding, which is
+//! always valid and keeps it stateless.
+
+use super::huffman;
+use std::collections::VecDeque;
+
+/// The static table (RFC 7541, Appendix A).
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Overhead of each dynamic table entry, in bytes.
+const ENTRY_OVERHEAD: usize = 32;
+
+/// The decompression error, to be reported as a `COMPRESSION_ERROR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HpackError;
+
+/// Stateful HPACK decoder.
+#[derive(Debug)]
+pub struct Decoder {
+    /// The dynamic table, newest entry first.
+    dynamic: VecDeque<(String, String)>,
+    /// Current size of the dynamic table.
+    size: usize,
+    /// Maximum size of the dynamic table, as set by the peer.
+    max_size: usize,
+    /// Upper bound for `max_size`, as advertised by us.
+    limit: usize,
+    /// Maximum decoded size of a header block, counted like dynamic table entries.
+    max_list_size: usize,
+}
+
+impl Decoder {
+    /// Create a decoder whose dynamic table is bounded by `limit` bytes, and whose decoded
+    /// header blocks are bounded by `max_list_size` bytes.
+    pub const fn new(limit: usize, max_list_size: usize) -> Self {
+        Self {
+            dynamic: VecDeque::new(),
+            size: 0,
+            max_size: limit,
+            limit,
+            max_list_size,
+        }
+    }
+
+    /// Decode a complete header block.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`HpackError`] if the block is malformed, or decodes to more than the maximum
+    /// size, since small indexed references can expand to large headers.
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut headers = Vec::new();
+        let mut list_size = 0;
+        while let Some(&first) = block.first() {
+            let header = if first & 0x80 != 0 {
+                // Indexed header field
+                let index = decode_integer(&mut block, 7)?;
+                self.get(index)?
+            } else if first & 0x40 != 0 {
+                // Literal header field with incremental indexing
+                let header = self.decode_literal(&mut block, 6)?;
+                self.insert(header.clone());
+                header
+            } else if first & 0x20 != 0 {
+                // Dynamic table size update
+                let size = decode_integer(&mut block, 5)?;
+                if size > self.limit {
+                    return Err(HpackError);
+                }
+                self.max_size = size;
+                self.evict();
+                continue;
+            } else {
+                // Literal header field without indexing, or never indexed
+                self.decode_literal(&mut block, 4)?
+            };
+            list_size += header.0.len() + header.1.len() + ENTRY_OVERHEAD;
+            if list_size > self.max_list_size {
+                return Err(HpackError);
+            }
+            headers.push(header);
+        }
+        Ok(headers)
+    }
+
+    /// Get the entry at the given index, in the combined static and dynamic index space.
+    fn get(&self, index: usize) -> Result<(String, String), HpackError> {
+        let (name, value) = match index {
+            0 => return Err(HpackError),
+            1..=61 => STATIC_TABLE[index - 1],
+            _ => {
+                let (name, value) = self.dynamic.get(index - 62).ok_or(HpackError)?;
+                (name.as_str(), value.as_str())
+            }
+        };
+        Ok((name.to_string(), value.to_string()))
+    }
+
+    /// Decode a literal header field, whose name index has the given prefix length.
+    fn decode_literal(
+        &self,
+        block: &mut &[u8],
+        prefix: u8,
+    ) -> Result<(String, String), HpackError> {
+        let index = decode_integer(block, prefix)?;
+        let name = if index == 0 {
+            decode_string(block)?
+        } else {
+            self.get(index)?.0
+        };
+        let value = decode_string(block)?;
+        Ok((name, value))
+    }
+
+    /// Insert an entry into the dynamic table.
+    fn insert(&mut self, header: (String, String)) {
+        self.size += header.0.len() + header.1.len() + ENTRY_OVERHEAD;
+        self.dynamic.push_front(header);
+        self.evict();
+    }
+
+    /// Evict entries until the dynamic table fits into its maximum size.
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((name, value)) = self.dynamic.pop_back() else {
+                break;
+            };
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+/// Decode an integer with the given prefix length, advancing `block`.
+fn decode_integer(block: &mut &[u8], prefix: u8) -> Result<usize, HpackError> {
+    let (&first, rest) = block.split_first().ok_or(HpackError)?;
+    *block = rest;
+    let mask = (1u8 << prefix) - 1;
+    let mut value = usize::from(first & mask);
+    if value < usize::from(mask) {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().ok_or(HpackError)?;
+        *block = rest;
+        if shift > 28 {
+            return Err(HpackError);
+        }
+        value += usize::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+/// Decode a string literal, advancing `block`.
+fn decode_string(block: &mut &[u8]) -> Result<String, HpackError> {
+    let huffman = block.first().ok_or(HpackError)? & 0x80 != 0;
+    let len = decode_integer(block, 7)?;
+    if block.len() < len {
+        return Err(HpackError);
+    }
+    let (raw, rest) = block.split_at(len);
+    *block = rest;
+    let bytes = if huffman {
+        huffman::decode(raw).ok_or(HpackError)?
+    } else {
+        raw.to_vec()
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Encode an integer with the given prefix length and high bits into `dest`.
+fn encode_integer(dest: &mut Vec<u8>, value: usize, prefix: u8, high: u8) {
+    let mask = (1u8 << prefix) - 1;
+    if value < usize::from(mask) {
+        #[allow(clippy::cast_possible_truncation, reason = "Value fits in the prefix")]
+        dest.push(high | value as u8);
+        return;
+    }
+    dest.push(high | mask);
+    let mut value = value - usize::from(mask);
+    while value >= 0x80 {
+        #[allow(clippy::cast_possible_truncation, reason = "Intended truncation")]
+        dest.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    #[allow(clippy::cast_possible_truncation, reason = "Value is less than 0x80")]
+    dest.push(value as u8);
+}
+
+/// Encode a header field as a literal without indexing into `dest`. The name must be lowercase.
+pub fn encode(dest: &mut Vec<u8>, name: &str, value: &str) {
+    if let Some(index) = STATIC_TABLE.iter().position(|(n, _)| *n == name) {
+        encode_integer(dest, index + 1, 4, 0);
+    } else {
+        dest.push(0);
+        encode_integer(dest, name.len(), 7, 0);
+        dest.extend_from_slice(name.as_bytes());
+    }
+    encode_integer(dest, value.len(), 7, 0);
+    dest.extend_from_slice(value.as_bytes());
+}
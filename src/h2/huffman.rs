@@ -0,0 +1,81 @@
+//! HPACK Huffman decoding (RFC 7541, Appendix B).
+
+use std::sync::OnceLock;
+
+/// Code lengths of symbols 0..=256 (256 being EOS). The code is canonical, so lengths suffice.
+const LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+
+/// Canonical decoding tables.
+struct Tables {
+    /// Symbols, sorted by code.
+    symbols: Vec<u16>,
+    /// For each length: first code, and index of its symbol in `symbols`.
+    first: [(u32, usize); 31],
+    /// Number of codes of each length.
+    count: [u32; 31],
+}
+
+/// Build decoding tables from [`LENGTHS`].
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut symbols: Vec<u16> = (0..=256).collect();
+        symbols.sort_by_key(|&symbol| LENGTHS[symbol as usize]);
+        let mut count = [0; 31];
+        for &len in &LENGTHS {
+            count[len as usize] += 1;
+        }
+        let mut first = [(0, 0); 31];
+        let (mut code, mut index) = (0, 0);
+        for len in 1..31 {
+            first[len] = (code, index);
+            code = (code + count[len]) << 1;
+            index += count[len] as usize;
+        }
+        Tables {
+            symbols,
+            first,
+            count,
+        }
+    })
+}
+
+/// Decode a Huffman-encoded string, returning `None` if it is malformed.
+pub fn decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let tables = tables();
+    let mut decoded = Vec::with_capacity(encoded.len() * 8 / 5);
+    let (mut code, mut len) = (0u32, 0usize);
+    for byte in encoded {
+        for shift in (0..8).rev() {
+            code = (code << 1) | u32::from((byte >> shift) & 1);
+            len += 1;
+            if len > 30 {
+                return None;
+            }
+            let (first, index) = tables.first[len];
+            if code.wrapping_sub(first) < tables.count[len] {
+                let symbol = tables.symbols[index + (code - first) as usize];
+                // EOS must not appear in the string
+                let symbol = u8::try_from(symbol).ok()?;
+                decoded.push(symbol);
+                (code, len) = (0, 0);
+            }
+        }
+    }
+    // Padding must be shorter than 8 bits, and consist of the most significant bits of EOS (all ones)
+    if len > 7 || code != (1 << len) - 1 {
+        return None;
+    }
+    Some(decoded)
+}
@@ -0,0 +1,40 @@
+//! Custom request handlers.
+
+use super::{Request, Response};
+use std::{fmt, future::Future, pin::Pin, rc::Rc};
+
+/// Future returned by [`Handler::handle`].
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Option<Response>> + 'a>>;
+
+/// A custom request handler, mounted at a path prefix with
+/// [`HTTPServerBuilder::handler`](super::HTTPServerBuilder::handler).
+pub trait Handler {
+    /// Handles a request, or returns `None` to fall through to the next handler and file serving.
+    fn handle<'a>(&'a self, request: &'a Request<'_>) -> HandlerFuture<'a>;
+}
+
+/// A [`Handler`] mounted at a path prefix.
+#[derive(Clone)]
+pub struct Route {
+    /// The path prefix, without trailing slash.
+    pub prefix: String,
+    /// The handler.
+    pub handler: Rc<dyn Handler>,
+}
+
+impl Route {
+    /// Whether the route matches the given request path, on a segment boundary.
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,438 @@
+//! Sans-I/O HTTP/1.1: parsing of request heads, framing and decoding of request bodies, and
+//! serialization of response heads.
+//!
+//! Nothing here reads or writes, so it can be reused with any runtime, or fuzzed, on buffers of
+//! bytes. The server appends what it reads to a buffer and feeds it to a [`Parser`] until the
+//! request is complete, which finds the head with [`head_len`], parses it with [`parse_head`],
+//! then waits until the body [`Framing`] is satisfied, decoding chunked bodies in place with
+//! [`decode_chunked`].
+//!
+//! Heads themselves are parsed by an [`HttpParser`] backend, which is [`BuiltinParser`] unless
+//! another one is given to [`Parser::with_backend`], so that parsers can be swapped to trade
+//! strictness for speed, or compared against each other on the same inputs.
+
+use super::{MAX_HEADERS, ParseRequestError, Request, ResponseHead};
+use std::{io::Write, ops::Range};
+
+/// Maximum size of a request head.
+pub const MAX_HEAD: usize = 64 * 1024;
+
+/// How the body of a request is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Without `Transfer-Encoding`, the body is what arrived along with the head.
+    Unframed,
+    /// With `Transfer-Encoding: chunked`, the body is a series of chunks ended by an empty one.
+    Chunked,
+    /// With `Content-Length`, the body has the given length.
+    Length(usize),
+}
+
+impl Framing {
+    /// Get the framing of the body of a request.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRequestError::UnsupportedTransferEncoding`] for transfer codings other
+    /// than `chunked`, and [`ParseRequestError::InvalidContentLength`] for invalid or differing
+    /// lengths, or a length along with a transfer coding, which proxies may read differently.
+    pub fn of(request: &Request<'_>) -> Result<Self, ParseRequestError> {
+        let mut length = None;
+        for value in request
+            .headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Content-Length"))
+            .flat_map(|(_, value)| value.split(','))
+        {
+            let value = value.trim();
+            let value = value
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| value.parse::<usize>().ok())
+                .flatten()
+                .filter(|&value| length.is_none_or(|length| length == value))
+                .ok_or(ParseRequestError::InvalidContentLength)?;
+            length = Some(value);
+        }
+        let mut codings = request
+            .headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Transfer-Encoding"))
+            .flat_map(|(_, value)| value.split(','))
+            .map(str::trim)
+            .filter(|coding| !coding.is_empty());
+        match (codings.next(), codings.next(), length) {
+            (None, _, None) => Ok(Self::Unframed),
+            (None, _, Some(length)) => Ok(Self::Length(length)),
+            (Some(_), _, Some(_)) => Err(ParseRequestError::InvalidContentLength),
+            (Some(coding), None, None) if coding.eq_ignore_ascii_case("chunked") => {
+                Ok(Self::Chunked)
+            }
+            _ => Err(ParseRequestError::UnsupportedTransferEncoding),
+        }
+    }
+}
+
+/// How lenient parsing of request heads is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Maximum number of headers.
+    pub max_headers: usize,
+    /// Whether to reject bare CR or LF line endings. See [`Request::check_line_endings`].
+    pub strict_crlf: bool,
+    /// Whether to accept header values continued on several lines. See [`Request::unfold`].
+    pub unfold_headers: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_headers: MAX_HEADERS,
+            strict_crlf: false,
+            unfold_headers: false,
+        }
+    }
+}
+
+/// Progress of a [`Parser`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The request is incomplete, and more bytes are needed.
+    Partial,
+    /// The request is complete, and takes the given number of bytes at the start of the buffer,
+    /// after which the next request starts.
+    Complete(usize),
+}
+
+/// State of a [`Parser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Looking for the end of the head, which is not before `scanned`.
+    Head { scanned: usize },
+    /// Waiting for the body with the given framing, after the head of the given length.
+    Body { head: usize, framing: Framing },
+    /// The request is complete, with its body at `body` once decoded.
+    Done {
+        head: usize,
+        body: Range<usize>,
+        consumed: usize,
+    },
+}
+
+/// Incremental parser of a request, fed a buffer of the bytes received so far each time more
+/// arrive, until it is complete.
+///
+/// The head is scanned once, and is at most [`MAX_HEAD`] bytes long, while bodies are at most
+/// `max_body` bytes long as sent. Chunked bodies are decoded in place, right after the head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parser<P = BuiltinParser> {
+    /// Parser of the head.
+    backend: P,
+    /// How lenient parsing of the head is.
+    options: ParseOptions,
+    /// Maximum size of a body, as sent.
+    max_body: usize,
+    /// Progress so far.
+    state: State,
+}
+
+impl Parser {
+    /// Creates a parser for a request whose body is at most `max_body` bytes long as sent.
+    #[must_use]
+    pub const fn new(options: ParseOptions, max_body: usize) -> Self {
+        Self::with_backend(BuiltinParser, options, max_body)
+    }
+}
+
+impl<P: HttpParser> Parser<P> {
+    /// Creates a parser for a request whose body is at most `max_body` bytes long as sent, and
+    /// whose head is parsed by `backend`.
+    #[must_use]
+    pub const fn with_backend(backend: P, options: ParseOptions, max_body: usize) -> Self {
+        Self {
+            backend,
+            options,
+            max_body,
+            state: State::Head { scanned: 0 },
+        }
+    }
+
+    /// Parses as much of the request at the start of `buffer` as possible, which holds all the
+    /// bytes received so far, including those seen by previous calls.
+    ///
+    /// # Errors
+    ///
+    /// See [`ParseRequestError`]. Heads and bodies longer than allowed are errors as soon as
+    /// they exceed their limit, without waiting for the rest.
+    pub fn parse(&mut self, buffer: &mut [u8]) -> Result<Status, ParseRequestError> {
+        if let State::Head { scanned } = self.state {
+            // The empty line ending the head may have started in the previous bytes
+            let start = scanned.saturating_sub(2);
+            let Some(len) = head_len(&buffer[start..]) else {
+                if buffer.len() >= MAX_HEAD {
+                    return Err(ParseRequestError::HeadTooLarge);
+                }
+                self.state = State::Head {
+                    scanned: buffer.len(),
+                };
+                return Ok(Status::Partial);
+            };
+            self.on_head(buffer, start + len)?;
+        }
+        self.on_body(buffer)
+    }
+
+    /// Parses the request at the start of `buffer` once no more bytes will arrive, like when
+    /// the client closed the connection. A head cut short is parsed as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRequestError::IncompleteBody`] if the body is cut short, or any other
+    /// [`ParseRequestError`] like [`Parser::parse`].
+    pub fn finish(&mut self, buffer: &mut [u8]) -> Result<usize, ParseRequestError> {
+        if let Status::Complete(consumed) = self.parse(buffer)? {
+            return Ok(consumed);
+        }
+        if matches!(self.state, State::Head { .. }) {
+            self.on_head(buffer, buffer.len())?;
+        }
+        match self.on_body(buffer)? {
+            Status::Complete(consumed) => Ok(consumed),
+            Status::Partial => Err(ParseRequestError::IncompleteBody),
+        }
+    }
+
+    /// Get the request parsed from `buffer`, which must be the buffer given to the parser.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRequestError::IncompleteBody`] if the request is not complete yet.
+    pub fn request<'b>(&self, buffer: &'b [u8]) -> Result<Request<'b>, ParseRequestError> {
+        let State::Done { head, ref body, .. } = self.state else {
+            return Err(ParseRequestError::IncompleteBody);
+        };
+        Ok(Request {
+            body: &buffer[body.clone()],
+            ..self
+                .backend
+                .parse(&buffer[..head], self.options.max_headers)?
+        })
+    }
+
+    /// Parse the head of the given length at the start of `buffer`, and wait for the body.
+    fn on_head(&mut self, buffer: &mut [u8], head: usize) -> Result<(), ParseRequestError> {
+        let (_, framing) = parse_head(&self.backend, &mut buffer[..head], self.options)?;
+        if let Framing::Length(len) = framing
+            && len > self.max_body
+        {
+            return Err(ParseRequestError::BodyTooLarge);
+        }
+        self.state = State::Body { head, framing };
+        Ok(())
+    }
+
+    /// Check whether the body is complete, decoding it in place if chunked.
+    fn on_body(&mut self, buffer: &mut [u8]) -> Result<Status, ParseRequestError> {
+        let (head, body, consumed) = match self.state {
+            State::Head { .. } => return Ok(Status::Partial),
+            State::Done { consumed, .. } => return Ok(Status::Complete(consumed)),
+            State::Body {
+                head,
+                framing: Framing::Unframed,
+            } => (head, head..buffer.len(), buffer.len()),
+            State::Body {
+                head,
+                framing: Framing::Length(len),
+            } => {
+                if buffer.len() - head < len {
+                    return Ok(Status::Partial);
+                }
+                (head, head..head + len, head + len)
+            }
+            State::Body {
+                head,
+                framing: Framing::Chunked,
+            } => {
+                let Some((len, sent)) = decode_chunked(&mut buffer[head..])? else {
+                    if buffer.len() - head > self.max_body {
+                        return Err(ParseRequestError::BodyTooLarge);
+                    }
+                    return Ok(Status::Partial);
+                };
+                if sent > self.max_body {
+                    return Err(ParseRequestError::BodyTooLarge);
+                }
+                (head, head..head + len, head + sent)
+            }
+        };
+        self.state = State::Done {
+            head,
+            body,
+            consumed,
+        };
+        Ok(Status::Complete(consumed))
+    }
+}
+
+/// A parser of request heads, used by [`Parser`] once a head is complete.
+pub trait HttpParser {
+    /// Parses the complete head of a request, with at most `max_headers` headers, into a
+    /// request with an empty body.
+    ///
+    /// Bare LF line endings and folded header values are dealt with beforehand, as configured
+    /// by [`ParseOptions`], so parsers may accept or reject them as they see fit.
+    ///
+    /// # Errors
+    ///
+    /// See [`ParseRequestError`].
+    fn parse<'b>(
+        &self,
+        head: &'b [u8],
+        max_headers: usize,
+    ) -> Result<Request<'b>, ParseRequestError>;
+}
+
+/// The built-in parser of request heads, [`Request::parse_limited`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BuiltinParser;
+
+impl HttpParser for BuiltinParser {
+    fn parse<'b>(
+        &self,
+        head: &'b [u8],
+        max_headers: usize,
+    ) -> Result<Request<'b>, ParseRequestError> {
+        Request::parse_limited(head, max_headers)
+    }
+}
+
+/// Get the length of the head at the start of `data`, up to and including the empty line ending
+/// it, or `None` if it is incomplete. Lines may end with CRLF or a bare LF.
+#[must_use]
+pub fn head_len(data: &[u8]) -> Option<usize> {
+    (0..data.len()).find_map(|pos| match &data[pos..] {
+        [b'\n', b'\n', ..] => Some(pos + 2),
+        [b'\n', b'\r', b'\n', ..] => Some(pos + 3),
+        _ => None,
+    })
+}
+
+/// Parses the head of a request at the start of `buffer` with `backend`, returning the request,
+/// whose body is what follows the head in `buffer`, and how its whole body is delimited.
+///
+/// Folded header values are unfolded in place first if allowed by `options`.
+///
+/// # Errors
+///
+/// See [`ParseRequestError`].
+pub fn parse_head<'b>(
+    backend: &impl HttpParser,
+    buffer: &'b mut [u8],
+    options: ParseOptions,
+) -> Result<(Request<'b>, Framing), ParseRequestError> {
+    if options.strict_crlf {
+        Request::check_line_endings(buffer)?;
+    }
+    if options.unfold_headers {
+        Request::unfold(buffer);
+    }
+    let len = head_len(buffer).unwrap_or(buffer.len());
+    let buffer = &*buffer;
+    let request = Request {
+        body: &buffer[len..],
+        ..backend.parse(&buffer[..len], options.max_headers)?
+    };
+    let framing = Framing::of(&request)?;
+    Ok((request, framing))
+}
+
+/// Decodes a complete chunked body at the start of `data` in place, ignoring chunk extensions
+/// and trailers, and returns its decoded length and its length as sent, or `None` if it is
+/// incomplete.
+///
+/// # Errors
+///
+/// Returns [`ParseRequestError::InvalidChunk`] if the body is malformed.
+pub fn decode_chunked(data: &mut [u8]) -> Result<Option<(usize, usize)>, ParseRequestError> {
+    let Some((ranges, sent)) = chunks(data)? else {
+        return Ok(None);
+    };
+    let mut len = 0;
+    for range in ranges {
+        let range_len = range.len();
+        data.copy_within(range, len);
+        len += range_len;
+    }
+    Ok(Some((len, sent)))
+}
+
+/// Appends the start line and headers of a response in HTTP/1.1 format to `buffer`, ending with
+/// the empty line before the body.
+///
+/// The connection is closed after the body, which marks its end, but the size is still
+/// announced so that responses to `HEAD` describe the content.
+pub fn write_head(head: &ResponseHead<'_>, buffer: &mut Vec<u8>) {
+    let _ = write!(buffer, "HTTP/1.1 {}\r\n", head.code);
+    if let Some(size) = head.size.filter(|_| head.code.allows_content()) {
+        let _ = write!(buffer, "Content-Length: {size}\r\n");
+    }
+    for (name, value) in head.fields() {
+        buffer.extend_from_slice(name.as_bytes());
+        buffer.extend_from_slice(b": ");
+        buffer.extend_from_slice(value.as_bytes());
+        buffer.extend_from_slice(b"\r\n");
+    }
+    buffer.extend_from_slice(b"\r\n");
+}
+
+/// Where the data of the chunks of a chunked body is, and where the body ends.
+type Chunks = (Vec<Range<usize>>, usize);
+
+/// Find the data of the chunks of a chunked body and where it ends, or `None` if it is
+/// incomplete.
+fn chunks(data: &[u8]) -> Result<Option<Chunks>, ParseRequestError> {
+    let mut ranges = Vec::new();
+    let mut position = 0;
+    loop {
+        let Some((line, next)) = line_at(data, position) else {
+            return Ok(None);
+        };
+        // Chunk extensions after `;` are ignored
+        let size = line.split(|&b| b == b';').next().unwrap_or_default();
+        let size = size.trim_ascii();
+        let size = std::str::from_utf8(size)
+            .ok()
+            .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|size| usize::from_str_radix(size, 16).ok())
+            .ok_or(ParseRequestError::InvalidChunk)?;
+        if size == 0 {
+            // Trailers are ignored, up to the empty line ending the body
+            let mut position = next;
+            while let Some((line, next)) = line_at(data, position) {
+                if line.is_empty() {
+                    return Ok(Some((ranges, next)));
+                }
+                position = next;
+            }
+            return Ok(None);
+        }
+        let end = next
+            .checked_add(size)
+            .ok_or(ParseRequestError::InvalidChunk)?;
+        position = match data.get(end..) {
+            Some([b'\r', b'\n', ..]) => end + 2,
+            Some([b'\n', ..]) => end + 1,
+            None | Some([] | [b'\r']) => return Ok(None),
+            Some(_) => return Err(ParseRequestError::InvalidChunk),
+        };
+        ranges.push(next..end);
+    }
+}
+
+/// Get the line starting at `start` without its line ending, and the start of the next one, or
+/// `None` if it is incomplete.
+fn line_at(data: &[u8], start: usize) -> Option<(&[u8], usize)> {
+    let len = data.get(start..)?.iter().position(|&b| b == b'\n')?;
+    let line = &data[start..start + len];
+    Some((line.strip_suffix(b"\r").unwrap_or(line), start + len + 1))
+}
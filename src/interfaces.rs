@@ -0,0 +1,86 @@
+//! Enumeration of local network interfaces.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// An address assigned to a local network interface.
+pub struct InterfaceAddr {
+    /// Name of the interface.
+    pub name: String,
+    /// The address.
+    pub ip: IpAddr,
+}
+
+/// Get the URLs at which a server bound to `addr` is reachable.
+///
+/// For unspecified addresses, this lists every matching interface address.
+pub fn reachable_urls(addr: SocketAddr) -> Vec<String> {
+    let port = addr.port();
+    if !addr.ip().is_unspecified() {
+        return vec![format!("http://{addr}")];
+    }
+    let mut urls: Vec<_> = interface_addrs()
+        .into_iter()
+        .filter(|iface| addr.is_ipv6() || iface.ip.is_ipv4())
+        .map(|iface| match iface.ip {
+            IpAddr::V4(ip) => format!("http://{ip}:{port}"),
+            IpAddr::V6(ip) if ip.is_unicast_link_local() => {
+                format!("http://[{ip}%25{}]:{port}", iface.name)
+            }
+            IpAddr::V6(ip) => format!("http://[{ip}]:{port}"),
+        })
+        .collect();
+    if urls.is_empty() {
+        urls.push(format!("http://{addr}"));
+    }
+    urls
+}
+
+/// List addresses of all local network interfaces that are up.
+#[cfg(unix)]
+pub fn interface_addrs() -> Vec<InterfaceAddr> {
+    use std::ffi::CStr;
+
+    let mut addrs = Vec::new();
+    let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: `head` is a valid out-pointer, and the list is freed below
+    if unsafe { libc::getifaddrs(&raw mut head) } != 0 {
+        return addrs;
+    }
+    let mut current = head;
+    while !current.is_null() {
+        // SAFETY: `current` is a non-null node of the list returned by `getifaddrs`
+        let ifa = unsafe { &*current };
+        current = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() || ifa.ifa_flags & libc::IFF_UP as libc::c_uint == 0 {
+            continue;
+        }
+        // SAFETY: `ifa_addr` is non-null and points to a socket address whose layout is given by its family
+        let ip = unsafe {
+            match i32::from((*ifa.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let sin = ifa.ifa_addr.cast::<libc::sockaddr_in>().read_unaligned();
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)))
+                }
+                libc::AF_INET6 => {
+                    let sin6 = ifa.ifa_addr.cast::<libc::sockaddr_in6>().read_unaligned();
+                    IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr))
+                }
+                _ => continue,
+            }
+        };
+        // SAFETY: `ifa_name` is a valid NUL-terminated string
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        addrs.push(InterfaceAddr { name, ip });
+    }
+    // SAFETY: `head` was returned by `getifaddrs` and is freed exactly once
+    unsafe { libc::freeifaddrs(head) };
+    addrs
+}
+
+/// List addresses of all local network interfaces that are up.
+#[cfg(not(unix))]
+pub fn interface_addrs() -> Vec<InterfaceAddr> {
+    Vec::new()
+}
@@ -9,31 +9,130 @@
     clippy::future_not_send, // compio is single-threaded by design
 )]
 
+#[cfg(feature = "archive")]
+mod archive;
+mod auth;
+pub mod blocking;
+mod body;
+mod build_info;
+#[cfg(feature = "proxy")]
+mod cache;
+mod config;
+mod cookie;
+mod cors;
+mod driver;
+mod embedded;
 mod error;
+#[cfg(feature = "fastcgi")]
+mod fastcgi;
+mod forwarded;
+mod glob;
+#[cfg(feature = "h2")]
+mod h2;
+mod handler;
+pub mod http1;
+mod logger;
+mod memory;
+mod mime;
+pub mod multipart;
+mod normalize;
+mod pool;
+#[cfg(feature = "proxy")]
+mod proxy;
 mod request;
 mod response;
+mod rewrite;
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+mod sendfile;
+mod sniff;
+pub mod sse;
+mod storage;
+mod throttle;
+mod timing;
+mod urlencoded;
+mod vhost;
+#[cfg(feature = "webhook")]
+mod webhook;
 
+#[cfg(feature = "archive")]
+pub use archive::Archive;
+pub use auth::{AuthError, BasicAuth};
+pub use body::{MAX_BODY, READ_TIMEOUT};
+pub use build_info::{BuildInfo, build_info};
+#[cfg(feature = "proxy")]
+pub use cache::CachingProxy;
 use compio::{
-    io::AsyncRead,
+    BufResult,
+    io::{AsyncRead, AsyncWriteExt},
     net::{TcpListener, TcpStream},
     runtime::spawn,
+    time::timeout,
 };
-pub use error::NanoserveError;
-pub use request::{ParseRequestError, RangeHeader, Request};
-pub use response::Response;
-use std::{io::Error as IoError, net::SocketAddr};
+pub use config::{HTTPServerBuilder, ServerConfig};
+pub use cookie::{Cookie, SameSite};
+pub use cors::Cors;
+pub use driver::{Driver, RuntimeOptions, current_driver};
+pub use embedded::EmbeddedFiles;
+use error::Context;
+pub use error::{ErrorKind, NanoserveError};
+#[cfg(feature = "fastcgi")]
+pub use fastcgi::FastCgi;
+pub use forwarded::{IpRange, ParseIpRangeError};
+pub use handler::{Handler, HandlerFuture, Route};
+use http1::{ParseOptions, Parser};
+pub use logger::{ResponseHook, Summary};
+pub use mime::MediaType;
+pub use normalize::{decode_path, normalize_path};
+#[cfg(feature = "proxy")]
+pub use proxy::Proxy;
+pub use request::{MAX_HEADERS, ParseRequestError, RangeHeader, Request, RequestHeaders};
+pub use response::{BodyStream, ChunkSize, Response, ResponseBody, ResponseCode, ResponseHead};
+pub use rewrite::{RedirectsFiles, RewriteAction, RewriteRule};
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, RawSocket};
+use std::{
+    any::Any,
+    cell::Cell,
+    future::{Future, poll_fn},
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe, resume_unwind},
+    pin::{Pin, pin},
+    rc::Rc,
+    task::Poll,
+    time::Instant,
+};
+pub use storage::{
+    DirEntry, FileCache, FileChanged, LocalFs, Metadata, MetadataCache, Storage, StorageFuture,
+};
+pub use throttle::GlobalThrottle;
+use timing::{Phase, Timer};
+pub use timing::{PhaseTimings, Timings, timings};
+pub use vhost::VirtualHost;
+#[cfg(feature = "webhook")]
+pub use webhook::{Webhook, WebhookEvent};
 
 /// A HTTP/1.1 server.
 ///
 /// # Usage
 ///
 /// - [`new`](Self::new): Creates a new HTTP server that listens on the given address.
+/// - [`builder`](Self::builder): Creates a [`HTTPServerBuilder`] for further configuration.
 /// - [`run`](Self::run): Runs the server, accepting and handling connections.
 /// - [`local_addr`](Self::local_addr): Gets the local address of the server.
+/// - [`connections`](Self::connections): Gets the number of connections being handled.
 #[derive(Debug, Clone)]
 pub struct HTTPServer {
     /// The TCP listener.
     listener: TcpListener,
+    /// The server configuration.
+    config: Rc<ServerConfig>,
+    /// Number of connections being handled, shared by clones.
+    connections: Rc<Cell<usize>>,
+    /// Number of tasks accepting connections concurrently.
+    acceptors: usize,
 }
 
 impl HTTPServer {
@@ -41,47 +140,272 @@ impl HTTPServer {
     ///
     /// # Errors
     ///
-    /// Returns an [`IoError`] if the server fails to bind to the address.
-    pub async fn new(addr: SocketAddr) -> Result<Self, IoError> {
-        let listener = TcpListener::bind(addr).await?;
-        Ok(Self { listener })
+    /// Returns a [`NanoserveError`] if the server fails to bind to the address.
+    pub async fn new(addr: SocketAddr) -> Result<Self, NanoserveError> {
+        Self::builder(addr).build().await
+    }
+
+    /// Creates a [`HTTPServerBuilder`] for a server that listens on the given address.
+    #[must_use]
+    pub fn builder(addr: SocketAddr) -> HTTPServerBuilder {
+        HTTPServerBuilder::new(addr)
     }
 
     /// Runs the server.
     ///
     /// # Errors
     ///
-    /// Returns an [`IoError`] if the server fails to start.
-    pub async fn run(&self) -> Result<(), IoError> {
+    /// Returns a [`NanoserveError`] if the server fails to start or to accept connections.
+    pub async fn run(&self) -> Result<(), NanoserveError> {
+        #[cfg(feature = "webhook")]
+        {
+            let local_addr = self.listener.local_addr().context(ErrorKind::Bind)?;
+            for webhook in &self.config.webhooks {
+                webhook.started(local_addr);
+            }
+        }
+        // Cancelled along with this future when dropped
+        let mut acceptors: Vec<_> = (0..self.acceptors.max(1))
+            .map(|_| spawn(self.clone().accept()))
+            .collect();
+        poll_fn(|cx| {
+            acceptors
+                .iter_mut()
+                .find_map(|task| match Pin::new(task).poll(cx) {
+                    Poll::Ready(result) => Some(result),
+                    Poll::Pending => None,
+                })
+                .map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+        .unwrap_or_else(|panic| resume_unwind(panic))
+    }
+
+    /// Accepts connections and spawns tasks handling them, until accepting fails.
+    async fn accept(self) -> Result<(), NanoserveError> {
         loop {
-            let (stream, addr) = self.listener.accept().await?;
-            println!("Accepted connection from {addr}");
+            let (stream, addr) = self.listener.accept().await.context(ErrorKind::Accept)?;
+            if self.config.nodelay
+                && let Err(e) = stream.set_nodelay(true)
+            {
+                eprintln!("Failed to set TCP_NODELAY for {addr}: {e}");
+            }
+            let config = Rc::clone(&self.config);
+            let connections = Rc::clone(&self.connections);
+            let overloaded = config
+                .max_connections
+                .is_some_and(|max| connections.get() >= max);
+            connections.set(connections.get() + 1);
             let task = spawn(async move {
-                Self::handle_connection(stream).await.unwrap_or_else(|e| {
-                    eprintln!("Error while handling connection from {addr}: {e}");
-                });
+                // Panics after the head was sent, like in body streams, can only end the
+                // connection, which is still counted as done
+                let handled = Self::handle_connection(stream, addr, &config, overloaded);
+                match catch_unwind(Box::pin(handled)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => eprintln!("Error while handling connection from {addr}: {e}"),
+                    Err(panic) => eprintln!(
+                        "Panic while handling connection from {addr}: {}",
+                        panic_message(&*panic)
+                    ),
+                }
+                connections.set(connections.get() - 1);
             });
             task.detach();
         }
     }
 
-    /// Handles a single connection.
-    async fn handle_connection(mut stream: TcpStream) -> Result<(), NanoserveError> {
-        let result = stream.read([0; 4096]).await;
-        let (size, buffer) = (result.0?, result.1);
-        let response = match Request::parse(&buffer[..size]) {
-            Err(e) => Response::bad_request(e.description()),
-            Ok(request) => {
-                println!("Received request:\n{request}");
-                Response::handle(&request).await
+    /// Handles a single connection, answering its request with 503 if the server is
+    /// `overloaded`.
+    async fn handle_connection(
+        mut stream: TcpStream,
+        peer: SocketAddr,
+        config: &Rc<ServerConfig>,
+        overloaded: bool,
+    ) -> Result<(), NanoserveError> {
+        let start = Instant::now();
+        let mut timer = Timer::start(config.timings);
+        let read_timeout = config.read_timeout.unwrap_or(READ_TIMEOUT);
+        let deadline = start + read_timeout;
+        let read = stream.read(pool::take(4096));
+        // Connections idle until the deadline are closed without a response
+        let Ok(BufResult(result, mut buffer)) = timeout(read_timeout, read).await else {
+            return Ok(());
+        };
+        result.context(ErrorKind::Read)?;
+        #[cfg(feature = "h2")]
+        if buffer.starts_with(h2::PREFACE) {
+            return h2::serve(stream, buffer, peer, Rc::clone(config)).await;
+        }
+        // Browsers trying https:// on this port would otherwise get a confusing error
+        if sniff::is_tls_client_hello(&buffer) {
+            eprintln!("TLS handshake from {peer} rejected: this port serves plain http://");
+            stream
+                .write_all(sniff::TLS_ALERT)
+                .await
+                .0
+                .context(ErrorKind::Write)?;
+            stream.close().await.context(ErrorKind::Write)?;
+            return Ok(());
+        }
+        let options = ParseOptions {
+            max_headers: config.max_headers.unwrap_or(MAX_HEADERS),
+            strict_crlf: config.strict_crlf,
+            unfold_headers: config.unfold_headers,
+        };
+        let mut parser = Parser::new(options, config.max_body.unwrap_or(MAX_BODY));
+        // The first read may only hold the start of the request
+        let read = body::read(&mut stream, &mut buffer, &mut parser, deadline)
+            .await
+            .context(ErrorKind::Read)?;
+        let parsed = read.and_then(|_| parser.request(&buffer));
+        let normalized = parsed
+            .as_ref()
+            .ok()
+            .map(|request| normalize_path(request.path, config.fold_case));
+        let request = parsed.map(|request| Request {
+            path: normalized.as_deref().unwrap_or(request.path),
+            peer: Some(peer),
+            ..request
+        });
+        timer.lap(Phase::Parse);
+        let response = Self::answer(&request, config, overloaded).await;
+        let (code, size) = (response.code, response.body.size());
+        timer.lap(Phase::FirstByte);
+        let (result, sent) = response.send(&mut stream, config).await;
+        // Clients aborting downloads are logged, while other failures are errors
+        let aborted = match result {
+            Ok(()) => {
+                stream.close().await.context(ErrorKind::Write)?;
+                None
             }
+            Err(e) if is_disconnect(&e) => Some(sent),
+            Err(e) => return Err(NanoserveError::io(ErrorKind::Write, e)),
+        };
+        timer.lap(Phase::Transfer);
+        if aborted.is_some() {
+            timer.abort();
+        }
+
+        let client = request
+            .as_ref()
+            .map_or_else(|_| Some(peer.ip()), |request| config.client_ip(request));
+        let (method, path) = request
+            .as_ref()
+            .map_or(("-", "-"), |request| (request.method, request.path));
+        let summary = Summary {
+            method,
+            path,
+            headers: request.as_ref().map_or(&[], |request| &request.headers),
+            status: code,
+            size,
+            sent: aborted,
+            elapsed: start.elapsed(),
+            client,
         };
-        response.write_to(&mut stream).await?;
-        stream.close().await?;
+        logger::report(&summary, config);
+        timer.finish(method, path);
+        pool::give(buffer);
 
         Ok(())
     }
 
+    /// Produces the response to a request read from a connection, even if malformed, or to be
+    /// shed as the server is `overloaded`.
+    async fn answer(
+        request: &Result<Request<'_>, ParseRequestError>,
+        config: &ServerConfig,
+        overloaded: bool,
+    ) -> Response {
+        match request {
+            // Where the next request would start is unknowable after a malformed one
+            Err(e) => Response::new(e.status(), e.description()).with_header("Connection", "close"),
+            // The request is read first, as closing with it unread would reset the connection
+            Ok(_) if overloaded => {
+                let retry_after = config.retry_after.as_millis().div_ceil(1000);
+                Response::new(ResponseCode::ServiceUnavailable, "503 Service Unavailable")
+                    .with_header("Retry-After", retry_after.to_string())
+                    .with_header("Connection", "close")
+            }
+            Ok(request) => {
+                let response = Self::respond(request, config).await;
+                #[cfg(feature = "webhook")]
+                for webhook in &config.webhooks {
+                    webhook.responded(request, response.code);
+                }
+                response
+            }
+        }
+    }
+
+    /// Produces the response to a well-formed request.
+    async fn respond(request: &Request<'_>, config: &ServerConfig) -> Response {
+        let mut response = match request.path.strip_prefix(&config.base_path) {
+            Some(path) if config.base_path.is_empty() || path.starts_with('/') => {
+                let request = Request {
+                    path,
+                    ..request.clone()
+                };
+                // Panics answer 500 rather than ending the connection task silently
+                catch_unwind(Self::rewrite(&request, config))
+                    .await
+                    .unwrap_or_else(|panic| {
+                        let message = panic_message(&*panic);
+                        eprintln!("Panic while handling {}: {message}", request.path);
+                        Response::internal_server_error()
+                    })
+            }
+            // The base path itself, which needs a trailing slash for relative links to work
+            Some(rest) if rest.is_empty() || rest.starts_with('?') => {
+                Response::new(ResponseCode::MovedPermanently, "301 Moved Permanently")
+                    .with_header("Location", config.link(&format!("/{rest}")))
+            }
+            _ => Response::not_found(),
+        };
+        if let Some(cors) = &config.cors {
+            cors.apply(&mut response);
+        }
+        // Responses to `HEAD` have the head of the response to `GET`, errors included
+        if request.method == "HEAD"
+            && let Some(size) = response.body.size()
+        {
+            response.body = ResponseBody::Omitted { size };
+        }
+        response
+    }
+
+    /// Applies the first matching rewrite rule, and handles the request.
+    async fn rewrite(request: &Request<'_>, config: &ServerConfig) -> Response {
+        let root = config.root(request);
+        let exists = |path: &str| root.join(path.trim_start_matches('/')).is_file();
+        let file_rules = config.redirects_files.rules(root);
+        let rules = config.rewrites.iter().chain(file_rules.iter());
+        let Some((rule, target)) = rewrite::find(rules, request.path, exists) else {
+            return Response::handle(request, config).await;
+        };
+        match rule.action {
+            RewriteAction::Redirect => {
+                let location = if target.starts_with('/') {
+                    config.link(&target)
+                } else {
+                    target
+                };
+                Response::redirect(rule.code, location)
+            }
+            RewriteAction::Rewrite => {
+                let target = normalize_path(&target, config.fold_case);
+                let request = Request {
+                    path: &target,
+                    ..request.clone()
+                };
+                let mut response = Response::handle(&request, config).await;
+                if response.code == ResponseCode::Ok {
+                    response.code = rule.code;
+                }
+                response
+            }
+        }
+    }
+
     /// Get the local address of the server.
     ///
     /// # Errors
@@ -90,4 +414,57 @@ impl HTTPServer {
     pub fn local_addr(&self) -> Result<SocketAddr, IoError> {
         self.listener.local_addr()
     }
+
+    /// Gets the number of connections being handled, for example to wait for them to finish
+    /// after the server has stopped accepting new ones.
+    #[must_use]
+    pub fn connections(&self) -> usize {
+        self.connections.get()
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for HTTPServer {
+    /// Gets the file descriptor of the listening socket, for example to pass it to a new process.
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for HTTPServer {
+    /// Gets the handle of the listening socket.
+    fn as_raw_socket(&self) -> RawSocket {
+        self.listener.as_raw_socket()
+    }
+}
+
+/// Polls a future, returning the payload of its panic if it panics.
+async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, Box<dyn Any + Send>> {
+    let mut future = pin!(future);
+    poll_fn(
+        |cx| match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        },
+    )
+    .await
+}
+
+/// Get the message of a panic, from its payload.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Check whether an error writing a response means the client disconnected.
+fn is_disconnect(e: &IoError) -> bool {
+    matches!(
+        e.kind(),
+        IoErrorKind::BrokenPipe | IoErrorKind::ConnectionReset | IoErrorKind::ConnectionAborted
+    )
 }
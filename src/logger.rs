@@ -0,0 +1,156 @@
+//! Console logging of handled requests.
+
+use super::{ServerConfig, response::ResponseCode};
+use std::{
+    fmt,
+    io::{IsTerminal, stdout},
+    net::IpAddr,
+    rc::Rc,
+    sync::OnceLock,
+    time::Duration,
+};
+
+/// Headers carrying credentials, whose values are redacted from summaries passed to hooks.
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+/// Value replacing those of headers carrying credentials.
+const REDACTED: &str = "[redacted]";
+
+/// Whether to colorize console output, decided once by whether stdout is a TTY.
+fn use_color() -> bool {
+    static USE_COLOR: OnceLock<bool> = OnceLock::new();
+    *USE_COLOR.get_or_init(|| stdout().is_terminal())
+}
+
+/// Summary of a handled request and its response, as logged and passed to
+/// [`HTTPServerBuilder::on_response`](crate::HTTPServerBuilder::on_response).
+///
+/// With the `serde` feature, it can be serialized, with `elapsed` in seconds and `headers` as
+/// name and value pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary<'a> {
+    /// The request method, or `-` for malformed requests.
+    pub method: &'a str,
+    /// The request path, or `-` for malformed requests.
+    pub path: &'a str,
+    /// The request headers.
+    pub headers: &'a [(&'a str, &'a str)],
+    /// The response code.
+    pub status: ResponseCode,
+    /// The size of the response body, or `None` if unknown in advance.
+    pub size: Option<u64>,
+    /// The bytes sent before the client disconnected, or `None` if it did not.
+    pub sent: Option<u64>,
+    /// Time from receiving the request to sending the response.
+    pub elapsed: Duration,
+    /// The address of the client, if known.
+    pub client: Option<IpAddr>,
+}
+
+/// A callback passed the [`Summary`] of each handled request, set with
+/// [`HTTPServerBuilder::on_response`](crate::HTTPServerBuilder::on_response).
+#[derive(Clone)]
+pub struct ResponseHook(Rc<dyn Fn(&Summary<'_>)>);
+
+impl ResponseHook {
+    /// Creates a hook calling `hook`.
+    pub fn new(hook: impl Fn(&Summary<'_>) + 'static) -> Self {
+        Self(Rc::new(hook))
+    }
+}
+
+impl fmt::Debug for ResponseHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseHook").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde_core::Serialize for Summary<'_> {
+    fn serialize<S: serde_core::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde_core::ser::SerializeStruct;
+
+        let mut summary = serializer.serialize_struct("Summary", 8)?;
+        summary.serialize_field("method", self.method)?;
+        summary.serialize_field("path", self.path)?;
+        summary.serialize_field("headers", self.headers)?;
+        summary.serialize_field("status", &self.status.as_u16())?;
+        summary.serialize_field("size", &self.size)?;
+        summary.serialize_field("sent", &self.sent)?;
+        summary.serialize_field("elapsed", &self.elapsed.as_secs_f64())?;
+        summary.serialize_field("client", &self.client)?;
+        summary.end()
+    }
+}
+
+/// Report a handled request: log it, and pass it to the hook of the server, if any, with the
+/// values of headers carrying credentials redacted.
+pub fn report(summary: &Summary<'_>, config: &ServerConfig) {
+    log_request(summary);
+    if let Some(hook) = &config.on_response {
+        let headers: Vec<_> = summary
+            .headers
+            .iter()
+            .map(|&(name, value)| {
+                let credential = CREDENTIAL_HEADERS
+                    .iter()
+                    .any(|credential| name.eq_ignore_ascii_case(credential));
+                (name, if credential { REDACTED } else { value })
+            })
+            .collect();
+        (hook.0)(&Summary {
+            headers: &headers,
+            ..summary.clone()
+        });
+    }
+}
+
+/// Log a handled request as one aligned line: status, method, path, size, duration and client.
+///
+/// Transfers the client aborted are logged with the bytes sent before, instead of the size.
+pub fn log_request(summary: &Summary<'_>) {
+    let status = summary.status.as_u16();
+    let (method, path) = (summary.method, summary.path);
+    let size = summary
+        .sent
+        .or(summary.size)
+        .map_or_else(|| "-".to_string(), human_size);
+    let elapsed = format!("{:.1?}", summary.elapsed);
+    let mut client = summary
+        .client
+        .map_or_else(|| "-".to_string(), |client| client.to_string());
+    if summary.sent.is_some() {
+        client.push_str(" (aborted)");
+    }
+    if use_color() {
+        let color = match status {
+            200..=299 => "32", // Green
+            300..=399 => "36", // Cyan
+            400..=499 => "33", // Yellow
+            _ => "31",         // Red
+        };
+        println!(
+            "\x1b[1;{color}m{status}\x1b[0m {method:<7} {path:<40} \x1b[2m{size:>10} {elapsed:>9} {client}\x1b[0m"
+        );
+    } else {
+        println!("{status} {method:<7} {path:<40} {size:>10} {elapsed:>9} {client}");
+    }
+}
+
+/// Format a byte count with binary units.
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    #[allow(clippy::cast_precision_loss, reason = "Only used for display")]
+    let mut value = size as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{value:.1} {unit}")
+}
@@ -1,23 +1,116 @@
 #![warn(clippy::all, clippy::nursery, clippy::pedantic, clippy::cargo)]
+#![allow(
+    clippy::multiple_crate_versions, // dependency issues
+    clippy::future_not_send, // compio is single-threaded by design
+)]
 
+mod bench;
 mod cli;
+mod completions;
+mod interfaces;
+#[cfg(unix)]
+mod mdns;
+mod portmap;
+#[cfg(unix)]
+mod restart;
 
-use cli::Cli;
+use argh::ArgsInfo;
+use cli::{Cli, Command};
 use compio::{runtime::spawn, signal::ctrl_c};
-use nanoserve::HTTPServer;
-use std::net::SocketAddr;
+use nanoserve::{Archive, CachingProxy, HTTPServer, HTTPServerBuilder, RuntimeOptions};
+use std::{net::SocketAddr, time::Duration};
 
-#[compio::main]
-async fn main() {
+fn main() {
     let cli: Cli = argh::from_env();
+    if cli.version {
+        println!("nanoserve {}", nanoserve::build_info());
+        return;
+    }
+    if let Some(Command::Completions(completions)) = &cli.command {
+        print!("{}", completions.shell.generate(&Cli::get_args_info()));
+        return;
+    }
+    let mut options = RuntimeOptions::new().driver(cli.driver);
+    if let Some(idle) = cli.sqpoll {
+        options = options.sqpoll(Duration::from_millis(idle));
+    }
+    if let Some(entries) = cli.queue_depth {
+        options = options.queue_depth(entries);
+    }
+    match options.build() {
+        Ok(runtime) => runtime.block_on(serve(cli)),
+        Err(e) => {
+            eprintln!("Failed to create runtime: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs the command, on the runtime created by `main`.
+async fn serve(cli: Cli) {
     let addr = SocketAddr::new(cli.address, cli.port);
-    let server = HTTPServer::new(addr)
-        .await
-        .expect("Failed to create server");
-    println!("Server listening on http://{addr}");
+    if let Some(Command::Bench(bench)) = &cli.command {
+        let duration = Duration::from_secs(bench.duration);
+        match bench::run(&bench.target, addr, bench.concurrency, duration).await {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let mut builder = builder(&cli, addr);
+    // Last, as it answers all requests
+    if let Some(path) = &cli.archive {
+        match Archive::open(path).await {
+            Ok(archive) => builder = builder.handler("/", archive),
+            Err(e) => {
+                eprintln!("Failed to open {path}: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let server = builder.build().await.expect("Failed to create server");
+    // The port may have been picked by the system, for --port 0
+    let local_addr = server.local_addr().unwrap_or(addr);
+    for url in interfaces::reachable_urls(local_addr) {
+        println!("Server listening on {url}");
+    }
+    if let Some(driver) = nanoserve::current_driver() {
+        println!("Performing I/O with the {driver} driver");
+    }
+
+    #[cfg(not(unix))]
+    if cli.mdns || cli.mdns_name.is_some() {
+        eprintln!("Announcing via mDNS is not supported on this platform, ignoring --mdns");
+    }
+    #[cfg(unix)]
+    let announcer = (cli.mdns || cli.mdns_name.is_some())
+        .then(|| announcer(&cli, &server))
+        .flatten();
+    #[cfg(unix)]
+    let announcer_task = announcer
+        .clone()
+        .map(|announcer| spawn(async move { announcer.run().await }));
+
+    let mapping = if cli.public {
+        map_port(&server).await
+    } else {
+        None
+    };
+    let mapping_task = mapping
+        .clone()
+        .map(|mapping| spawn(async move { mapping.renew().await }));
 
     // Spawn the server in a separate task
-    let server_task = spawn(async move { server.run().await });
+    let server_task = spawn({
+        let server = server.clone();
+        async move { server.run().await }
+    });
+    // Restart without downtime on SIGUSR2, handing over the server task
+    #[cfg(unix)]
+    let server_task = spawn(restart::on_signal(server, server_task));
 
     // Wait for Ctrl+C
     ctrl_c().await.expect("Failed to listen for Ctrl+C");
@@ -25,5 +118,171 @@ async fn main() {
 
     // Cancel the server task
     drop(server_task);
+    if let Some(mapping) = mapping {
+        drop(mapping_task);
+        mapping.remove().await;
+    }
+    #[cfg(unix)]
+    if let Some(announcer) = announcer {
+        drop(announcer_task);
+        announcer.goodbye().await;
+    }
+    if cli.timings {
+        print!("Timings:\n{}", nanoserve::timings());
+    }
     println!("Server stopped successfully");
 }
+
+/// Configures the server from the command line.
+fn builder(cli: &Cli, addr: SocketAddr) -> HTTPServerBuilder {
+    let mut builder = HTTPServer::builder(addr);
+    #[cfg(unix)]
+    if let Some(listener) = restart::inherited_listener() {
+        builder = builder.listener(listener);
+    }
+    if let Some(cors) = cli.cors() {
+        builder = builder.cors(cors);
+    }
+    match cli.auth() {
+        Ok(Some(auth)) => builder = builder.auth(auth),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+    builder = builder
+        .nodelay(cli.nodelay)
+        .acceptors(cli.acceptors)
+        .backlog(cli.backlog)
+        .max_headers(cli.max_headers)
+        .read_timeout(Duration::from_secs(cli.read_timeout))
+        .timings(cli.timings)
+        .unfold_headers(cli.unfold_headers)
+        .strict_crlf(cli.strict_crlf)
+        .fold_case(cli.fold_case)
+        .chunk_size(cli.chunk_size, cli.max_chunk_size);
+    if let Some(rate) = cli.throttle {
+        builder = builder.throttle(rate);
+    }
+    if let Some(rate) = cli.global_throttle {
+        builder = builder.global_throttle(rate);
+    }
+    if let Some(max) = cli.max_connections {
+        builder = builder.max_connections(max, Duration::from_secs(cli.retry_after));
+    }
+    if let Some(bytes) = cli.max_body {
+        builder = builder.max_body(usize::try_from(bytes).unwrap_or(usize::MAX));
+    }
+    if let Some(bytes) = cli.memory_budget {
+        builder = builder.memory_budget(usize::try_from(bytes).unwrap_or(usize::MAX));
+    }
+    if let Some(size) = cli.send_buffer {
+        builder = builder.send_buffer_size(size);
+    }
+    if let Some(size) = cli.recv_buffer {
+        builder = builder.recv_buffer_size(size);
+    }
+    if cli.metadata_cache > 0 {
+        builder = builder.metadata_cache(Duration::from_millis(cli.metadata_cache));
+    }
+    if cli.open_files > 0 {
+        builder = builder.file_cache(cli.open_files);
+    }
+    for (name, host) in &cli.vhost {
+        builder = builder.virtual_host(name, host.clone());
+    }
+    // Redirects first, so that catch-all rewrites do not shadow them
+    for rule in cli.redirect.iter().chain(&cli.rewrite) {
+        builder = builder.rewrite(rule.clone());
+    }
+    for webhook in &cli.webhook {
+        let mut webhook = webhook.clone();
+        if let Some(count) = cli.webhook_not_found {
+            webhook = webhook.not_found_threshold(count, Duration::from_mins(1));
+        }
+        builder = builder.webhook(webhook);
+    }
+    if let Some(base_path) = &cli.base_path {
+        builder = builder.base_path(base_path);
+    }
+    for range in &cli.trusted_proxy {
+        builder = builder.trusted_proxy(*range);
+    }
+    for (prefix, proxy) in &cli.proxy {
+        let proxy = proxy
+            .clone()
+            .pool(cli.proxy_pool, Duration::from_secs(cli.proxy_idle_timeout));
+        builder = builder.handler(prefix, proxy);
+    }
+    let cache_dir = cli
+        .cache_dir
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("nanoserve-cache"));
+    for (prefix, upstream) in &cli.cache_proxy {
+        let proxy =
+            CachingProxy::new(upstream, &cache_dir).default_ttl(Duration::from_secs(cli.cache_ttl));
+        builder = builder.handler(prefix, proxy);
+    }
+    for fastcgi in &cli.fastcgi {
+        builder = builder.handler("/", fastcgi.clone());
+    }
+    builder
+}
+
+/// Map the port of the server on the local router, printing the public URL or why it failed.
+async fn map_port(server: &HTTPServer) -> Option<portmap::Mapping> {
+    let addr = server.local_addr().ok()?;
+    if addr.ip().is_loopback() {
+        eprintln!("Not mapping the port: not listening on the local network (try -a 0.0.0.0)");
+        return None;
+    }
+    match portmap::Mapping::new(addr.port()).await {
+        Ok(mapping) => {
+            println!("Public URL: http://{}", mapping.external);
+            Some(mapping)
+        }
+        Err(e) => {
+            eprintln!("Failed to map the port: {e}");
+            None
+        }
+    }
+}
+
+/// Create the mDNS announcer for the server, printing why if it cannot be.
+#[cfg(unix)]
+fn announcer(cli: &Cli, server: &HTTPServer) -> Option<mdns::Announcer> {
+    let addr = server.local_addr().ok()?;
+    let addrs = mdns::announced_addrs(
+        addr.ip(),
+        interfaces::interface_addrs()
+            .into_iter()
+            .map(|iface| iface.ip),
+    );
+    if addrs.is_empty() || addrs.iter().all(std::net::Ipv4Addr::is_loopback) {
+        eprintln!("Not announcing via mDNS: not listening on the local network (try -a 0.0.0.0)");
+        return None;
+    }
+    let host = mdns::host_name().unwrap_or_else(|| "nanoserve".to_string());
+    let service = mdns::Service {
+        instance: cli
+            .mdns_name
+            .clone()
+            .unwrap_or_else(|| format!("nanoserve on {host}")),
+        host,
+        addrs,
+        port: addr.port(),
+        path: cli.base_path.clone().unwrap_or_else(|| "/".to_string()),
+    };
+    let instance = service.instance.clone();
+    match mdns::Announcer::new(service) {
+        Ok(announcer) => {
+            println!("Announcing \"{instance}\" via mDNS");
+            Some(announcer)
+        }
+        Err(e) => {
+            eprintln!("Failed to announce via mDNS: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Announcement of the server on the local network via multicast DNS (mDNS) and DNS-SD.
+//!
+//! The server is advertised as an `_http._tcp` service, so that it shows up in service browsers
+//! on other devices. Only IPv4 is supported, and name conflicts are not probed for.
+
+use compio::{BufResult, net::UdpSocket, time::sleep};
+use std::{
+    io::Result as IoResult,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket},
+    os::fd::FromRawFd,
+    time::Duration,
+};
+
+/// The mDNS multicast group.
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+/// The mDNS port.
+const PORT: u16 = 5353;
+/// The service type.
+const SERVICE: &str = "_http._tcp.local";
+/// Name used to enumerate service types.
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+/// Record types.
+mod kind {
+    pub const A: u16 = 1;
+    pub const PTR: u16 = 12;
+    pub const TXT: u16 = 16;
+    pub const SRV: u16 = 33;
+    pub const ANY: u16 = 255;
+}
+
+/// Class `IN`.
+const CLASS_IN: u16 = 1;
+/// Set on the class of unique records, telling caches to flush other records of the same name.
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// The service to announce.
+#[derive(Debug, Clone)]
+pub struct Service {
+    /// Instance name, like `nanoserve on laptop`.
+    pub instance: String,
+    /// Host name without the `.local` suffix.
+    pub host: String,
+    /// Addresses of the host.
+    pub addrs: Vec<Ipv4Addr>,
+    /// Port of the server.
+    pub port: u16,
+    /// Path of the server, for the `path` TXT attribute.
+    pub path: String,
+}
+
+impl Service {
+    /// Full name of the instance.
+    fn instance_name(&self) -> String {
+        // A label has at most 63 bytes
+        let mut end = self.instance.len().min(63);
+        while !self.instance.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}.{SERVICE}", &self.instance[..end])
+    }
+
+    /// Full name of the host.
+    fn host_name(&self) -> String {
+        format!("{}.local", self.host)
+    }
+
+    /// Whether a question is about this service.
+    fn concerns(&self, name: &str, kind: u16) -> bool {
+        let is = |other: &str| name.eq_ignore_ascii_case(other);
+        match kind {
+            kind::PTR => is(SERVICE) || is(SERVICES),
+            kind::SRV | kind::TXT => is(&self.instance_name()),
+            kind::A => is(&self.host_name()),
+            kind::ANY => {
+                is(SERVICE) || is(SERVICES) || is(&self.instance_name()) || is(&self.host_name())
+            }
+            _ => false,
+        }
+    }
+
+    /// Build a response with all records of the service, with TTLs of zero to say goodbye.
+    fn response(&self, goodbye: bool) -> Vec<u8> {
+        let (host_ttl, other_ttl) = if goodbye { (0, 0) } else { (120, 4500) };
+        let instance = self.instance_name();
+        let host = self.host_name();
+        let mut message = Vec::new();
+        let count = u16::try_from(4 + self.addrs.len()).unwrap_or(u16::MAX);
+        // ID, flags (response, authoritative), and counts of questions, answers, authority and additional records
+        for field in [0, 0x8400, 0, count, 0, 0] {
+            message.extend_from_slice(&u16::to_be_bytes(field));
+        }
+
+        let mut rdata = Vec::new();
+        encode_name(&mut rdata, &instance);
+        record(
+            &mut message,
+            SERVICE,
+            kind::PTR,
+            CLASS_IN,
+            other_ttl,
+            &rdata,
+        );
+        rdata.clear();
+        encode_name(&mut rdata, SERVICE);
+        record(
+            &mut message,
+            SERVICES,
+            kind::PTR,
+            CLASS_IN,
+            other_ttl,
+            &rdata,
+        );
+        // Priority, weight, port and target
+        rdata.clear();
+        for field in [0, 0, self.port] {
+            rdata.extend_from_slice(&field.to_be_bytes());
+        }
+        encode_name(&mut rdata, &host);
+        let unique = CLASS_IN | CACHE_FLUSH;
+        record(&mut message, &instance, kind::SRV, unique, host_ttl, &rdata);
+        rdata.clear();
+        let path = format!("path={}", self.path);
+        rdata.push(u8::try_from(path.len()).unwrap_or(u8::MAX));
+        rdata.extend(path.bytes().take(255));
+        record(
+            &mut message,
+            &instance,
+            kind::TXT,
+            unique,
+            other_ttl,
+            &rdata,
+        );
+        for addr in &self.addrs {
+            record(
+                &mut message,
+                &host,
+                kind::A,
+                unique,
+                host_ttl,
+                &addr.octets(),
+            );
+        }
+        message
+    }
+}
+
+/// Announces a [`Service`] and answers queries about it.
+#[derive(Debug, Clone)]
+pub struct Announcer {
+    /// Socket joined to the multicast group.
+    socket: UdpSocket,
+    /// The announced service.
+    service: Service,
+}
+
+impl Announcer {
+    /// Binds the mDNS port, shared with other responders like Avahi, and joins the group.
+    pub fn new(service: Service) -> IoResult<Self> {
+        let socket = bind_shared()?;
+        socket.set_multicast_ttl_v4(255)?;
+        socket.set_multicast_loop_v4(true)?;
+        // Join on every interface, as the default one might not be on the local network
+        socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        for addr in &service.addrs {
+            // Fails for the interface joined above, which is fine
+            let _ = socket.join_multicast_v4(&GROUP, addr);
+        }
+        Ok(Self {
+            socket: UdpSocket::from_std(socket)?,
+            service,
+        })
+    }
+
+    /// Announces the service, then answers queries about it until cancelled.
+    pub async fn run(&self) {
+        // Announce twice, one second apart, as recommended by RFC 6762
+        for _ in 0..2 {
+            self.send(false).await;
+            sleep(Duration::from_secs(1)).await;
+        }
+        let mut buffer = Vec::with_capacity(9000);
+        loop {
+            let BufResult(result, returned) = self.socket.recv_from(buffer).await;
+            buffer = returned;
+            match result {
+                Ok(_) if self.is_relevant_query(&buffer) => self.send(false).await,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error while receiving mDNS queries: {e}");
+                    return;
+                }
+            }
+            buffer.clear();
+        }
+    }
+
+    /// Tells others that the service is going away.
+    pub async fn goodbye(&self) {
+        self.send(true).await;
+    }
+
+    /// Sends all records of the service to the group.
+    async fn send(&self, goodbye: bool) {
+        let group = SocketAddr::V4(SocketAddrV4::new(GROUP, PORT));
+        let BufResult(result, _) = self
+            .socket
+            .send_to(self.service.response(goodbye), group)
+            .await;
+        if let Err(e) = result {
+            eprintln!("Error while sending mDNS response: {e}");
+        }
+    }
+
+    /// Whether a message is a query with a question about the service.
+    fn is_relevant_query(&self, message: &[u8]) -> bool {
+        let field = |offset: usize| {
+            message
+                .get(offset..offset + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let (Some(flags), Some(questions)) = (field(2), field(4)) else {
+            return false;
+        };
+        if flags & 0x8000 != 0 {
+            return false;
+        }
+        let mut offset = 12;
+        for _ in 0..questions {
+            let Some((name, end)) = decode_name(message, offset) else {
+                return false;
+            };
+            let Some(kind) = field(end) else {
+                return false;
+            };
+            if self.service.concerns(&name, kind) {
+                return true;
+            }
+            offset = end + 4;
+        }
+        false
+    }
+}
+
+/// Create a UDP socket bound to the mDNS port with `SO_REUSEADDR` and `SO_REUSEPORT`.
+fn bind_shared() -> IoResult<StdUdpSocket> {
+    use std::io::Error as IoError;
+
+    let check = |result: libc::c_int| {
+        if result < 0 {
+            Err(IoError::last_os_error())
+        } else {
+            Ok(result)
+        }
+    };
+    // SAFETY: plain system calls, with the descriptor owned by the returned socket right away
+    unsafe {
+        let fd = check(libc::socket(
+            libc::AF_INET,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            0,
+        ))?;
+        let socket = StdUdpSocket::from_raw_fd(fd);
+        let one: libc::c_int = 1;
+        #[allow(clippy::cast_possible_truncation, reason = "Size of a c_int")]
+        let len = size_of::<libc::c_int>() as libc::socklen_t;
+        for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+            check(libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                option,
+                (&raw const one).cast(),
+                len,
+            ))?;
+        }
+        #[allow(clippy::cast_possible_truncation, reason = "Address families fit")]
+        let addr = libc::sockaddr_in {
+            sin_family: libc::AF_INET as libc::sa_family_t,
+            sin_port: PORT.to_be(),
+            sin_addr: libc::in_addr { s_addr: 0 },
+            sin_zero: [0; 8],
+        };
+        #[allow(clippy::cast_possible_truncation, reason = "Size of a sockaddr_in")]
+        let len = size_of::<libc::sockaddr_in>() as libc::socklen_t;
+        check(libc::bind(fd, (&raw const addr).cast(), len))?;
+        Ok(socket)
+    }
+}
+
+/// Get the IPv4 addresses to announce for a server bound to `addr`.
+pub fn announced_addrs(
+    addr: IpAddr,
+    interfaces: impl IntoIterator<Item = IpAddr>,
+) -> Vec<Ipv4Addr> {
+    match addr {
+        IpAddr::V4(ip) if !ip.is_unspecified() => vec![ip],
+        IpAddr::V6(ip) if !ip.is_unspecified() => ip.to_ipv4_mapped().into_iter().collect(),
+        _ => interfaces
+            .into_iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V4(ip) if !ip.is_loopback() => Some(ip),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+/// Get the host name of this machine, without domain.
+pub fn host_name() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its length
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return None;
+    }
+    let end = buffer.iter().position(|&b| b == 0)?;
+    let name = String::from_utf8_lossy(&buffer[..end]);
+    let name = name.split('.').next()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Append a resource record.
+fn record(message: &mut Vec<u8>, name: &str, kind: u16, class: u16, ttl: u32, rdata: &[u8]) {
+    encode_name(message, name);
+    message.extend_from_slice(&kind.to_be_bytes());
+    message.extend_from_slice(&class.to_be_bytes());
+    message.extend_from_slice(&ttl.to_be_bytes());
+    let len = u16::try_from(rdata.len()).unwrap_or(u16::MAX);
+    message.extend_from_slice(&len.to_be_bytes());
+    message.extend_from_slice(rdata);
+}
+
+/// Append a name, without compression. Instance names may contain dots, so their first label
+/// is only split from the service type.
+fn encode_name(message: &mut Vec<u8>, name: &str) {
+    let (first, rest) = name
+        .strip_suffix(SERVICE)
+        .and_then(|first| first.strip_suffix('.'))
+        .map_or((None, name), |first| (Some(first), SERVICE));
+    for label in first.into_iter().chain(rest.split('.')) {
+        let len = label.len().min(63);
+        #[allow(clippy::cast_possible_truncation, reason = "At most 63")]
+        message.push(len as u8);
+        message.extend_from_slice(&label.as_bytes()[..len]);
+    }
+    message.push(0);
+}
+
+/// Decode the name at `offset`, following compression pointers, and get the offset after it.
+fn decode_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bound the number of jumps, so that pointer loops cannot hang
+    for _ in 0..128 {
+        let len = usize::from(*message.get(offset)?);
+        match len {
+            0 => {
+                let name = labels.join(".");
+                return Some((name, end.unwrap_or(offset + 1)));
+            }
+            0xc0.. => {
+                let low = usize::from(*message.get(offset + 1)?);
+                end.get_or_insert(offset + 2);
+                offset = (len & 0x3f) << 8 | low;
+            }
+            1..=63 => {
+                let label = message.get(offset + 1..offset + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
@@ -0,0 +1,41 @@
+//! Accounting of the memory held by caches and buffer pools, against a single budget.
+//!
+//! Idle pooled buffers are the first to go when the budget runs short, as they are the cheapest
+//! to replace; caches then evict their own entries.
+
+use super::pool;
+use std::cell::Cell;
+
+thread_local! {
+    /// Maximum number of bytes held, per thread like the buffer pool.
+    static BUDGET: Cell<usize> = const { Cell::new(usize::MAX) };
+    /// Number of bytes held.
+    static USED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Set the maximum number of bytes held by caches and buffer pools together.
+pub fn set_budget(bytes: usize) {
+    BUDGET.set(bytes);
+}
+
+/// Account for `bytes` about to be held, freeing idle pooled buffers if needed. Returns `false`
+/// if they do not fit the budget, in which case they must not be held.
+pub fn reserve(bytes: usize) -> bool {
+    let fits = || {
+        let used = USED.get().saturating_add(bytes);
+        let fits = used <= BUDGET.get();
+        if fits {
+            USED.set(used);
+        }
+        fits
+    };
+    fits() || {
+        pool::trim();
+        fits()
+    }
+}
+
+/// Account for `bytes` no longer held.
+pub fn release(bytes: usize) {
+    USED.set(USED.get().saturating_sub(bytes));
+}
@@ -0,0 +1,118 @@
+//! Media types, guessed for files from their extension, or parsed from headers.
+
+/// Get the media type for a path by its extension, defaulting to `application/octet-stream`.
+pub fn guess(path: &str) -> &'static str {
+    let extension = path
+        .rsplit_once('.')
+        .filter(|(_, extension)| !extension.contains('/'))
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("txt" | "md") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A media type like `text/html; charset=utf-8`, as in a `Content-Type` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType<'a> {
+    /// The top-level type, like `text`.
+    pub kind: &'a str,
+    /// The subtype, like `html`.
+    pub subtype: &'a str,
+    /// The parameters, like `charset`, with quotes removed around values.
+    pub params: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> MediaType<'a> {
+    /// Parse a media type, or get `None` if it has no valid type and subtype.
+    #[must_use]
+    pub fn parse(s: &'a str) -> Option<Self> {
+        let (essence, rest) = s.split_once(';').unwrap_or((s, ""));
+        let (kind, subtype) = essence.trim().split_once('/')?;
+        let is_token = |s: &str| {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b))
+        };
+        (is_token(kind) && is_token(subtype)).then(|| Self {
+            kind,
+            subtype,
+            params: params(rest),
+        })
+    }
+
+    /// Check whether this is the media type `essence`, like `text/html`, ignoring case and
+    /// parameters.
+    #[must_use]
+    pub fn is(&self, essence: &str) -> bool {
+        essence.split_once('/').is_some_and(|(kind, subtype)| {
+            self.kind.eq_ignore_ascii_case(kind) && self.subtype.eq_ignore_ascii_case(subtype)
+        })
+    }
+
+    /// Get the value of the parameter `name`, like `utf-8` for `charset`.
+    #[must_use]
+    pub fn param(&self, name: &str) -> Option<&'a str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, value)| value)
+    }
+}
+
+/// Parse parameters like `; name="a;b"; size=3`, removing quotes around values, which may
+/// contain semicolons. Escaped quotes are kept as is, and parameters without a value are skipped.
+pub fn params(mut s: &str) -> Vec<(&str, &str)> {
+    let mut params = Vec::new();
+    while let Some((name, rest)) = s.split_once('=') {
+        let rest = rest.trim_start();
+        let (value, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut escaped = false;
+            let Some(end) = quoted.find(|c| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            }) else {
+                break;
+            };
+            let rest = &quoted[end + 1..];
+            (
+                &quoted[..end],
+                rest.split_once(';').map_or("", |(_, rest)| rest),
+            )
+        } else {
+            let (value, rest) = rest.split_once(';').unwrap_or((rest, ""));
+            (value.trim_end(), rest)
+        };
+        let name = name.rsplit(';').next().unwrap_or_default().trim();
+        if !name.is_empty() {
+            params.push((name, value));
+        }
+        s = rest;
+    }
+    params
+}
@@ -0,0 +1,385 @@
+//! Parsing of `multipart/form-data` bodies, as sent by HTML forms uploading files.
+//!
+//! Parts of a body in memory, like the ones read before handlers run, are found one at a time as
+//! [`Multipart`] is iterated, and their content is a slice of the body, so that files are never
+//! copied.
+//!
+//! Bodies arriving in chunks are parsed by a [`MultipartParser`] instead, which is sans-I/O like
+//! [`http1`](crate::http1): it is fed each chunk and emits the head of each part and the chunks
+//! of its content as soon as they are found, so that large uploads can be written out as they
+//! arrive rather than buffered whole.
+
+use super::{
+    ParseRequestError, Request,
+    mime::{self, MediaType},
+};
+use std::str::from_utf8;
+
+/// Maximum size of the headers of a part parsed by a [`MultipartParser`].
+const MAX_PART_HEAD: usize = 8 * 1024;
+
+/// Iterator over the parts of a `multipart/form-data` body, from [`Request::multipart`].
+#[derive(Debug, Clone)]
+pub struct Multipart<'a> {
+    /// The request body.
+    body: &'a [u8],
+    /// Delimiter preceding each part, which is CRLF, `--` and the boundary.
+    delimiter: Vec<u8>,
+    /// Position right after the last delimiter found, or `None` once done.
+    position: Option<usize>,
+}
+
+/// A part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part<'a> {
+    /// The headers of the part.
+    pub headers: Vec<(&'a str, &'a str)>,
+    /// The content of the part.
+    pub content: &'a [u8],
+}
+
+impl<'a> Multipart<'a> {
+    /// Find the parts of a request body, or `None` if the request is not `multipart/form-data`
+    /// with a boundary.
+    #[must_use]
+    pub fn new(request: &Request<'a>) -> Option<Self> {
+        let delimiter = delimiter(&request.content_type()?)?;
+        // The first delimiter may start the body, without the CRLF ending a preamble
+        let position = if request.body.starts_with(&delimiter[2..]) {
+            delimiter.len() - 2
+        } else {
+            find(request.body, &delimiter, 0).map_or(request.body.len(), |at| at + delimiter.len())
+        };
+        Some(Self {
+            body: request.body,
+            delimiter,
+            position: Some(position),
+        })
+    }
+
+    /// Parse the part starting right after a delimiter at `position`, returning it and the
+    /// position after the next delimiter, or `None` after the last part.
+    fn part_at(&self, position: usize) -> Result<Option<(Part<'a>, usize)>, ParseRequestError> {
+        let rest = &self.body[position..];
+        if rest.starts_with(b"--") {
+            return Ok(None);
+        }
+        // Transport padding may follow the delimiter
+        let padding = rest
+            .iter()
+            .take_while(|&&b| b == b' ' || b == b'\t')
+            .count();
+        if !rest[padding..].starts_with(b"\r\n") {
+            return Err(ParseRequestError::InvalidMultipart);
+        }
+        let start = position + padding + 2;
+        let (headers, content_start) = if self.body[start..].starts_with(b"\r\n") {
+            (Vec::new(), start + 2)
+        } else {
+            let end =
+                find(self.body, b"\r\n\r\n", start).ok_or(ParseRequestError::InvalidMultipart)?;
+            (parse_headers(&self.body[start..end])?, end + 4)
+        };
+        let end = find(self.body, &self.delimiter, content_start)
+            .ok_or(ParseRequestError::InvalidMultipart)?;
+        let part = Part {
+            headers,
+            content: &self.body[content_start..end],
+        };
+        Ok(Some((part, end + self.delimiter.len())))
+    }
+}
+
+impl<'a> Iterator for Multipart<'a> {
+    type Item = Result<Part<'a>, ParseRequestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.position.take()?;
+        if position >= self.body.len() {
+            // Without a closing delimiter, the body was cut short
+            return Some(Err(ParseRequestError::InvalidMultipart));
+        }
+        match self.part_at(position) {
+            Ok(Some((part, next))) => {
+                self.position = Some(next);
+                Some(Ok(part))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<'a> Part<'a> {
+    /// Get the value of the header `name`.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, value)| value)
+    }
+
+    /// Get the media type of the part, from its `Content-Type` header.
+    #[must_use]
+    pub fn content_type(&self) -> Option<MediaType<'a>> {
+        self.header("Content-Type").and_then(MediaType::parse)
+    }
+
+    /// Get the name of the form field of the part, from its `Content-Disposition` header.
+    #[must_use]
+    pub fn name(&self) -> Option<&'a str> {
+        self.disposition_param("name")
+    }
+
+    /// Get the name of the uploaded file, from its `Content-Disposition` header, or `None` for
+    /// other fields. Clients may send full paths, so only the last component should be used.
+    #[must_use]
+    pub fn filename(&self) -> Option<&'a str> {
+        self.disposition_param("filename")
+    }
+
+    /// Get a parameter of the `Content-Disposition` header.
+    fn disposition_param(&self, key: &str) -> Option<&'a str> {
+        disposition_param(self.header("Content-Disposition")?, key)
+    }
+}
+
+/// Incremental parser of a `multipart/form-data` body, fed with the chunks of the body as they
+/// arrive.
+///
+/// Content is emitted as soon as it cannot be the start of a delimiter, so at most the length
+/// of the delimiter, or the headers of a part, are buffered between chunks.
+#[derive(Debug, Clone)]
+pub struct MultipartParser {
+    /// Delimiter preceding each part, which is CRLF, `--` and the boundary.
+    delimiter: Vec<u8>,
+    /// Bytes fed but not parsed yet.
+    buffer: Vec<u8>,
+    /// What is expected next.
+    state: State,
+}
+
+/// What a [`MultipartParser`] expects next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// The preamble, up to the first delimiter.
+    Preamble,
+    /// The end of a delimiter: transport padding and CRLF, or `--` after the last part.
+    Delimiter,
+    /// The headers of a part.
+    Headers,
+    /// The content of a part, up to the next delimiter.
+    Content,
+    /// The epilogue, after the closing delimiter, which is ignored.
+    Done,
+}
+
+/// What a [`MultipartParser`] found in the chunks fed to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The start of a part, ending the previous one.
+    Part(PartHead),
+    /// A chunk of the content of the current part.
+    Content(Vec<u8>),
+    /// The closing delimiter, ending the last part.
+    End,
+}
+
+/// The headers of a part found by a [`MultipartParser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartHead {
+    /// The headers of the part.
+    pub headers: Vec<(String, String)>,
+}
+
+impl MultipartParser {
+    /// Creates a parser for a body whose parts are delimited by `boundary`.
+    #[must_use]
+    pub fn new(boundary: &str) -> Self {
+        Self::with_delimiter([b"\r\n--", boundary.as_bytes()].concat())
+    }
+
+    /// Creates a parser for a body of the given `Content-Type`, or `None` if it is not
+    /// `multipart/form-data` with a boundary.
+    #[must_use]
+    pub fn for_content_type(content_type: &str) -> Option<Self> {
+        delimiter(&MediaType::parse(content_type)?).map(Self::with_delimiter)
+    }
+
+    /// Creates a parser for a body whose parts are preceded by `delimiter`.
+    fn with_delimiter(delimiter: Vec<u8>) -> Self {
+        Self {
+            delimiter,
+            // The first delimiter may start the body, without the CRLF ending a preamble
+            buffer: b"\r\n".to_vec(),
+            state: State::Preamble,
+        }
+    }
+
+    /// Parse the next chunk of the body, returning what was found so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRequestError::InvalidMultipart`] if the body is malformed, after which
+    /// the parser should not be fed anymore.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Event>, ParseRequestError> {
+        let mut events = Vec::new();
+        if self.state == State::Done {
+            return Ok(events);
+        }
+        self.buffer.extend_from_slice(chunk);
+        while self.step(&mut events)? {}
+        Ok(events)
+    }
+
+    /// Check that the whole body was fed, up to its closing delimiter.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRequestError::InvalidMultipart`] if the body was cut short.
+    pub fn finish(&self) -> Result<(), ParseRequestError> {
+        if self.state == State::Done {
+            Ok(())
+        } else {
+            Err(ParseRequestError::InvalidMultipart)
+        }
+    }
+
+    /// Parse what the buffer holds in the current state, pushing what is found to `events`,
+    /// and returning whether the parser can go on without more bytes.
+    fn step(&mut self, events: &mut Vec<Event>) -> Result<bool, ParseRequestError> {
+        match self.state {
+            State::Preamble | State::Content => {
+                let found = find(&self.buffer, &self.delimiter, 0);
+                // Otherwise the end of the buffer may be the start of a delimiter
+                let end = found.unwrap_or_else(|| {
+                    self.buffer.len() - self.buffer.len().min(self.delimiter.len() - 1)
+                });
+                let chunk: Vec<u8> = self.buffer.drain(..end).collect();
+                if self.state == State::Content && !chunk.is_empty() {
+                    events.push(Event::Content(chunk));
+                }
+                if found.is_none() {
+                    return Ok(false);
+                }
+                self.buffer.drain(..self.delimiter.len());
+                self.state = State::Delimiter;
+            }
+            State::Delimiter => {
+                if self.buffer.starts_with(b"--") {
+                    self.buffer = Vec::new();
+                    self.state = State::Done;
+                    events.push(Event::End);
+                    return Ok(false);
+                }
+                // Transport padding may follow the delimiter
+                let padding = self
+                    .buffer
+                    .iter()
+                    .take_while(|&&b| b == b' ' || b == b'\t')
+                    .count();
+                self.buffer.drain(..padding);
+                if self.buffer.len() < 2 {
+                    return Ok(false);
+                }
+                if !self.buffer.starts_with(b"\r\n") {
+                    return Err(ParseRequestError::InvalidMultipart);
+                }
+                self.buffer.drain(..2);
+                self.state = State::Headers;
+            }
+            State::Headers => {
+                let (headers, len) = if self.buffer.starts_with(b"\r\n") {
+                    (Vec::new(), 2)
+                } else if let Some(end) = find(&self.buffer, b"\r\n\r\n", 0) {
+                    let headers = parse_headers(&self.buffer[..end])?
+                        .into_iter()
+                        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                        .collect();
+                    (headers, end + 4)
+                } else if self.buffer.len() > MAX_PART_HEAD {
+                    return Err(ParseRequestError::InvalidMultipart);
+                } else {
+                    return Ok(false);
+                };
+                self.buffer.drain(..len);
+                self.state = State::Content;
+                events.push(Event::Part(PartHead { headers }));
+            }
+            State::Done => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl PartHead {
+    /// Get the value of the header `name`.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the media type of the part, from its `Content-Type` header.
+    #[must_use]
+    pub fn content_type(&self) -> Option<MediaType<'_>> {
+        self.header("Content-Type").and_then(MediaType::parse)
+    }
+
+    /// Get the name of the form field of the part, from its `Content-Disposition` header.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        disposition_param(self.header("Content-Disposition")?, "name")
+    }
+
+    /// Get the name of the uploaded file, from its `Content-Disposition` header, or `None` for
+    /// other fields. Clients may send full paths, so only the last component should be used.
+    #[must_use]
+    pub fn filename(&self) -> Option<&str> {
+        disposition_param(self.header("Content-Disposition")?, "filename")
+    }
+}
+
+/// Get the delimiter of the parts of a body of the given media type, or `None` if it is not
+/// `multipart/form-data` with a boundary.
+fn delimiter(media_type: &MediaType<'_>) -> Option<Vec<u8>> {
+    let boundary = Some(media_type)
+        .filter(|media_type| media_type.is("multipart/form-data"))?
+        .param("boundary")
+        .filter(|boundary| !boundary.is_empty())?;
+    Some([b"\r\n--", boundary.as_bytes()].concat())
+}
+
+/// Parse the headers of a part, without the empty line ending them.
+fn parse_headers(head: &[u8]) -> Result<Vec<(&str, &str)>, ParseRequestError> {
+    from_utf8(head)?
+        .split("\r\n")
+        .map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let name = name.trim();
+            (!name.is_empty()).then_some((name, value.trim()))
+        })
+        .collect::<Option<_>>()
+        .ok_or(ParseRequestError::InvalidMultipart)
+}
+
+/// Get a parameter of a `Content-Disposition` header.
+fn disposition_param<'h>(disposition: &'h str, key: &str) -> Option<&'h str> {
+    let (_, params) = disposition.split_once(';')?;
+    mime::params(params)
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+}
+
+/// Find `needle` in `haystack` at or after `start`.
+fn find(haystack: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    haystack
+        .get(start..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|at| start + at)
+}
@@ -0,0 +1,154 @@
+//! Normalization of request paths, so that file resolution, logging, rewrite rules and access
+//! rules all see the same canonical path.
+//!
+//! This follows the syntax-based normalization of RFC 3986: percent-encoded unreserved
+//! characters are decoded, other escapes use uppercase hexadecimal digits, and dot segments are
+//! removed, never climbing above the root. Repeated slashes are collapsed too, as filesystems
+//! treat them as one.
+
+use std::{borrow::Cow, fmt::Write};
+
+/// Normalize the path of an origin-form request target like `/a/./b/../c?query`.
+///
+/// The query is kept as is. ASCII letters of the path are lowercased if `fold_case` is set, for
+/// case-insensitive filesystems. Other targets, like `*`, are returned unchanged.
+#[must_use]
+pub fn normalize_path(target: &str, fold_case: bool) -> Cow<'_, str> {
+    let (path, query) = target
+        .split_once('?')
+        .map_or((target, None), |(path, query)| (path, Some(query)));
+    if !path.starts_with('/') || is_normal(path, fold_case) {
+        return Cow::Borrowed(target);
+    }
+
+    let decoded = normalize_escapes(path, fold_case);
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut normalized = String::with_capacity(target.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    // Directories keep their trailing slash, including those reached by dot segments
+    let last = decoded.rsplit('/').next().unwrap_or_default();
+    if normalized.is_empty() || matches!(last, "" | "." | "..") {
+        normalized.push('/');
+    }
+    if let Some(query) = query {
+        normalized.push('?');
+        normalized.push_str(query);
+    }
+    Cow::Owned(normalized)
+}
+
+/// Check whether a path is already normal, which is the case of most requests.
+fn is_normal(path: &str, fold_case: bool) -> bool {
+    !path.contains('%')
+        && !path.contains("//")
+        && !path.contains("/./")
+        && !path.contains("/../")
+        && !path.ends_with("/.")
+        && !path.ends_with("/..")
+        && !(fold_case && path.bytes().any(|b| b.is_ascii_uppercase()))
+}
+
+/// Decode the escapes of unreserved characters and uppercase the others, lowercasing ASCII
+/// letters if `fold_case` is set.
+fn normalize_escapes(path: &str, fold_case: bool) -> String {
+    let push = |normalized: &mut String, text: &str| {
+        if fold_case {
+            normalized.extend(text.chars().map(|c| c.to_ascii_lowercase()));
+        } else {
+            normalized.push_str(text);
+        }
+    };
+    let mut normalized = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(index) = rest.find('%') {
+        push(&mut normalized, &rest[..index]);
+        rest = &rest[index + 1..];
+        let byte = rest
+            .get(..2)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                let c = char::from(byte);
+                normalized.push(if fold_case { c.to_ascii_lowercase() } else { c });
+                rest = &rest[2..];
+            }
+            Some(byte) => {
+                let _ = write!(normalized, "%{byte:02X}");
+                rest = &rest[2..];
+            }
+            // A lone `%` is kept as is
+            None => normalized.push('%'),
+        }
+    }
+    push(&mut normalized, rest);
+    normalized
+}
+
+/// Decode a normalized request path into a path relative to a document root.
+///
+/// Returns `None` if the decoded path is not UTF-8, or has a dot segment or a segment with a NUL
+/// byte, another control character or a slash. Backslashes and colons are also rejected on
+/// Windows, where they separate paths and name drives or streams, and are literal on Unix.
+#[must_use]
+pub fn decode_path(path: &str) -> Option<Cow<'_, str>> {
+    let path = path.trim_start_matches('/');
+    if !path.contains('%') {
+        return path
+            .split('/')
+            .all(is_valid_segment)
+            .then_some(Cow::Borrowed(path));
+    }
+    let mut decoded = String::with_capacity(path.len());
+    for (index, segment) in path.split('/').enumerate() {
+        let segment = decode_segment(segment)?;
+        if !is_valid_segment(&segment) {
+            return None;
+        }
+        if index > 0 {
+            decoded.push('/');
+        }
+        decoded.push_str(&segment);
+    }
+    Some(Cow::Owned(decoded))
+}
+
+/// Decode the escapes of a path segment, keeping a lone `%` as is.
+fn decode_segment(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        if let Some(escaped) = escaped {
+            bytes.push(escaped);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Check whether a decoded segment can be safely joined to a document root.
+fn is_valid_segment(segment: &str) -> bool {
+    !matches!(segment, "." | "..")
+        && !segment
+            .chars()
+            .any(|c| c == '/' || c.is_control() || (cfg!(windows) && matches!(c, '\\' | ':')))
+}
@@ -0,0 +1,48 @@
+//! Reuse of I/O buffers across requests and connections.
+
+use super::memory;
+use std::cell::RefCell;
+
+/// Maximum number of idle buffers kept.
+const MAX_IDLE: usize = 64;
+/// Capacity above which buffers are not kept, so that the pool stays small.
+const MAX_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    /// Idle buffers, per thread since runtimes are single-threaded.
+    static IDLE: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Take an empty buffer with at least the given capacity from the pool, or allocate one.
+pub fn take(capacity: usize) -> Vec<u8> {
+    IDLE.with_borrow_mut(|idle| {
+        idle.iter()
+            .rposition(|buffer| buffer.capacity() >= capacity)
+            .map_or_else(
+                || Vec::with_capacity(capacity),
+                |index| {
+                    let buffer = idle.swap_remove(index);
+                    memory::release(buffer.capacity());
+                    buffer
+                },
+            )
+    })
+}
+
+/// Return a buffer to the pool, for later [`take`]s.
+pub fn give(mut buffer: Vec<u8>) {
+    if buffer.capacity() > MAX_CAPACITY
+        || IDLE.with_borrow(Vec::len) >= MAX_IDLE
+        || !memory::reserve(buffer.capacity())
+    {
+        return;
+    }
+    buffer.clear();
+    IDLE.with_borrow_mut(|idle| idle.push(buffer));
+}
+
+/// Drop all idle buffers, to free memory for other uses.
+pub fn trim() {
+    let idle = IDLE.take();
+    memory::release(idle.iter().map(Vec::capacity).sum());
+}
@@ -0,0 +1,409 @@
+//! Port mapping on the local router, to make the server reachable from the internet.
+//!
+//! NAT-PMP is tried first, as it is simple and cheap, falling back to `UPnP` IGD. Mappings are
+//! leased for an hour and renewed periodically, and removed on shutdown.
+
+use compio::{
+    BufResult,
+    io::{AsyncRead, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time::{sleep, timeout},
+};
+use std::{
+    fmt::Write,
+    fs,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+/// Lease duration requested for mappings.
+const LIFETIME: Duration = Duration::from_hours(1);
+/// Port NAT-PMP servers listen on.
+const NAT_PMP_PORT: u16 = 5351;
+/// SSDP multicast address, to discover `UPnP` devices.
+const SSDP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), 1900);
+/// `UPnP` services able to map ports, from most to least preferred.
+const UPNP_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// A port mapping on the router.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    /// Public address of the mapping.
+    pub external: SocketAddrV4,
+    /// Local port mapped to.
+    internal_port: u16,
+    /// How the mapping was made.
+    method: Method,
+}
+
+/// Protocol used to create a [`Mapping`].
+#[derive(Debug, Clone)]
+enum Method {
+    /// NAT-PMP, with the address of the gateway.
+    NatPmp(Ipv4Addr),
+    /// `UPnP` IGD, with the control URL, service type and local address.
+    Upnp {
+        control: String,
+        service: &'static str,
+        local: Ipv4Addr,
+    },
+}
+
+impl Mapping {
+    /// Maps the same external port to the local TCP `port`.
+    pub async fn new(port: u16) -> Result<Self, String> {
+        let nat_pmp = match default_gateway() {
+            Some(gateway) => match nat_pmp_map(gateway, port, port, LIFETIME).await {
+                Ok(external) => {
+                    return Ok(Self {
+                        external,
+                        internal_port: port,
+                        method: Method::NatPmp(gateway),
+                    });
+                }
+                Err(e) => e,
+            },
+            None => "no default gateway".to_string(),
+        };
+        let (control, service, local) = upnp_discover()
+            .await
+            .map_err(|e| format!("NAT-PMP: {nat_pmp}; UPnP: {e}"))?;
+        let mapping = Self {
+            external: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port),
+            internal_port: port,
+            method: Method::Upnp {
+                control,
+                service,
+                local,
+            },
+        };
+        mapping
+            .upnp_add()
+            .await
+            .map_err(|e| format!("NAT-PMP: {nat_pmp}; UPnP: {e}"))?;
+        let ip = mapping
+            .upnp_external_ip()
+            .await
+            .map_err(|e| format!("UPnP: {e}"))?;
+        Ok(Self {
+            external: SocketAddrV4::new(ip, port),
+            ..mapping
+        })
+    }
+
+    /// Renews the mapping before its lease expires, until cancelled.
+    pub async fn renew(&self) {
+        loop {
+            sleep(LIFETIME / 2).await;
+            let result = match &self.method {
+                Method::NatPmp(gateway) => {
+                    nat_pmp_map(*gateway, self.internal_port, self.external.port(), LIFETIME)
+                        .await
+                        .map(|_| ())
+                }
+                Method::Upnp { .. } => self.upnp_add().await,
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to renew port mapping: {e}");
+            }
+        }
+    }
+
+    /// Removes the mapping.
+    pub async fn remove(&self) {
+        let result = match &self.method {
+            Method::NatPmp(gateway) => nat_pmp_map(*gateway, self.internal_port, 0, Duration::ZERO)
+                .await
+                .map(|_| ()),
+            Method::Upnp { .. } => {
+                let port = self.external.port().to_string();
+                self.upnp_call(
+                    "DeletePortMapping",
+                    &[
+                        ("NewRemoteHost", ""),
+                        ("NewExternalPort", &port),
+                        ("NewProtocol", "TCP"),
+                    ],
+                )
+                .await
+                .map(|_| ())
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to remove port mapping: {e}");
+        }
+    }
+
+    /// Adds or renews the mapping via `UPnP`.
+    async fn upnp_add(&self) -> Result<(), String> {
+        let Method::Upnp { local, .. } = &self.method else {
+            unreachable!("not a UPnP mapping");
+        };
+        let external = self.external.port().to_string();
+        let internal = self.internal_port.to_string();
+        let local = local.to_string();
+        let lifetime = LIFETIME.as_secs().to_string();
+        self.upnp_call(
+            "AddPortMapping",
+            &[
+                ("NewRemoteHost", ""),
+                ("NewExternalPort", &external),
+                ("NewProtocol", "TCP"),
+                ("NewInternalPort", &internal),
+                ("NewInternalClient", &local),
+                ("NewEnabled", "1"),
+                ("NewPortMappingDescription", "nanoserve"),
+                ("NewLeaseDuration", &lifetime),
+            ],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Gets the external address of the router via `UPnP`.
+    async fn upnp_external_ip(&self) -> Result<Ipv4Addr, String> {
+        let response = self.upnp_call("GetExternalIPAddress", &[]).await?;
+        xml_element(&response, "NewExternalIPAddress")
+            .and_then(|ip| ip.trim().parse().ok())
+            .ok_or_else(|| "no external address in response".to_string())
+    }
+
+    /// Calls a `UPnP` action, returning the response body.
+    async fn upnp_call(&self, action: &str, args: &[(&str, &str)]) -> Result<String, String> {
+        let Method::Upnp {
+            control, service, ..
+        } = &self.method
+        else {
+            unreachable!("not a UPnP mapping");
+        };
+        let args = args.iter().fold(String::new(), |mut xml, (name, value)| {
+            let _ = write!(xml, "<{name}>{value}</{name}>");
+            xml
+        });
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body></s:Envelope>"
+        );
+        let headers = format!(
+            "Content-Type: text/xml; charset=\"utf-8\"\r\nSOAPAction: \"{service}#{action}\"\r\n"
+        );
+        let (status, body) = http_request("POST", control, &headers, &body).await?;
+        if status == 200 {
+            Ok(body)
+        } else {
+            let reason = xml_element(&body, "errorDescription").unwrap_or("unknown error");
+            Err(format!("{action} failed with status {status}: {reason}"))
+        }
+    }
+}
+
+/// Maps a TCP port via NAT-PMP, returning the external address.
+///
+/// A lifetime of zero removes the mapping, in which case the external port should be zero.
+async fn nat_pmp_map(
+    gateway: Ipv4Addr,
+    internal: u16,
+    external: u16,
+    lifetime: Duration,
+) -> Result<SocketAddrV4, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .map_err(|e| e.to_string())?;
+    socket
+        .connect(SocketAddr::V4(SocketAddrV4::new(gateway, NAT_PMP_PORT)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Public address request
+    let response = nat_pmp_request(&socket, vec![0, 0], 12).await?;
+    let ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+
+    // Mapping request for TCP
+    let lifetime = u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX);
+    let mut request = vec![0, 2, 0, 0];
+    request.extend_from_slice(&internal.to_be_bytes());
+    request.extend_from_slice(&external.to_be_bytes());
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    let response = nat_pmp_request(&socket, request, 16).await?;
+    let port = u16::from_be_bytes([response[10], response[11]]);
+    Ok(SocketAddrV4::new(ip, port))
+}
+
+/// Sends a NAT-PMP request, retrying with exponential backoff, and checks the response.
+async fn nat_pmp_request(
+    socket: &UdpSocket,
+    request: Vec<u8>,
+    len: usize,
+) -> Result<Vec<u8>, String> {
+    let opcode = request[1];
+    let mut request = request;
+    let mut wait = Duration::from_millis(250);
+    // Fewer attempts than the RFC's nine, as UPnP is still to be tried
+    for _ in 0..4 {
+        let BufResult(result, returned) = socket.send(request).await;
+        request = returned;
+        result.map_err(|e| e.to_string())?;
+        let Ok(BufResult(result, response)) =
+            timeout(wait, socket.recv(Vec::with_capacity(len))).await
+        else {
+            wait *= 2;
+            continue;
+        };
+        result.map_err(|e| e.to_string())?;
+        if response.len() < len || response[1] != opcode | 0x80 {
+            return Err("invalid response from gateway".to_string());
+        }
+        return match u16::from_be_bytes([response[2], response[3]]) {
+            0 => Ok(response),
+            code => Err(format!("gateway refused with result code {code}")),
+        };
+    }
+    Err("no response from gateway".to_string())
+}
+
+/// Get the default IPv4 gateway from the routing table.
+fn default_gateway() -> Option<Ipv4Addr> {
+    // Columns are interface, destination, gateway and more, with little-endian hex addresses
+    let table = fs::read_to_string("/proc/net/route").ok()?;
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        Some(Ipv4Addr::from(gateway.swap_bytes()))
+    })
+}
+
+/// Discover an internet gateway device via SSDP, returning the control URL and type of its
+/// port mapping service, and the local address used to reach it.
+async fn upnp_discover() -> Result<(String, &'static str, Ipv4Addr), String> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .map_err(|e| e.to_string())?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {SSDP}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n\
+         ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n"
+    );
+    let BufResult(result, _) = socket
+        .send_to(search.into_bytes(), SocketAddr::V4(SSDP))
+        .await;
+    result.map_err(|e| e.to_string())?;
+
+    let mut buffer = Vec::with_capacity(2048);
+    loop {
+        let Ok(BufResult(result, returned)) =
+            timeout(Duration::from_secs(3), socket.recv_from(buffer)).await
+        else {
+            return Err("no internet gateway device found".to_string());
+        };
+        buffer = returned;
+        result.map_err(|e| e.to_string())?;
+        let response = String::from_utf8_lossy(&buffer).into_owned();
+        buffer.clear();
+        let Some(location) = response.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        }) else {
+            continue;
+        };
+        match upnp_service(&location).await {
+            Ok(found) => return Ok(found),
+            Err(e) => eprintln!("Skipping UPnP device at {location}: {e}"),
+        }
+    }
+}
+
+/// Find the port mapping service in the description of a device.
+async fn upnp_service(location: &str) -> Result<(String, &'static str, Ipv4Addr), String> {
+    let (_, description) = http_request("GET", location, "", "").await?;
+    let (service, control) = UPNP_SERVICES
+        .iter()
+        .find_map(|service| {
+            let start = description.find(&format!("<serviceType>{service}</serviceType>"))?;
+            Some((*service, xml_element(&description[start..], "controlURL")?))
+        })
+        .ok_or("no port mapping service")?;
+    let control = if control.starts_with("http://") {
+        control.to_string()
+    } else {
+        let base = xml_element(&description, "URLBase").unwrap_or(location);
+        let (_, authority) = split_url(base)?;
+        format!("http://{authority}/{}", control.trim_start_matches('/'))
+    };
+    let (_, authority) = split_url(&control)?;
+    let stream = TcpStream::connect(authority)
+        .await
+        .map_err(|e| e.to_string())?;
+    let local = match stream.local_addr().map_err(|e| e.to_string())? {
+        SocketAddr::V4(addr) => *addr.ip(),
+        SocketAddr::V6(_) => return Err("gateway is only reachable over IPv6".to_string()),
+    };
+    Ok((control, service, local))
+}
+
+/// Send a HTTP/1.0 request, returning the status and body of the response.
+async fn http_request(
+    method: &str,
+    url: &str,
+    headers: &str,
+    body: &str,
+) -> Result<(u16, String), String> {
+    let (path, authority) = split_url(url)?;
+    let mut stream = TcpStream::connect(authority)
+        .await
+        .map_err(|e| e.to_string())?;
+    let request = format!(
+        "{method} {path} HTTP/1.0\r\nHost: {authority}\r\n{headers}Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    let BufResult(result, _) = stream.write_all(request.into_bytes()).await;
+    result.map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    loop {
+        let BufResult(result, chunk) = stream.read(Vec::with_capacity(8192)).await;
+        match result.map_err(|e| e.to_string())? {
+            0 => break,
+            _ => response.extend_from_slice(&chunk),
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or("invalid HTTP response")?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or("invalid HTTP response")?;
+    Ok((status, body.to_string()))
+}
+
+/// Split a `http://` URL into its path and authority.
+fn split_url(url: &str) -> Result<(&str, &str), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("unsupported URL {url}"))?;
+    Ok(rest
+        .find('/')
+        .map_or(("/", rest), |index| (&rest[index..], &rest[..index])))
+}
+
+/// Get the text of the first element with the given name, ignoring namespace prefixes.
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let (open, _) = xml.match_indices(&format!("{name}>")).find(|&(index, _)| {
+        // Either `<name>` or `<prefix:name>`
+        matches!(xml[..index].chars().next_back(), Some('<' | ':'))
+    })?;
+    let start = open + name.len() + 1;
+    let end = start + xml[start..].find('<')?;
+    Some(&xml[start..end])
+}
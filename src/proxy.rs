@@ -0,0 +1,399 @@
+//! Reverse proxy handler.
+
+use super::{
+    Handler, HandlerFuture, Request, Response, ResponseBody, ResponseCode, response::unfold,
+};
+use compio::{
+    BufResult,
+    io::{AsyncRead, AsyncWriteExt},
+    net::TcpStream,
+};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    fmt::Write,
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    mem::take,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Maximum size of the response head of the upstream server.
+const MAX_HEAD: usize = 64 * 1024;
+/// Size of each chunk read from the upstream server.
+pub const CHUNK_SIZE: usize = 8192;
+/// Hop-by-hop headers, which must not be forwarded in either direction.
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+];
+/// Default maximum number of idle connections kept to the upstream server.
+const MAX_IDLE: usize = 8;
+/// Default time idle connections are kept, shorter than the keep-alive timeout of common servers.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(4);
+/// Methods whose requests can be sent twice with the effect of once, so they can be retried.
+const IDEMPOTENT_METHODS: [&str; 6] = ["GET", "HEAD", "OPTIONS", "TRACE", "PUT", "DELETE"];
+
+/// A [`Handler`] forwarding requests to an upstream HTTP server.
+///
+/// The request path is forwarded as is, so that a proxy mounted at `/api` forwards `/api/users`
+/// to `/api/users` upstream. `Host` is rewritten to the upstream authority, and the original
+/// client is described by the `X-Forwarded-For`, `X-Forwarded-Host` and `X-Forwarded-Proto`
+/// headers. The response body is streamed back as it arrives.
+///
+/// Connections to the upstream server are kept alive and reused for later requests when the
+/// upstream server allows it, up to a number of idle connections set by [`Proxy::pool`]. Requests
+/// with idempotent methods, like `GET`, are retried on a new connection if a reused one was
+/// closed before answering.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    /// The upstream authority, e.g. `localhost:3000`.
+    upstream: String,
+    /// Idle connections to the upstream server, shared by clones of the proxy.
+    pool: Rc<Pool>,
+}
+
+/// Idle keep-alive connections to an upstream server.
+#[derive(Debug)]
+struct Pool {
+    /// Maximum number of idle connections, 0 to close connections after each response.
+    max_idle: usize,
+    /// Time after which idle connections are closed.
+    idle_timeout: Duration,
+    /// Idle connections with the time they were returned, the most recent last.
+    idle: RefCell<Vec<(TcpStream, Instant)>>,
+}
+
+impl Pool {
+    /// Takes the most recently used idle connection that has not timed out, if any.
+    fn take(&self) -> Option<TcpStream> {
+        let mut idle = self.idle.borrow_mut();
+        let now = Instant::now();
+        idle.retain(|(_, since)| now.duration_since(*since) < self.idle_timeout);
+        idle.pop().map(|(stream, _)| stream)
+    }
+
+    /// Returns a connection after a complete response, closing it if the pool is full.
+    fn give(&self, stream: TcpStream) {
+        let mut idle = self.idle.borrow_mut();
+        if idle.len() < self.max_idle {
+            idle.push((stream, Instant::now()));
+        }
+    }
+}
+
+impl Proxy {
+    /// Creates a proxy to the given upstream, like `localhost:3000` or `http://127.0.0.1:8000/`.
+    #[must_use]
+    pub fn new(upstream: &str) -> Self {
+        let upstream = upstream.strip_prefix("http://").unwrap_or(upstream);
+        Self {
+            upstream: upstream.trim_end_matches('/').to_string(),
+            pool: Rc::new(Pool {
+                max_idle: MAX_IDLE,
+                idle_timeout: IDLE_TIMEOUT,
+                idle: RefCell::default(),
+            }),
+        }
+    }
+
+    /// Keeps up to `max_idle` idle connections to the upstream server for `idle_timeout` each,
+    /// 8 for 4 seconds by default. A `max_idle` of 0 closes connections after each response.
+    #[must_use]
+    pub fn pool(mut self, max_idle: usize, idle_timeout: Duration) -> Self {
+        self.pool = Rc::new(Pool {
+            max_idle,
+            idle_timeout,
+            idle: RefCell::default(),
+        });
+        self
+    }
+
+    /// Get the upstream authority.
+    #[must_use]
+    pub fn upstream(&self) -> &str {
+        &self.upstream
+    }
+
+    /// Forward the request and read the response head.
+    async fn forward(&self, request: &Request<'_>) -> IoResult<Response> {
+        let head = self.request_head(request);
+        if let Some(stream) = self.pool.take() {
+            // The upstream server may have closed the idle connection meanwhile, so failures
+            // before any of the response arrived are retried on a new connection, as long as
+            // the upstream server could not have acted twice on the request
+            match self.exchange(stream, head.clone(), request).await {
+                Ok(response) => return Ok(response),
+                Err(Failure::Unanswered(_)) if IDEMPOTENT_METHODS.contains(&request.method) => {}
+                Err(failure) => return Err(failure.into()),
+            }
+        }
+        let stream = TcpStream::connect(self.upstream.as_str()).await?;
+        Ok(self.exchange(stream, head, request).await?)
+    }
+
+    /// Send the request on a connection and read the response head.
+    async fn exchange(
+        &self,
+        mut stream: TcpStream,
+        head: String,
+        request: &Request<'_>,
+    ) -> Result<Response, Failure> {
+        stream
+            .write_all(head)
+            .await
+            .0
+            .map_err(Failure::Unanswered)?;
+        if !request.body.is_empty() {
+            let body = request.body.to_vec();
+            stream
+                .write_all(body)
+                .await
+                .0
+                .map_err(Failure::Unanswered)?;
+        }
+        let BufResult(result, first) = stream.read(Vec::with_capacity(CHUNK_SIZE)).await;
+        if result.map_err(Failure::Unanswered)? == 0 {
+            let e = invalid("connection closed before the response head");
+            return Err(Failure::Unanswered(e));
+        }
+
+        let head = read_response_head(&mut stream, first)
+            .await
+            .map_err(Failure::Answered)?;
+        // Without a known length, the end of the body is only marked by closing the connection
+        let remaining = if request.method == "HEAD" || matches!(head.code, 100..200 | 204 | 304) {
+            Some(0)
+        } else {
+            head.length
+        };
+        let pool = (head.keep_alive && remaining.is_some()).then(|| self.pool.clone());
+        Ok(Response {
+            code: head.code.into(),
+            headers: head.headers,
+            body: ResponseBody::Stream(unfold(
+                Body {
+                    stream,
+                    first: head.rest,
+                    remaining,
+                    pool,
+                },
+                Body::next,
+            )),
+            chunk_size: None,
+        })
+    }
+
+    /// Build the head of the request forwarded upstream.
+    fn request_head(&self, request: &Request<'_>) -> String {
+        // HTTP/1.0 keeps the upstream from using chunked encoding, and only keeps the connection
+        // alive when asked to
+        let mut head = format!(
+            "{} {} HTTP/1.0\r\nHost: {}\r\n",
+            request.method, request.path, self.upstream
+        );
+        let mut forwarded_for = None;
+        let mut host = None;
+        for &(name, value) in &request.headers {
+            let lower = name.to_ascii_lowercase();
+            match lower.as_str() {
+                "host" => host = Some(value),
+                "x-forwarded-for" => forwarded_for = Some(value),
+                "content-length" | "x-forwarded-host" | "x-forwarded-proto" | "upgrade" => {}
+                _ if HOP_BY_HOP.contains(&lower.as_str()) => {}
+                _ => {
+                    let _ = write!(head, "{name}: {value}\r\n");
+                }
+            }
+        }
+        if let Some(peer) = request.peer {
+            let client = peer.ip();
+            let _ = match forwarded_for {
+                Some(previous) => write!(head, "X-Forwarded-For: {previous}, {client}\r\n"),
+                None => write!(head, "X-Forwarded-For: {client}\r\n"),
+            };
+        }
+        if let Some(host) = host {
+            let _ = write!(head, "X-Forwarded-Host: {host}\r\n");
+        }
+        head.push_str("X-Forwarded-Proto: http\r\n");
+        if self.pool.max_idle > 0 {
+            head.push_str("Connection: keep-alive\r\n");
+        } else {
+            head.push_str("Connection: close\r\n");
+        }
+        if !request.body.is_empty() {
+            let _ = write!(head, "Content-Length: {}\r\n", request.body.len());
+        }
+        head.push_str("\r\n");
+        head
+    }
+}
+
+impl Handler for Proxy {
+    fn handle<'a>(&'a self, request: &'a Request<'_>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            Some(self.forward(request).await.unwrap_or_else(|e| {
+                eprintln!("Error while proxying to {}: {e}", self.upstream);
+                Response::new(ResponseCode::BadGateway, "502 Bad Gateway")
+            }))
+        })
+    }
+}
+
+/// Build an error for a malformed upstream response.
+fn invalid(message: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("upstream: {message}"))
+}
+
+/// A failed exchange with the upstream server.
+enum Failure {
+    /// Nothing of the response was received.
+    Unanswered(IoError),
+    /// The response was partly received, or is malformed.
+    Answered(IoError),
+}
+
+impl From<Failure> for IoError {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Unanswered(e) | Failure::Answered(e) => e,
+        }
+    }
+}
+
+/// The response head of the upstream server.
+struct Head {
+    /// The status code.
+    code: u16,
+    /// The end-to-end headers.
+    headers: Vec<(Cow<'static, str>, String)>,
+    /// The bytes of the body read along with the head.
+    rest: Vec<u8>,
+    /// The `Content-Length` of the body, if any.
+    length: Option<u64>,
+    /// Whether the upstream server keeps the connection open after the response.
+    keep_alive: bool,
+}
+
+/// Read the response head of the upstream server, returning the status code, the end-to-end
+/// headers and the bytes of the body read along with the head.
+pub async fn read_head(
+    stream: &mut TcpStream,
+) -> IoResult<(u16, Vec<(Cow<'static, str>, String)>, Vec<u8>)> {
+    let head = read_response_head(stream, Vec::new()).await?;
+    Ok((head.code, head.headers, head.rest))
+}
+
+/// Read the response head of the upstream server, whose first bytes may be in `buffer` already.
+async fn read_response_head(stream: &mut TcpStream, mut buffer: Vec<u8>) -> IoResult<Head> {
+    let end = loop {
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if buffer.len() > MAX_HEAD {
+            return Err(invalid("response head too large"));
+        }
+        let BufResult(result, chunk) = stream.read(Vec::with_capacity(CHUNK_SIZE)).await;
+        if result? == 0 {
+            return Err(invalid("connection closed before the response head"));
+        }
+        buffer.extend_from_slice(&chunk);
+    };
+    let rest = buffer.split_off(end);
+    let head = String::from_utf8(buffer).map_err(|_| invalid("response head is not UTF-8"))?;
+    let mut lines = head.lines();
+    let code = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+    let mut headers = Vec::new();
+    let mut length = None;
+    let mut keep_alive = false;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let (name, value) = (name.trim(), value.trim());
+        let lower = name.to_ascii_lowercase();
+        match lower.as_str() {
+            "connection" => {
+                keep_alive = value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("keep-alive"));
+            }
+            "content-length" => length = value.parse().ok(),
+            _ => {}
+        }
+        if !HOP_BY_HOP.contains(&lower.as_str()) {
+            headers.push((Cow::Owned(name.to_string()), value.to_string()));
+        }
+    }
+    Ok(Head {
+        code,
+        headers,
+        rest,
+        length,
+        keep_alive,
+    })
+}
+
+/// A response body being read from the upstream server.
+struct Body {
+    /// The connection to the upstream server.
+    stream: TcpStream,
+    /// The bytes of the body read along with the head.
+    first: Vec<u8>,
+    /// Number of bytes of the body left to read, if known.
+    remaining: Option<u64>,
+    /// The pool to return the connection to once the body is read, if it can be reused.
+    pool: Option<Rc<Pool>>,
+}
+
+impl Body {
+    /// Read the next chunk of the body, returning the connection to the pool at its end.
+    async fn next(mut self) -> Option<(Vec<u8>, Self)> {
+        if self.remaining == Some(0) {
+            if let Some(pool) = self.pool {
+                pool.give(self.stream);
+            }
+            return None;
+        }
+        let mut chunk = if self.first.is_empty() {
+            let BufResult(result, chunk) = self.stream.read(Vec::with_capacity(CHUNK_SIZE)).await;
+            match result {
+                Ok(n) if n > 0 => chunk,
+                _ => return None,
+            }
+        } else {
+            take(&mut self.first)
+        };
+        if let Some(remaining) = &mut self.remaining {
+            // Bytes past the announced length leave the connection in an unknown state
+            if chunk.len() as u64 > *remaining {
+                chunk.truncate(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                self.pool = None;
+            }
+            *remaining -= chunk.len() as u64;
+        }
+        Some((chunk, self))
+    }
+}
+
+/// Read the next chunk of the response body, starting with the bytes read along with the head.
+pub async fn read_body(
+    (mut stream, first): (TcpStream, Vec<u8>),
+) -> Option<(Vec<u8>, (TcpStream, Vec<u8>))> {
+    if !first.is_empty() {
+        return Some((first, (stream, Vec::new())));
+    }
+    let BufResult(result, chunk) = stream.read(Vec::with_capacity(CHUNK_SIZE)).await;
+    match result {
+        Ok(n) if n > 0 => Some((chunk, (stream, Vec::new()))),
+        _ => None,
+    }
+}
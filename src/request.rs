@@ -1,11 +1,21 @@
 //! Request parsing module.
 
+use super::{MediaType, ResponseCode, http1, multipart::Multipart, urlencoded};
 use std::{
+    borrow::Cow,
     fmt,
+    net::SocketAddr,
     num::ParseIntError,
+    ops::Deref,
+    slice,
     str::{Utf8Error, from_utf8},
 };
 
+/// Number of headers stored without allocating, enough for typical requests.
+const INLINE_HEADERS: usize = 32;
+/// Maximum number of headers of a request, unless configured otherwise.
+pub const MAX_HEADERS: usize = 100;
+
 /// An HTTP request.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Request<'a> {
@@ -16,15 +26,90 @@ pub struct Request<'a> {
     /// The HTTP version.
     pub version: &'a str,
     /// The headers.
-    pub headers: Vec<(&'a str, &'a str)>,
-    /// The body.
+    pub headers: RequestHeaders<'a>,
+    /// The body, complete and decoded from its transfer coding.
     pub body: &'a [u8],
+    /// The address of the client, if known.
+    pub peer: Option<SocketAddr>,
+}
+
+/// Headers of a [`Request`], stored inline up to a typical count so that parsing does not
+/// allocate, and on the heap beyond.
+#[derive(Clone, Default)]
+pub struct RequestHeaders<'a> {
+    /// The first headers.
+    inline: [(&'a str, &'a str); INLINE_HEADERS],
+    /// Number of headers in `inline`.
+    len: usize,
+    /// All headers, once there are too many for `inline`.
+    spilled: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> RequestHeaders<'a> {
+    /// Append a header.
+    pub fn push(&mut self, header: (&'a str, &'a str)) {
+        if self.spilled.is_empty() && self.len < INLINE_HEADERS {
+            self.inline[self.len] = header;
+            self.len += 1;
+        } else {
+            if self.spilled.is_empty() {
+                self.spilled.extend_from_slice(&self.inline);
+            }
+            self.spilled.push(header);
+        }
+    }
+}
+
+impl<'a> Deref for RequestHeaders<'a> {
+    type Target = [(&'a str, &'a str)];
+
+    fn deref(&self) -> &Self::Target {
+        if self.spilled.is_empty() {
+            &self.inline[..self.len]
+        } else {
+            &self.spilled
+        }
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b RequestHeaders<'a> {
+    type Item = &'b (&'a str, &'a str);
+    type IntoIter = slice::Iter<'b, (&'a str, &'a str)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> FromIterator<(&'a str, &'a str)> for RequestHeaders<'a> {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a str)>>(iter: I) -> Self {
+        let mut headers = Self::default();
+        for header in iter {
+            headers.push(header);
+        }
+        headers
+    }
+}
+
+impl PartialEq for RequestHeaders<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for RequestHeaders<'_> {}
+
+impl fmt::Debug for RequestHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
 }
 
 /// Range header representation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RangeHeader {
-    /// A valid range with start and end.
+    /// A range from the first to the last byte included, like `bytes=0-499`, open-ended without
+    /// the last, or the length of a suffix without the first, like `bytes=-500`.
     Bytes(Option<u64>, Option<u64>),
     /// Invalid or unsupported range format.
     Invalid,
@@ -39,29 +124,60 @@ pub enum ParseRequestError {
     InvalidRequestLine,
     /// The packet header is not properly encoded in UTF-8.
     InvalidUtf8,
+    /// The request has more headers than allowed.
+    TooManyHeaders,
+    /// A header field is malformed, like a name with spaces or a value with control characters.
+    InvalidHeader,
+    /// A header value is continued on the next line, which is obsolete. See [`Request::unfold`].
+    ObsoleteFold,
+    /// The request target contains control characters, like NUL bytes.
+    InvalidTarget,
+    /// The `Host` header is not a valid authority, or is repeated with different values.
+    InvalidHost,
+    /// The chunked request body is malformed.
+    InvalidChunk,
+    /// The request body is longer than allowed.
+    BodyTooLarge,
+    /// The request body ends before it is complete.
+    IncompleteBody,
+    /// The `Content-Length` header is invalid, repeated with different values, or sent along
+    /// with `Transfer-Encoding`.
+    InvalidContentLength,
+    /// The request body has a transfer coding other than `chunked`.
+    UnsupportedTransferEncoding,
+    /// The `multipart/form-data` request body is malformed.
+    InvalidMultipart,
+    /// The request head is larger than allowed.
+    HeadTooLarge,
+    /// The request was not received completely in time.
+    RequestTimeout,
+    /// A line of the request head ends with a bare CR or LF. See [`Request::check_line_endings`].
+    BareLineEnding,
     /// IO error while reading lines.
     IoError,
 }
 
 impl<'a> Request<'a> {
-    /// Parses a raw HTTP request.
+    /// Parses a raw HTTP request, with at most [`MAX_HEADERS`] headers.
+    ///
+    /// The request is taken as complete, with everything after the head as its body. See
+    /// [`http1::Parser`] to parse requests as they arrive instead.
     ///
     /// # Errors
     ///
     /// See [`ParseRequestError`].
     pub fn parse(request: &'a [u8]) -> Result<Self, ParseRequestError> {
+        Self::parse_limited(request, MAX_HEADERS)
+    }
+
+    /// Parses a raw HTTP request, with at most `max_headers` headers.
+    ///
+    /// # Errors
+    ///
+    /// See [`ParseRequestError`].
+    pub fn parse_limited(request: &'a [u8], max_headers: usize) -> Result<Self, ParseRequestError> {
         // Find the header/body separator in raw bytes (double CRLF or double LF)
-        let separator = request
-            .windows(4)
-            .position(|w| w == b"\r\n\r\n")
-            .map(|pos| pos + 4)
-            .or_else(|| {
-                request
-                    .windows(2)
-                    .position(|w| w == b"\n\n")
-                    .map(|pos| pos + 2)
-            })
-            .unwrap_or(request.len());
+        let separator = http1::head_len(request).unwrap_or(request.len());
 
         // Split header and data at byte level
         let header_bytes = &request[..separator.min(request.len())];
@@ -84,9 +200,43 @@ impl<'a> Request<'a> {
         let version = version_part
             .strip_prefix("HTTP/")
             .ok_or(ParseRequestError::InvalidRequestLine)?;
+        if path.chars().any(char::is_control) {
+            return Err(ParseRequestError::InvalidTarget);
+        }
+        let (authority, path) = split_absolute(path)?;
 
         // Parse headers
-        let headers = Self::parse_headers(&mut lines);
+        let mut headers = Self::parse_headers(&mut lines, max_headers)?;
+        // Requests with several hosts could be routed for one and answered for another
+        let mut hosts = headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Host"))
+            .map(|&(_, host)| host);
+        let first = hosts.next();
+        let mut repeated = false;
+        for host in hosts {
+            if !first.is_some_and(|first| first.eq_ignore_ascii_case(host)) {
+                return Err(ParseRequestError::InvalidHost);
+            }
+            repeated = true;
+        }
+        // The authority of an absolute-form target replaces the `Host` header, like in HTTP/2
+        let host = authority.or(first);
+        if host.is_some_and(|host| !is_authority(host)) {
+            return Err(ParseRequestError::InvalidHost);
+        }
+        if authority.is_some() || repeated {
+            headers = host
+                .map(|host| ("Host", host))
+                .into_iter()
+                .chain(
+                    headers
+                        .iter()
+                        .copied()
+                        .filter(|(key, _)| !key.eq_ignore_ascii_case("Host")),
+                )
+                .collect();
+        }
 
         Ok(Self {
             method,
@@ -94,22 +244,84 @@ impl<'a> Request<'a> {
             version,
             headers,
             body,
+            peer: None,
         })
     }
 
-    /// Parse HTTP headers from lines.
-    fn parse_headers<'b>(lines: &mut impl Iterator<Item = &'b str>) -> Vec<(&'b str, &'b str)> {
-        let mut headers = Vec::new();
+    /// Checks that the lines of the head of a raw request all end with CRLF, as required by
+    /// RFC 9112. Parsing also accepts bare LF, which proxies may read differently.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseRequestError::BareLineEnding`] on a bare CR or LF.
+    pub fn check_line_endings(request: &[u8]) -> Result<(), ParseRequestError> {
+        let end = request
+            .windows(3)
+            .position(|w| w.starts_with(b"\n\n") || w == b"\n\r\n")
+            .map_or(request.len(), |pos| pos + 2);
+        let head = &request[..end.min(request.len())];
+        let bare = head.iter().enumerate().any(|(i, &b)| match b {
+            b'\n' => i == 0 || head[i - 1] != b'\r',
+            b'\r' => head.get(i + 1).is_some_and(|&next| next != b'\n'),
+            _ => false,
+        });
+        if bare {
+            Err(ParseRequestError::BareLineEnding)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replaces the line breaks of header values continued on lines starting with whitespace
+    /// (obs-fold) with spaces, in the head of a raw request, as allowed by RFC 9112. Parsing
+    /// rejects such requests otherwise.
+    pub fn unfold(request: &mut [u8]) {
+        let Some(first) = request.iter().position(|&b| b == b'\n') else {
+            return;
+        };
+        // Lines right after the request line do not continue a header, and stay rejected
+        let mut start = first + 1;
+        while let Some(len) = request[start..].iter().position(|&b| b == b'\n') {
+            let end = start + len;
+            if matches!(&request[start..end], b"" | b"\r") {
+                return; // End of headers
+            }
+            if start > first + 1 && matches!(request[start], b' ' | b'\t') {
+                request[start - 1] = b' ';
+                if request[start - 2] == b'\r' {
+                    request[start - 2] = b' ';
+                }
+            }
+            start = end + 1;
+        }
+    }
+
+    /// Parse HTTP headers from lines, rejecting fields that do not follow RFC 9110.
+    fn parse_headers<'b>(
+        lines: &mut impl Iterator<Item = &'b str>,
+        max_headers: usize,
+    ) -> Result<RequestHeaders<'b>, ParseRequestError> {
+        let mut headers = RequestHeaders::default();
         for line in lines {
-            let line = line.trim();
             if line.is_empty() {
                 break; // End of headers
             }
-            if let Some((key, value)) = line.split_once(':') {
-                headers.push((key.trim(), value.trim()));
+            if line.starts_with([' ', '\t']) {
+                return Err(ParseRequestError::ObsoleteFold);
+            }
+            // The name is a token right before the colon, with no whitespace around it
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(ParseRequestError::InvalidHeader)?;
+            if !is_token(key) || !is_field_value(value) {
+                return Err(ParseRequestError::InvalidHeader);
+            }
+            if headers.len() >= max_headers {
+                return Err(ParseRequestError::TooManyHeaders);
             }
+            headers.push((key, value.trim_matches([' ', '\t'])));
         }
-        headers
+        Ok(headers)
     }
 
     /// Parse the `Range` header, if present.
@@ -142,6 +354,205 @@ impl<'a> Request<'a> {
         RangeHeader::None
     }
 
+    /// Get the authority the request is for, like `example.com:8080`, from its `Host` header,
+    /// which parsing made unique and valid.
+    #[must_use]
+    pub fn host(&self) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Host"))
+            .map(|&(_, host)| host)
+    }
+
+    /// Get the decoded name and value pairs of the query string, like `page=2&sort=name`.
+    #[must_use]
+    pub fn query_pairs(&self) -> Vec<(Cow<'a, str>, Cow<'a, str>)> {
+        self.path
+            .split_once('?')
+            .map_or_else(Vec::new, |(_, query)| urlencoded::parse(query))
+    }
+
+    /// Deserialize the query string into `T`, like a struct with `page` and `sort` fields for
+    /// `?page=2&sort=name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`](serde_core::de::value::Error) if a field is missing or a value cannot
+    /// be parsed as the type of its field.
+    #[cfg(feature = "serde")]
+    pub fn query<T: serde_core::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, serde_core::de::value::Error> {
+        urlencoded::deserialize(self.query_pairs())
+    }
+
+    /// Get the media type of the body, from the `Content-Type` header, or `None` if it is missing
+    /// or invalid.
+    #[must_use]
+    pub fn content_type(&self) -> Option<MediaType<'a>> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+            .and_then(|&(_, value)| MediaType::parse(value))
+    }
+
+    /// Get the decoded name and value pairs of an `application/x-www-form-urlencoded` body, or
+    /// `None` if the request has another `Content-Type` or its body is not UTF-8.
+    #[must_use]
+    pub fn form(&self) -> Option<Vec<(Cow<'a, str>, Cow<'a, str>)>> {
+        let is_form = self
+            .content_type()
+            .is_some_and(|media_type| media_type.is("application/x-www-form-urlencoded"));
+        if !is_form {
+            return None;
+        }
+        from_utf8(self.body).ok().map(urlencoded::parse)
+    }
+
+    /// Deserialize an `application/x-www-form-urlencoded` body into `T`, like a struct with
+    /// `name` and `email` fields for `name=Ada&email=ada%40example.com`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`](serde_core::de::value::Error) if the body is not such a form, a field
+    /// is missing or a value cannot be parsed as the type of its field.
+    #[cfg(feature = "serde")]
+    pub fn form_as<T: serde_core::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, serde_core::de::value::Error> {
+        let pairs = self.form().ok_or_else(|| {
+            serde_core::de::Error::custom("request body is not a urlencoded form")
+        })?;
+        urlencoded::deserialize(pairs)
+    }
+
+    /// Iterate over the parts of a `multipart/form-data` body, or get `None` if the request has
+    /// another `Content-Type`.
+    #[must_use]
+    pub fn multipart(&self) -> Option<Multipart<'a>> {
+        Multipart::new(self)
+    }
+
+    /// Get the name and value pairs of the `Cookie` headers, in order, removing the quotes
+    /// around quoted values. Pairs without `=` or a name are skipped.
+    #[must_use]
+    pub fn cookies(&self) -> Vec<(&'a str, &'a str)> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Cookie"))
+            .flat_map(|(_, value)| value.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let (name, value) = (name.trim(), value.trim());
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                (!name.is_empty()).then_some((name, value))
+            })
+            .collect()
+    }
+
+    /// Check whether the `If-None-Match` header lists the entity tag `etag`, or any with `*`.
+    /// Tags are compared weakly, ignoring the `W/` prefix.
+    #[must_use]
+    pub fn matches_etag(&self, etag: &str) -> bool {
+        let etag = etag.trim_start_matches("W/");
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("If-None-Match"))
+            .flat_map(|(_, value)| value.split(','))
+            .any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+    }
+
+    /// Pick the content coding the `Accept-Encoding` header prefers among `available`, listed in
+    /// the order of preference of the server.
+    ///
+    /// Codings are weighted by their q-values, `*` weights the codings not listed, and `identity`
+    /// is acceptable unless excluded with `q=0`. Without the header, only `identity` is, as
+    /// clients omitting it may not decode others. Returns `None` if no coding is acceptable.
+    ///
+    /// Responses selected this way must name `Accept-Encoding` with [`Response::add_vary`](crate::Response::add_vary).
+    #[must_use]
+    pub fn preferred_encoding<'e>(&self, available: &[&'e str]) -> Option<&'e str> {
+        let codings = self.qualities("Accept-Encoding");
+        let listed = |name: &str| {
+            codings
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(name))
+                .map(|&(_, quality)| quality)
+        };
+        let mut preferred = None;
+        for &name in available {
+            let quality = listed(name)
+                .or_else(|| listed("*"))
+                .unwrap_or_else(|| u16::from(name.eq_ignore_ascii_case("identity")));
+            if quality > preferred.map_or(0, |(_, best)| best) {
+                preferred = Some((name, quality));
+            }
+        }
+        preferred.map(|(name, _)| name)
+    }
+
+    /// Pick the language the `Accept-Language` header prefers among the tags `available`, like
+    /// `de` or `en-US`, to select a localized page.
+    ///
+    /// Ranges match the tags they are a prefix of, so `de` matches `de-CH`, and the tags they
+    /// refine, so `de-CH` matches `de`. A tag is weighted by its most specific matching range.
+    /// Returns `None` if no language is acceptable, to fall back to a default page.
+    ///
+    /// Responses selected this way must name `Accept-Language` with [`Response::add_vary`](crate::Response::add_vary).
+    #[must_use]
+    pub fn preferred_language<'l>(&self, available: &[&'l str]) -> Option<&'l str> {
+        let ranges = self.qualities("Accept-Language");
+        let mut preferred = None;
+        for &tag in available {
+            let quality = ranges
+                .iter()
+                .filter(|(range, _)| {
+                    *range == "*"
+                        || is_language_prefix(range, tag)
+                        || is_language_prefix(tag, range)
+                })
+                .max_by_key(|(range, _)| match *range {
+                    "*" => 0,
+                    range if range.eq_ignore_ascii_case(tag) => usize::MAX,
+                    range => range.len(),
+                })
+                .map_or(0, |&(_, quality)| quality);
+            if quality > preferred.map_or(0, |(_, best)| best) {
+                preferred = Some((tag, quality));
+            }
+        }
+        preferred.map(|(tag, _)| tag)
+    }
+
+    /// Get the values listed by the header `name` with their q-values in thousandths, skipping
+    /// values with invalid q-values.
+    fn qualities(&self, name: &str) -> Vec<(&'a str, u16)> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .filter_map(|item| {
+                let mut params = item.split(';');
+                let value = params.next()?.trim();
+                let quality = params
+                    .find_map(|param| {
+                        let (key, quality) = param.split_once('=')?;
+                        key.trim()
+                            .eq_ignore_ascii_case("q")
+                            .then(|| parse_quality(quality.trim()))
+                    })
+                    .unwrap_or(Some(1000))?;
+                (!value.is_empty()).then_some((value, quality))
+            })
+            .collect()
+    }
+
     /// Helper to parse an optional u64 from a &str.
     fn parse_optional(s: &str) -> Result<Option<u64>, ParseIntError> {
         if s.is_empty() {
@@ -164,6 +575,73 @@ impl fmt::Display for Request<'_> {
     }
 }
 
+/// Split an absolute-form request target like `http://host:8080/path`, sent by some proxies and
+/// tools, into its authority and path. Other targets have no authority.
+fn split_absolute(target: &str) -> Result<(Option<&str>, &str), ParseRequestError> {
+    // Paths may contain `://` too, like `/redirect?to=http://example.com`
+    let Some((scheme, rest)) = target
+        .split_once("://")
+        .filter(|_| !target.starts_with('/'))
+    else {
+        return Ok((None, target));
+    };
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return Err(ParseRequestError::InvalidRequestLine);
+    }
+    let (authority, path) = rest
+        .find('/')
+        .map_or((rest, "/"), |index| rest.split_at(index));
+    // A query right after the authority, like `http://host?query`, has no path to borrow
+    if authority.is_empty() || authority.contains('?') {
+        return Err(ParseRequestError::InvalidRequestLine);
+    }
+    Ok((Some(authority), path))
+}
+
+/// Parse a q-value like `0.5` in thousandths.
+fn parse_quality(s: &str) -> Option<u16> {
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = fraction
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(3)
+        .fold(0, |quality, digit| quality * 10 + u16::from(digit - b'0'));
+    match integer {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// Check whether the language tag `prefix` is `tag` or a prefix of its subtags.
+fn is_language_prefix(prefix: &str, tag: &str) -> bool {
+    tag.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        && matches!(tag.as_bytes().get(prefix.len()), None | Some(b'-'))
+}
+
+/// Check whether `s` is a valid authority, with a host name or IP address and an optional port,
+/// and no user information.
+fn is_authority(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"-._~%!$&'()*+,;=:[]".contains(&b))
+}
+
+/// Check whether `s` is a token, like a header name.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Check whether `s` is a valid header value, without control characters other than tabs.
+fn is_field_value(s: &str) -> bool {
+    s.bytes().all(|b| b == b'\t' || !b.is_ascii_control())
+}
+
 impl ParseRequestError {
     /// Get a description of the error.
     #[must_use]
@@ -171,17 +649,47 @@ impl ParseRequestError {
         match self {
             Self::InvalidRequestLine => "Invalid request line",
             Self::InvalidUtf8 => "Invalid UTF-8 in request",
+            Self::TooManyHeaders => "Too many headers in request",
+            Self::InvalidHeader => "Invalid header field in request",
+            Self::ObsoleteFold => "Obsolete line folding in request headers",
+            Self::InvalidTarget => "Invalid characters in request target",
+            Self::BareLineEnding => "Bare CR or LF in request head",
+            Self::InvalidHost => "Invalid or conflicting Host headers in request",
+            Self::InvalidChunk => "Invalid chunked request body",
+            Self::BodyTooLarge => "Request body too large",
+            Self::IncompleteBody => "Request body ended early",
+            Self::InvalidContentLength => "Invalid or conflicting Content-Length in request",
+            Self::UnsupportedTransferEncoding => "Unsupported transfer coding in request",
+            Self::InvalidMultipart => "Invalid multipart request body",
+            Self::HeadTooLarge => "Request head too large",
+            Self::RequestTimeout => "Request not received in time",
             Self::IoError => "IO error while reading request",
         }
     }
 }
 
+impl ParseRequestError {
+    /// Get the status of the response answering a request that failed with this error.
+    #[must_use]
+    pub const fn status(self) -> ResponseCode {
+        match self {
+            Self::RequestTimeout => ResponseCode::RequestTimeout,
+            Self::TooManyHeaders | Self::HeadTooLarge => ResponseCode::RequestHeaderFieldsTooLarge,
+            Self::BodyTooLarge => ResponseCode::ContentTooLarge,
+            Self::UnsupportedTransferEncoding => ResponseCode::NotImplemented,
+            _ => ResponseCode::BadRequest,
+        }
+    }
+}
+
 impl fmt::Display for ParseRequestError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.description())
     }
 }
 
+impl std::error::Error for ParseRequestError {}
+
 impl From<Utf8Error> for ParseRequestError {
     fn from(_: Utf8Error) -> Self {
         Self::InvalidUtf8
@@ -1,51 +1,271 @@
 //! Response module for Nanoserve HTTP server.
 
-use super::{RangeHeader, Request};
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+use super::sendfile;
+use super::{
+    Cookie, FileChanged, RangeHeader, Request, ServerConfig, decode_path, http1, pool,
+    throttle::Throttled,
+};
 use compio::{
+    BufResult,
+    buf::{IntoInner, IoBuf},
     fs::File,
-    io::{AsyncReadAt, AsyncWriteExt},
+    io::{AsyncReadAt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use futures_core::Stream;
+#[cfg(target_os = "linux")]
+use socket2::SockRef;
+#[cfg(unix)]
+use std::os::fd::AsFd;
+#[cfg(windows)]
+use std::os::windows::io::AsHandle;
+use std::{
+    borrow::Cow,
+    fmt,
+    future::{Future, poll_fn},
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+    path::Path,
+    pin::{Pin, pin},
+    task::{Context, Poll, Waker},
 };
-use std::{io::Result as IoResult, path::Path};
 
 /// An HTTP response.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Response {
     /// The response code.
     pub code: ResponseCode,
+    /// Additional response headers.
+    pub headers: Vec<(Cow<'static, str>, String)>,
     /// The response body.
     pub body: ResponseBody,
+    /// Preferred size of the writes of a streaming body, or `None` for the size files are read
+    /// in. Chunks are gathered up to this size, but written as soon as no more are ready.
+    pub chunk_size: Option<usize>,
+}
+
+/// Sizes of the chunks files are read and written in.
+///
+/// Transfers start with chunks of `initial` bytes, doubling up to `max` bytes while chunks are
+/// read in full, so that small files take little memory and large ones few system calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSize {
+    /// Size of the first chunk.
+    pub initial: usize,
+    /// Size chunks may grow up to.
+    pub max: usize,
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        Self {
+            initial: 8 * 1024,
+            max: 256 * 1024,
+        }
+    }
 }
 
 /// Response codes used by Nanoserve.
-#[repr(u16)]
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResponseCode {
     /// 200 OK
     #[default]
-    Ok = 200,
+    Ok,
+    /// 204 No Content
+    NoContent,
     /// 206 Partial Content
-    PartialContent = 206,
+    PartialContent,
+    /// 301 Moved Permanently
+    MovedPermanently,
+    /// 302 Found
+    Found,
+    /// 304 Not Modified
+    NotModified,
     /// 400 Bad Request
-    BadRequest = 400,
+    BadRequest,
+    /// 401 Unauthorized
+    Unauthorized,
     /// 404 Not Found
-    NotFound = 404,
+    NotFound,
     /// 405 Method Not Allowed
-    MethodNotAllowed = 405,
+    MethodNotAllowed,
+    /// 408 Request Timeout
+    RequestTimeout,
+    /// 413 Content Too Large
+    ContentTooLarge,
     /// 416 Range Not Satisfiable
-    RangeNotSatisfiable = 416,
-    // /// 500 Internal Server Error
-    // InternalServerError = 500,
+    RangeNotSatisfiable,
+    /// 431 Request Header Fields Too Large
+    RequestHeaderFieldsTooLarge,
+    /// 500 Internal Server Error
+    InternalServerError,
+    /// 501 Not Implemented
+    NotImplemented,
+    /// 502 Bad Gateway
+    BadGateway,
+    /// 503 Service Unavailable
+    ServiceUnavailable,
+    /// Any other code, e.g. relayed from an upstream server.
+    Other(u16),
+}
+
+/// Typical capacity needed for the start line and headers.
+const HEAD_LEN: usize = 512;
+/// Maximum length of in-memory bodies written along with the head.
+const INLINE_BODY_LEN: usize = 16 * 1024;
+/// Methods files are served for.
+const FILE_METHODS: [&str; 2] = ["GET", "HEAD"];
+
+/// A stream of body chunks.
+pub type BodyStream = Pin<Box<dyn Stream<Item = Vec<u8>>>>;
+
+/// Get the byte range requested of content of the given size, `None` for the whole content, or
+/// the error response for an invalid range.
+///
+/// Ranges only apply to `GET`, and to `HEAD` describing the same response without its body, so
+/// the `Range` header of other methods is ignored.
+pub fn byte_range(request: &Request<'_>, size: u64) -> Result<Option<(u64, u64)>, Response> {
+    if !matches!(request.method, "GET" | "HEAD") {
+        return Ok(None);
+    }
+    let invalid = || Response::new(ResponseCode::BadRequest, "Invalid Range Header");
+    match request.parse_range_header() {
+        RangeHeader::Bytes(first, last) => {
+            // Validate range, telling clients the size so that they can retry or resume
+            let unsatisfiable = |body| {
+                Response::new(ResponseCode::RangeNotSatisfiable, body)
+                    .with_header("Content-Range", format!("bytes */{size}"))
+            };
+            let (start, end) = match (first, last) {
+                (Some(first), Some(last)) if last < first => {
+                    return Err(unsatisfiable("Start byte must not exceed end byte"));
+                }
+                // The last byte is included, and may be past the end of the content
+                (Some(first), last) => (
+                    first,
+                    last.map_or(size, |last| last.saturating_add(1).min(size)),
+                ),
+                (None, Some(suffix)) => (size - suffix.min(size), size),
+                (None, None) => return Err(invalid()),
+            };
+            if start >= end {
+                Err(unsatisfiable("Range is outside of the content"))
+            } else {
+                Ok(Some((start, end)))
+            }
+        }
+        RangeHeader::Invalid => Err(invalid()),
+        RangeHeader::None => Ok(None),
+    }
+}
+
+/// Get the response to requests with methods other than those files are served for.
+pub fn file_method_not_allowed() -> Response {
+    Response::new(ResponseCode::MethodNotAllowed, "405 Method Not Allowed")
+        .with_header("Allow", FILE_METHODS.join(", "))
+}
+
+/// Get the response to a file that failed to open.
+fn file_error(path: &Path, e: &IoError) -> Response {
+    // Removed since its metadata was cached
+    if e.kind() == ErrorKind::NotFound {
+        return Response::not_found();
+    }
+    eprintln!("Error while opening {}: {e}", path.display());
+    Response::internal_server_error()
+}
+
+/// Get the `Content-Range` header for the bytes `start..end` of content of the given size.
+pub fn content_range(start: u64, end: u64, size: u64) -> (Cow<'static, str>, String) {
+    let last = end - 1;
+    (
+        "Content-Range".into(),
+        format!("bytes {start}-{last}/{size}"),
+    )
+}
+
+/// Create a [`BodyStream`] by repeatedly calling `f` on some state, until it returns `None`.
+#[cfg(any(feature = "fastcgi", feature = "proxy"))]
+pub fn unfold<T, F, Fut>(state: T, f: F) -> BodyStream
+where
+    T: 'static,
+    F: Fn(T) -> Fut + 'static,
+    Fut: Future<Output = Option<(Vec<u8>, T)>> + 'static,
+{
+    /// The stream returned by [`unfold`].
+    struct Unfold<F, Fut> {
+        f: F,
+        /// The pending call, or `None` once the stream has ended.
+        pending: Option<Pin<Box<Fut>>>,
+    }
+
+    // `f` is never pinned, and the pending future is boxed
+    impl<F, Fut> Unpin for Unfold<F, Fut> {}
+
+    impl<T, F, Fut> Stream for Unfold<F, Fut>
+    where
+        F: Fn(T) -> Fut,
+        Fut: Future<Output = Option<(Vec<u8>, T)>>,
+    {
+        type Item = Vec<u8>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let Some(pending) = &mut self.pending else {
+                return Poll::Ready(None);
+            };
+            match pending.as_mut().poll(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(Some((chunk, state))) => {
+                    self.pending = Some(Box::pin((self.f)(state)));
+                    Poll::Ready(Some(chunk))
+                }
+                Poll::Ready(None) => {
+                    self.pending = None;
+                    Poll::Ready(None)
+                }
+            }
+        }
+    }
+
+    Box::pin(Unfold {
+        pending: Some(Box::pin(f(state))),
+        f,
+    })
 }
 
 /// Response body.
-#[derive(Debug, Clone)]
 pub enum ResponseBody {
     /// Static body.
     Static(&'static str),
+    /// Static binary body, like an asset embedded in the executable.
+    Bytes(&'static [u8]),
+    /// Body generated at runtime, like a rendered page.
+    Owned(Vec<u8>),
+    /// Body shared with other owners without copying, like a cached page.
+    Shared(bytes::Bytes),
     /// From file.
-    File { file: File, size: u64 },
+    File {
+        /// The file.
+        file: File,
+        /// Size of the file.
+        size: u64,
+    },
     /// From partial file.
-    PartialFile { file: File, start: u64, end: u64 },
+    PartialFile {
+        /// The file.
+        file: File,
+        /// Start offset, inclusive.
+        start: u64,
+        /// End offset, exclusive.
+        end: u64,
+    },
+    /// Streamed chunk by chunk, with unknown length.
+    Stream(BodyStream),
+    /// Left out, as in responses to `HEAD`, for content of the given size.
+    Omitted {
+        /// Size of the content left out.
+        size: u64,
+    },
 }
 
 impl Response {
@@ -53,7 +273,107 @@ impl Response {
     #[must_use]
     pub const fn new(code: ResponseCode, body: &'static str) -> Self {
         let body = ResponseBody::Static(body);
-        Self { code, body }
+        Self {
+            code,
+            headers: Vec::new(),
+            body,
+            chunk_size: None,
+        }
+    }
+
+    /// Add a header to this response.
+    pub fn add_header(&mut self, name: impl Into<Cow<'static, str>>, value: impl Into<String>) {
+        self.headers.push((name.into(), value.into()));
+    }
+
+    /// Add a request header this response depends on to its `Vary` header, so caches don't
+    /// serve it for requests negotiating another representation.
+    pub fn add_vary(&mut self, name: &str) {
+        let Some((_, vary)) = self
+            .headers
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Vary"))
+        else {
+            self.headers.push(("Vary".into(), name.to_owned()));
+            return;
+        };
+        let varies = vary
+            .split(',')
+            .map(str::trim)
+            .any(|value| value == "*" || value.eq_ignore_ascii_case(name));
+        if !varies {
+            vary.push_str(", ");
+            vary.push_str(name);
+        }
+    }
+
+    /// Add a `Set-Cookie` header setting the given cookie.
+    pub fn set_cookie(&mut self, cookie: &Cookie) {
+        self.add_header("Set-Cookie", cookie.encode());
+    }
+
+    /// Get the start line and headers of this response.
+    #[must_use]
+    pub fn head(&self) -> ResponseHead<'_> {
+        ResponseHead {
+            code: self.code,
+            headers: &self.headers,
+            size: self.body.size(),
+        }
+    }
+
+    /// Hint the preferred size of the writes of a streaming body, small for latency-sensitive
+    /// streams and large for bulk transfers, returning the modified response.
+    #[must_use]
+    pub const fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size);
+        self
+    }
+
+    /// Add a header to this response, returning the modified response.
+    #[must_use]
+    pub fn with_header(
+        mut self,
+        name: impl Into<Cow<'static, str>>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.add_header(name, value);
+        self
+    }
+
+    /// Construct a new [`Ok`](ResponseCode::Ok) response with an HTML body.
+    #[must_use]
+    pub fn html(body: impl Into<ResponseBody>) -> Self {
+        Self::typed(body.into(), "text/html; charset=utf-8")
+    }
+
+    /// Construct a new [`Ok`](ResponseCode::Ok) response with a body of JSON text, already
+    /// serialized.
+    #[must_use]
+    pub fn json(body: impl Into<ResponseBody>) -> Self {
+        Self::typed(body.into(), "application/json")
+    }
+
+    /// Construct a new [`Ok`](ResponseCode::Ok) response with a plain text body.
+    #[must_use]
+    pub fn text(body: impl Into<ResponseBody>) -> Self {
+        Self::typed(body.into(), "text/plain; charset=utf-8")
+    }
+
+    /// Construct a new redirection to `location` with the given code, like
+    /// [`Found`](ResponseCode::Found), and an empty body.
+    #[must_use]
+    pub fn redirect(code: ResponseCode, location: impl Into<String>) -> Self {
+        Self::new(code, "").with_header("Location", location)
+    }
+
+    /// Construct a new [`Ok`](ResponseCode::Ok) response with a body of the given type.
+    fn typed(body: ResponseBody, content_type: &'static str) -> Self {
+        Self {
+            body,
+            ..Self::new(ResponseCode::Ok, "")
+        }
+        .with_header("Content-Type", content_type)
     }
 
     /// Construct a new [`BadRequest`](ResponseCode::BadRequest) response with the given body.
@@ -68,68 +388,113 @@ impl Response {
         Self::new(ResponseCode::NotFound, "404 Not Found")
     }
 
+    /// Construct a new [`InternalServerError`](ResponseCode::InternalServerError) response.
+    #[must_use]
+    pub const fn internal_server_error() -> Self {
+        Self::new(
+            ResponseCode::InternalServerError,
+            "500 Internal Server Error",
+        )
+    }
+
     /// Handles a well-formed [`Request`].
     #[must_use]
-    pub async fn handle(request: &Request<'_>) -> Self {
+    pub async fn handle(request: &Request<'_>, config: &ServerConfig) -> Self {
         // Version & Method check
-        if request.version != "1.1" {
+        if !matches!(request.version, "1.1" | "2") {
             return Self::new(ResponseCode::BadRequest, "Unsupported HTTP Version");
         }
-        if request.method != "GET" {
-            return Self::new(ResponseCode::MethodNotAllowed, "405 Method Not Allowed");
+        if let Some(preflight) = config
+            .cors
+            .as_ref()
+            .and_then(|cors| cors.preflight(request))
+        {
+            return preflight;
         }
-        // Resolve path relative to current directory
-        let trimmed = request.path.trim_start_matches('/');
-        let path = Path::new(".").join(trimmed);
-        if !path.exists() || !path.is_file() {
-            return Self::not_found();
+        let host = config.virtual_host(request);
+        let auth = host
+            .and_then(|host| host.auth.as_ref())
+            .or(config.auth.as_ref());
+        if let Some(auth) = auth
+            && let Some(unauthorized) = auth.check(request).await
+        {
+            return unauthorized;
         }
-        // Open file and read metadata
-        let Ok(file) = File::open(&path).await else {
-            return Self::not_found();
+        for route in config
+            .routes
+            .iter()
+            .filter(|route| route.matches(request.path))
+        {
+            if let Some(response) = route.handler.handle(request).await {
+                return response;
+            }
+        }
+        if !FILE_METHODS.contains(&request.method) {
+            return file_method_not_allowed();
+        }
+        // Resolve path relative to the document root
+        let root = host.map_or(config.root.as_path(), |host| &host.root);
+        let path = request
+            .path
+            .split_once('?')
+            .map_or(request.path, |(path, _)| path);
+        let Some(relative) = decode_path(path) else {
+            return Self::bad_request("Invalid request path");
         };
-        let Ok(metadata) = file.metadata().await else {
-            return Self::not_found();
+        let path = root.join(&*relative);
+        match Self::file(request, &path, config).await {
+            // Changed since its metadata was cached, so it is served again with fresh metadata
+            Err(e) if FileChanged::is(&e) => {
+                config.metadata_cache.invalidate(&path);
+                Self::file(request, &path, config)
+                    .await
+                    .unwrap_or_else(|e| file_error(&path, &e))
+            }
+            result => result.unwrap_or_else(|e| file_error(&path, &e)),
+        }
+    }
+
+    /// Serve the file at `path` for a `GET` or `HEAD` request.
+    async fn file(request: &Request<'_>, path: &Path, config: &ServerConfig) -> IoResult<Self> {
+        let metadata = match config.metadata_cache.metadata(config.storage(), path).await {
+            Some(metadata) if !metadata.is_dir => metadata,
+            _ => return Ok(Self::not_found()),
         };
-        if !metadata.is_file() {
-            return Self::not_found();
+        let size = metadata.size;
+        // Revalidations only need the metadata, which is likely cached, so they skip the file
+        let etag = metadata.etag();
+        if let Some(etag) = etag.as_ref().filter(|etag| request.matches_etag(etag)) {
+            return Ok(Self {
+                code: ResponseCode::NotModified,
+                headers: vec![("ETag".into(), etag.clone())],
+                body: ResponseBody::Omitted { size },
+                chunk_size: None,
+            });
         }
-        let size = metadata.len();
         // Check for Range header
-        let range = request.parse_range_header();
-        match range {
-            RangeHeader::Bytes(start, end) => {
-                let start = start.unwrap_or(0);
-                let end = end.unwrap_or(size);
-                // Validate range
-                if end > size {
-                    return Self::new(
-                        ResponseCode::RangeNotSatisfiable,
-                        "End byte exceeds file size",
-                    );
-                } else if start >= end {
-                    return Self::new(
-                        ResponseCode::RangeNotSatisfiable,
-                        "Start byte must be less than end byte",
-                    );
-                }
-                // Create partial content response
-                let body = ResponseBody::PartialFile { file, start, end };
-                Self {
-                    code: ResponseCode::PartialContent,
-                    body,
-                }
-            }
-            RangeHeader::Invalid => Self::new(ResponseCode::BadRequest, "Invalid Range Header"),
-            RangeHeader::None => {
-                // Create response
-                let body = ResponseBody::File { file, size };
-                Self {
-                    code: ResponseCode::Ok,
-                    body,
-                }
-            }
+        let range = match byte_range(request, size) {
+            Ok(range) => range,
+            Err(response) => return Ok(response),
+        };
+        let (code, (start, end)) = range.map_or((ResponseCode::Ok, (0, size)), |range| {
+            (ResponseCode::PartialContent, range)
+        });
+        // The head only needs the metadata, so the file is not even opened
+        let body = if request.method == "HEAD" {
+            ResponseBody::Omitted { size: end - start }
+        } else {
+            config.open(path, &metadata, start, end).await?
+        };
+        let mut headers: Vec<_> = etag.map(|etag| ("ETag".into(), etag)).into_iter().collect();
+        if range.is_some() {
+            headers.push(content_range(start, end, size));
         }
+        Ok(Self {
+            code,
+            headers,
+            body,
+            chunk_size: None,
+        })
     }
 
     /// Write this [`Response`] to the given destination.
@@ -138,67 +503,623 @@ impl Response {
     ///
     /// Returns an [`IoError`](std::io::Error) if writing fails.
     pub async fn write_to<D: AsyncWriteExt>(self, dest: &mut D) -> IoResult<()> {
-        // Start line and headers
-        dest.write_all("HTTP/1.1 ").await.0?;
-        dest.write_all(self.code.description()).await.0?;
-        dest.write_all("\r\nAccept-Ranges: bytes\r\n\r\n").await.0?;
+        self.write_chunked(dest, ChunkSize::default()).await
+    }
 
-        // // Dummy body
+    /// Write this [`Response`] to the given destination, reading files in chunks of the given
+    /// size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`](std::io::Error) if writing fails.
+    pub async fn write_chunked<D: AsyncWriteExt>(
+        self,
+        dest: &mut D,
+        chunk: ChunkSize,
+    ) -> IoResult<()> {
+        let mut head = pool::take(HEAD_LEN);
+        http1::write_head(&self.head(), &mut head);
+        // Small bodies in memory go out along with the head, in a single write
+        let inline = self
+            .body
+            .in_memory()
+            .filter(|body| body.len() <= INLINE_BODY_LEN);
+        if let Some(body) = inline {
+            head.extend_from_slice(body);
+        }
+        let inline = inline.is_some();
+        Self::write_buffer(head, dest).await?;
+
+        // Body
         match self.body {
+            _ if inline => {}
+            ResponseBody::Omitted { .. } => {}
             ResponseBody::Static(body) => dest.write_all(body).await.0?,
+            ResponseBody::Bytes(body) => dest.write_all(body).await.0?,
+            ResponseBody::Owned(body) => dest.write_all(body).await.0?,
+            ResponseBody::Shared(body) => dest.write_all(body).await.0?,
             ResponseBody::File { file, size } => {
-                Self::write_file_range(&file, dest, 0, size).await?;
+                Self::write_file_range(&file, dest, 0, size, chunk).await?;
             }
             ResponseBody::PartialFile { file, start, end } => {
-                Self::write_file_range(&file, dest, start, end).await?;
+                Self::write_file_range(&file, dest, start, end, chunk).await?;
+            }
+            ResponseBody::Stream(stream) => {
+                let size = self.chunk_size.unwrap_or(chunk.initial);
+                Self::write_stream(stream, dest, size).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write this [`Response`] to the given destination synchronously, without a runtime, like
+    /// in tests, CGI-style programs or other runtimes.
+    ///
+    /// Files are read with blocking reads, while streaming bodies are polled without waiting,
+    /// so they must not depend on a runtime to make progress.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`](std::io::Error) if writing or reading a file fails, or of kind
+    /// [`ErrorKind::WouldBlock`] if a streaming body is not ready.
+    pub fn write_sync<W: std::io::Write>(self, dest: &mut W) -> IoResult<()> {
+        let mut head = Vec::with_capacity(HEAD_LEN);
+        http1::write_head(&self.head(), &mut head);
+        dest.write_all(&head)?;
+        if let Some(body) = self.body.in_memory() {
+            return dest.write_all(body);
+        }
+        match self.body {
+            ResponseBody::File { file, size } => Self::copy_file_range(&file, dest, 0, size),
+            ResponseBody::PartialFile { file, start, end } => {
+                Self::copy_file_range(&file, dest, start, end)
             }
+            ResponseBody::Stream(mut stream) => {
+                let mut cx = Context::from_waker(Waker::noop());
+                loop {
+                    match stream.as_mut().poll_next(&mut cx) {
+                        Poll::Ready(Some(chunk)) => dest.write_all(&chunk)?,
+                        Poll::Ready(None) => return Ok(()),
+                        Poll::Pending => {
+                            return Err(IoError::new(
+                                ErrorKind::WouldBlock,
+                                "streaming body is not ready",
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => Ok(()),
         }
+    }
+
+    /// Serialize this [`Response`] in HTTP/1.1 format, like [`Response::write_sync`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Response::write_sync`].
+    pub fn to_bytes(self) -> IoResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_sync(&mut bytes)?;
+        Ok(bytes)
+    }
 
+    /// Helper function to copy `file[start..end]` to `dest` with blocking reads.
+    fn copy_file_range<W: std::io::Write>(
+        file: &File,
+        dest: &mut W,
+        start: u64,
+        end: u64,
+    ) -> IoResult<()> {
+        // The runtime reads at explicit positions, so moving the shared cursor is harmless
+        #[cfg(unix)]
+        let mut file = std::fs::File::from(file.as_fd().try_clone_to_owned()?);
+        #[cfg(windows)]
+        let mut file = std::fs::File::from(file.as_handle().try_clone_to_owned()?);
+        file.seek(SeekFrom::Start(start))?;
+        std::io::copy(&mut file.take(end - start), dest)?;
         Ok(())
     }
 
-    /// Helper function to write `file[start..end]` to `dest`.
+    /// Write this [`Response`] to a client connection, returning the number of bytes written
+    /// even if writing failed, like when the client disconnected.
+    ///
+    /// On Linux, the connection is corked while sending files, so that the head and the start
+    /// of the file share packets instead of the head going out alone.
+    pub(crate) async fn send(
+        self,
+        stream: &mut TcpStream,
+        config: &ServerConfig,
+    ) -> (IoResult<()>, u64) {
+        #[cfg(target_os = "linux")]
+        let corked = matches!(
+            self.body,
+            ResponseBody::File { .. } | ResponseBody::PartialFile { .. }
+        ) && SockRef::from(&*stream).set_tcp_cork(true).is_ok();
+        let mut counted = Counted {
+            inner: &mut *stream,
+            count: 0,
+        };
+        let result = if config.throttle.is_some() || config.global_throttle.is_some() {
+            let mut stream = Throttled::new(&mut counted, config);
+            self.write_chunked(&mut stream, config.chunk_size).await
+        } else {
+            self.transmit(&mut counted, config.chunk_size).await
+        };
+        let sent = counted.count;
+        #[cfg(target_os = "linux")]
+        if corked {
+            // Flushes what is held back
+            let _ = SockRef::from(&*stream).set_tcp_cork(false);
+        }
+        (result, sent)
+    }
+
+    /// Helper function to write this response to a client connection, transmitting files from
+    /// the kernel directly with `sendfile` when the `sendfile` feature is enabled on Linux.
+    async fn transmit(
+        self,
+        stream: &mut Counted<&mut TcpStream>,
+        chunk: ChunkSize,
+    ) -> IoResult<()> {
+        #[cfg(not(all(feature = "sendfile", target_os = "linux")))]
+        return self.write_chunked(stream, chunk).await;
+
+        #[cfg(all(feature = "sendfile", target_os = "linux"))]
+        {
+            let Self {
+                code,
+                headers,
+                body,
+                chunk_size,
+            } = self;
+            let (file, start, end) = match body {
+                ResponseBody::File { file, size } => (file, 0, size),
+                ResponseBody::PartialFile { file, start, end } => (file, start, end),
+                body => {
+                    return Self {
+                        code,
+                        headers,
+                        body,
+                        chunk_size,
+                    }
+                    .write_chunked(stream, chunk)
+                    .await;
+                }
+            };
+            let mut head = pool::take(HEAD_LEN);
+            let response_head = ResponseHead {
+                code,
+                headers: &headers,
+                size: Some(end - start),
+            };
+            http1::write_head(&response_head, &mut head);
+            Self::write_buffer(head, stream).await?;
+            // Files `sendfile` cannot handle are sent through a buffer instead
+            let mut position = start;
+            let result = sendfile::send_file(stream.inner, &file, &mut position, end).await;
+            stream.count += position - start;
+            result?;
+            Self::write_file_range(&file, stream, position, end, chunk).await
+        }
+    }
+
+    /// Helper function to write a pooled buffer to `dest`, returning it to the pool.
+    async fn write_buffer<D: AsyncWriteExt>(buffer: Vec<u8>, dest: &mut D) -> IoResult<()> {
+        let BufResult(result, buffer) = dest.write_all(buffer).await;
+        pool::give(buffer);
+        result
+    }
+
+    /// Helper function to write a streaming body to `dest`, gathering chunks until `size` bytes
+    /// are buffered or no more chunks are ready.
+    async fn write_stream<D: AsyncWriteExt>(
+        mut stream: BodyStream,
+        dest: &mut D,
+        size: usize,
+    ) -> IoResult<()> {
+        let mut buffer = pool::take(size);
+        let mut result = Ok(());
+        loop {
+            // Only wait for the stream when nothing is left to write
+            let next = poll_fn(|cx| match stream.as_mut().poll_next(cx) {
+                Poll::Pending if buffer.is_empty() => Poll::Pending,
+                poll => Poll::Ready(poll),
+            })
+            .await;
+            let done = match next {
+                Poll::Ready(Some(chunk)) if buffer.is_empty() && chunk.len() >= size => {
+                    result = dest.write_all(chunk).await.0;
+                    if result.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Poll::Ready(Some(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    if buffer.len() < size {
+                        continue;
+                    }
+                    false
+                }
+                Poll::Ready(None) => true,
+                Poll::Pending => false,
+            };
+            if !buffer.is_empty() {
+                let BufResult(written, returned) = dest.write_all(buffer).await;
+                buffer = returned;
+                buffer.clear();
+                result = written;
+            }
+            if done || result.is_err() {
+                break;
+            }
+        }
+        pool::give(buffer);
+        result
+    }
+
+    /// Helper function to write `file[start..end]` to `dest`, in chunks growing from
+    /// `chunk.initial` to `chunk.max` as long as they are read in full.
+    ///
+    /// Two buffers are in flight, so that the next chunk is read while the previous one is
+    /// being written, overlapping disk and network latency.
     async fn write_file_range<D: AsyncWriteExt>(
         file: &File,
         dest: &mut D,
         start: u64,
         end: u64,
+        chunk: ChunkSize,
     ) -> IoResult<()> {
-        const BUF_LEN: usize = 8192;
-        let mut buffer = vec![0; BUF_LEN];
+        let mut len = chunk.initial.max(1);
         let mut position = start;
-        while position < end {
-            let result = file.read_at(buffer, position).await;
-            let (read_bytes, mut buf) = (result.0?, result.1);
-            if read_bytes == 0 {
+        let BufResult(result, mut current) =
+            Self::read_chunk(file, pool::take(len), position, end, len).await;
+        let mut read_bytes = result?;
+        let mut next = pool::take(len);
+        while read_bytes > 0 {
+            position += read_bytes as u64;
+            // Large sequential transfers take fewer, larger reads and writes
+            if read_bytes == len && len < chunk.max {
+                len = (len * 2).min(chunk.max);
+            }
+            if position >= end {
+                let BufResult(result, written) = dest.write_all(current).await;
+                current = written;
+                result?;
                 break;
             }
-            // Only write up to the end boundary
-            #[allow(clippy::cast_possible_truncation, reason = "BUF_LEN fits in usize")]
-            let remaining = (end - position).min(BUF_LEN as u64) as usize;
-            let to_write = read_bytes.min(remaining);
-            buf.truncate(to_write);
-            let result = dest.write_all(buf).await;
-            result.0?;
-            buffer = result.1;
-            buffer.resize(BUF_LEN, 0);
-            position += to_write as u64;
+            let (write, read) = join(
+                dest.write_all(current),
+                Self::read_chunk(file, next, position, end, len),
+            )
+            .await;
+            write.0?;
+            (next, current) = (write.1, read.1);
+            read_bytes = read.0?;
         }
+        pool::give(current);
+        pool::give(next);
         Ok(())
     }
+
+    /// Helper function to read the chunk of at most `len` bytes of `file[position..end]` at
+    /// `position` into `buffer`.
+    async fn read_chunk(
+        file: &File,
+        mut buffer: Vec<u8>,
+        position: u64,
+        end: u64,
+        len: usize,
+    ) -> BufResult<usize, Vec<u8>> {
+        #[allow(clippy::cast_possible_truncation, reason = "Chunk sizes fit in usize")]
+        let len = (end - position).min(len as u64) as usize;
+        buffer.clear();
+        buffer.reserve(len);
+        let BufResult(result, slice) = file.read_at(buffer.slice(..len), position).await;
+        BufResult(result, slice.into_inner())
+    }
+}
+
+/// Run two futures concurrently, waiting for both.
+async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (pin!(a), pin!(b));
+    let (mut a_output, mut b_output) = (None, None);
+    poll_fn(|cx| {
+        if a_output.is_none() {
+            a_output = ready(a.as_mut().poll(cx));
+        }
+        if b_output.is_none() {
+            b_output = ready(b.as_mut().poll(cx));
+        }
+        match (a_output.take(), b_output.take()) {
+            (Some(a), Some(b)) => Poll::Ready((a, b)),
+            (a, b) => {
+                (a_output, b_output) = (a, b);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+/// Get the output of a poll, if ready.
+fn ready<T>(poll: Poll<T>) -> Option<T> {
+    match poll {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
+/// A writer counting the bytes written through it.
+struct Counted<W> {
+    /// The underlying writer.
+    inner: W,
+    /// Bytes written so far.
+    count: u64,
+}
+
+impl<W: AsyncWrite> AsyncWrite for Counted<W> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let result = self.inner.write(buf).await;
+        if let Ok(written) = result.0 {
+            self.count += written as u64;
+        }
+        result
+    }
+
+    async fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> IoResult<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// The start line and headers of a [`Response`], shared by HTTP/1.1 and HTTP/2.
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseHead<'a> {
+    /// The response code.
+    pub code: ResponseCode,
+    /// Additional response headers.
+    pub headers: &'a [(Cow<'static, str>, String)],
+    /// Size of the body, if known.
+    pub size: Option<u64>,
+}
+
+impl ResponseHead<'_> {
+    /// Get the header fields, starting with those derived from the body.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.size
+            .map(|_| ("Accept-Ranges", "bytes"))
+            .into_iter()
+            .chain(
+                self.headers
+                    .iter()
+                    .map(|(name, value)| (name.as_ref(), value.as_str())),
+            )
+    }
+}
+
+impl ResponseBody {
+    /// Get the length of the body in bytes, or of the content left out of it, or `None` for
+    /// streaming bodies.
+    #[must_use]
+    pub const fn size(&self) -> Option<u64> {
+        match (self, self.range()) {
+            (Self::Omitted { size }, _) => Some(*size),
+            (_, Some((start, end))) => Some(end - start),
+            (_, None) => None,
+        }
+    }
+
+    /// Check whether no bytes of the body are to be sent.
+    #[cfg_attr(not(feature = "h2"), allow(dead_code))]
+    pub(crate) const fn is_empty(&self) -> bool {
+        matches!(self, Self::Omitted { .. }) || matches!(self.size(), Some(0))
+    }
+
+    /// Get the range of the underlying content covered by the body, or `None` for streaming and
+    /// omitted bodies.
+    pub(crate) const fn range(&self) -> Option<(u64, u64)> {
+        match self {
+            Self::Static(body) => Some((0, body.len() as u64)),
+            Self::Bytes(body) => Some((0, body.len() as u64)),
+            Self::Owned(body) => Some((0, body.len() as u64)),
+            Self::Shared(body) => Some((0, body.len() as u64)),
+            Self::File { size, .. } => Some((0, *size)),
+            Self::PartialFile { start, end, .. } => Some((*start, *end)),
+            Self::Stream(_) | Self::Omitted { .. } => None,
+        }
+    }
+
+    /// Read up to `len` bytes of the underlying content, starting at `position`.
+    ///
+    /// Streaming and omitted bodies cannot be read this way, and yield no bytes.
+    #[cfg_attr(not(feature = "h2"), allow(dead_code))]
+    pub(crate) async fn read_at(&self, position: u64, len: usize) -> IoResult<Vec<u8>> {
+        if let Some(body) = self.in_memory() {
+            return Ok(slice_at(body, position, len).to_vec());
+        }
+        match self {
+            Self::File { file, .. } | Self::PartialFile { file, .. } => {
+                let result = file.read_at(Vec::with_capacity(len), position).await;
+                let (read, buffer) = (result.0?, result.1);
+                debug_assert!(read == buffer.len());
+                Ok(buffer)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Get the bytes of a body held in memory, or `None` for files, streaming and omitted bodies.
+    fn in_memory(&self) -> Option<&[u8]> {
+        match self {
+            Self::Static(body) => Some(body.as_bytes()),
+            Self::Bytes(body) => Some(body),
+            Self::Owned(body) => Some(body),
+            Self::Shared(body) => Some(body),
+            _ => None,
+        }
+    }
+}
+
+/// Get up to `len` bytes of an in-memory body, starting at `position`.
+fn slice_at(body: &[u8], position: u64, len: usize) -> &[u8] {
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "Bodies in memory are shorter than `usize::MAX`"
+    )]
+    let start = (position as usize).min(body.len());
+    let end = start.saturating_add(len).min(body.len());
+    &body[start..end]
+}
+
+impl From<&'static str> for ResponseBody {
+    fn from(body: &'static str) -> Self {
+        Self::Static(body)
+    }
+}
+
+impl From<&'static [u8]> for ResponseBody {
+    fn from(body: &'static [u8]) -> Self {
+        Self::Bytes(body)
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(body: Vec<u8>) -> Self {
+        Self::Owned(body)
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(body: String) -> Self {
+        Self::Owned(body.into_bytes())
+    }
+}
+
+impl From<bytes::Bytes> for ResponseBody {
+    fn from(body: bytes::Bytes) -> Self {
+        Self::Shared(body)
+    }
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static(body) => f.debug_tuple("Static").field(body).finish(),
+            Self::Bytes(body) => f.debug_tuple("Bytes").field(&body.len()).finish(),
+            Self::Owned(body) => f.debug_tuple("Owned").field(&body.len()).finish(),
+            Self::Shared(body) => f.debug_tuple("Shared").field(&body.len()).finish(),
+            Self::File { file, size } => f
+                .debug_struct("File")
+                .field("file", file)
+                .field("size", size)
+                .finish(),
+            Self::PartialFile { file, start, end } => f
+                .debug_struct("PartialFile")
+                .field("file", file)
+                .field("start", start)
+                .field("end", end)
+                .finish(),
+            Self::Stream(_) => f.debug_tuple("Stream").finish_non_exhaustive(),
+            Self::Omitted { size } => f.debug_struct("Omitted").field("size", size).finish(),
+        }
+    }
 }
 
 impl ResponseCode {
-    /// Get description of the response code.
-    pub const fn description(self) -> &'static str {
+    /// Get the numeric value of the response code.
+    #[must_use]
+    pub const fn as_u16(self) -> u16 {
         match self {
-            Self::Ok => "200 OK",
-            Self::PartialContent => "206 Partial Content",
-            Self::BadRequest => "400 Bad Request",
-            Self::NotFound => "404 Not Found",
-            Self::MethodNotAllowed => "405 Method Not Allowed",
-            Self::RangeNotSatisfiable => "416 Range Not Satisfiable",
-            // Self::InternalServerError => "500 Internal Server Error",
+            Self::Ok => 200,
+            Self::NoContent => 204,
+            Self::PartialContent => 206,
+            Self::MovedPermanently => 301,
+            Self::Found => 302,
+            Self::NotModified => 304,
+            Self::BadRequest => 400,
+            Self::Unauthorized => 401,
+            Self::NotFound => 404,
+            Self::MethodNotAllowed => 405,
+            Self::RequestTimeout => 408,
+            Self::ContentTooLarge => 413,
+            Self::RangeNotSatisfiable => 416,
+            Self::RequestHeaderFieldsTooLarge => 431,
+            Self::InternalServerError => 500,
+            Self::NotImplemented => 501,
+            Self::BadGateway => 502,
+            Self::ServiceUnavailable => 503,
+            Self::Other(code) => code,
         }
     }
+
+    /// Whether responses with this code may have content, and so a `Content-Length`, which
+    /// informational and [`NoContent`](Self::NoContent) responses must not have.
+    #[must_use]
+    pub const fn allows_content(self) -> bool {
+        !matches!(self.as_u16(), 100..=199 | 204)
+    }
+
+    /// Get the reason phrase of the response code, which is empty for [`Other`](Self::Other).
+    #[must_use]
+    pub const fn reason(self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::NoContent => "No Content",
+            Self::PartialContent => "Partial Content",
+            Self::MovedPermanently => "Moved Permanently",
+            Self::Found => "Found",
+            Self::NotModified => "Not Modified",
+            Self::BadRequest => "Bad Request",
+            Self::Unauthorized => "Unauthorized",
+            Self::NotFound => "Not Found",
+            Self::MethodNotAllowed => "Method Not Allowed",
+            Self::RequestTimeout => "Request Timeout",
+            Self::ContentTooLarge => "Content Too Large",
+            Self::RangeNotSatisfiable => "Range Not Satisfiable",
+            Self::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            Self::InternalServerError => "Internal Server Error",
+            Self::NotImplemented => "Not Implemented",
+            Self::BadGateway => "Bad Gateway",
+            Self::ServiceUnavailable => "Service Unavailable",
+            Self::Other(_) => "",
+        }
+    }
+}
+
+impl From<u16> for ResponseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            200 => Self::Ok,
+            204 => Self::NoContent,
+            206 => Self::PartialContent,
+            301 => Self::MovedPermanently,
+            302 => Self::Found,
+            304 => Self::NotModified,
+            400 => Self::BadRequest,
+            401 => Self::Unauthorized,
+            404 => Self::NotFound,
+            405 => Self::MethodNotAllowed,
+            408 => Self::RequestTimeout,
+            413 => Self::ContentTooLarge,
+            416 => Self::RangeNotSatisfiable,
+            431 => Self::RequestHeaderFieldsTooLarge,
+            500 => Self::InternalServerError,
+            501 => Self::NotImplemented,
+            502 => Self::BadGateway,
+            503 => Self::ServiceUnavailable,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.as_u16(), self.reason())
+    }
 }
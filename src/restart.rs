@@ -0,0 +1,76 @@
+//! Zero-downtime restarts.
+//!
+//! On `SIGUSR2`, a new process is started with the same arguments, inheriting the listening
+//! socket through the `NANOSERVE_LISTEN_FD` environment variable. This process then stops
+//! accepting connections and exits once those in flight are done, so that the binary can be
+//! upgraded without dropping a single connection.
+
+use compio::{runtime::JoinHandle, signal::unix::signal, time::sleep};
+use nanoserve::{HTTPServer, NanoserveError};
+use std::{
+    env,
+    io::{Error as IoError, Result as IoResult},
+    net::TcpListener,
+    os::fd::{AsRawFd, FromRawFd, RawFd},
+    process::{Child, Command},
+    time::{Duration, Instant},
+};
+
+/// Environment variable holding the file descriptor of the inherited listener.
+const LISTEN_FD: &str = "NANOSERVE_LISTEN_FD";
+
+/// How long to wait for in-flight connections, such as idle HTTP/2 ones, before exiting anyway.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Get the listener inherited from a previous process, if any.
+pub fn inherited_listener() -> Option<TcpListener> {
+    let fd: RawFd = env::var(LISTEN_FD).ok()?.parse().ok()?;
+    // SAFETY: the previous process passed this descriptor to us, and nothing else owns it
+    Some(unsafe { TcpListener::from_raw_fd(fd) })
+}
+
+/// Restart on `SIGUSR2`: start a new process, stop `server_task` and exit once `server` is idle.
+pub async fn on_signal(server: HTTPServer, server_task: JoinHandle<Result<(), NanoserveError>>) {
+    loop {
+        if let Err(e) = signal(libc::SIGUSR2).await {
+            eprintln!("Failed to listen for SIGUSR2: {e}");
+            return;
+        }
+        match spawn_successor(server.as_raw_fd()) {
+            Ok(child) => {
+                println!(
+                    "Started new process {}, finishing in-flight requests...",
+                    child.id()
+                );
+                break;
+            }
+            Err(e) => eprintln!("Failed to restart: {e}"),
+        }
+    }
+    drop(server_task);
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while server.connections() > 0 && Instant::now() < deadline {
+        sleep(Duration::from_millis(50)).await;
+    }
+    println!("Old process exiting");
+    std::process::exit(0);
+}
+
+/// Start a new process with the same arguments, passing it the listening socket.
+fn spawn_successor(fd: RawFd) -> IoResult<Child> {
+    // Unlike the original, the duplicate is not close-on-exec, so it is inherited
+    // SAFETY: `fd` is a valid descriptor, owned by the server
+    let inheritable = unsafe { libc::dup(fd) };
+    if inheritable < 0 {
+        return Err(IoError::last_os_error());
+    }
+    let child = env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .args(env::args_os().skip(1))
+            .env(LISTEN_FD, inheritable.to_string())
+            .spawn()
+    });
+    // SAFETY: the duplicate is owned by us, and only needed by the child
+    unsafe { libc::close(inheritable) };
+    child
+}
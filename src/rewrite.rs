@@ -0,0 +1,108 @@
+//! URL rewrite and redirect rules.
+
+mod netlify;
+
+use super::{ResponseCode, glob};
+pub use netlify::RedirectsFiles;
+
+/// What to do with a request matching a [`RewriteRule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewriteAction {
+    /// Serve the target path instead, without the client noticing.
+    Rewrite,
+    /// Redirect the client to the target, which may be a path or an absolute URL.
+    Redirect,
+}
+
+/// A rule mapping request paths matching a glob pattern to a target.
+///
+/// In the pattern, `*` matches any sequence of characters and `?` any single character. The text
+/// matched by each `*` can be referenced in the target as `$1`, `$2`, and so on. Patterns are
+/// matched against the path without query string, which is kept unless the target has its own.
+///
+/// For example, `RewriteRule::rewrite("/*", "/$1.html")` serves `/about.html` for `/about`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewriteRule {
+    /// The glob pattern.
+    pub pattern: String,
+    /// The target, with `$N` references to captures.
+    pub target: String,
+    /// Whether to rewrite or redirect.
+    pub action: RewriteAction,
+    /// Status code of redirects, or of rewritten responses that would otherwise be `200 OK`.
+    pub code: ResponseCode,
+    /// Whether to apply the rule even if a file exists at the requested path.
+    pub force: bool,
+}
+
+impl RewriteRule {
+    /// Creates an internal rewrite rule.
+    #[must_use]
+    pub fn rewrite(pattern: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            target: target.into(),
+            action: RewriteAction::Rewrite,
+            code: ResponseCode::Ok,
+            force: false,
+        }
+    }
+
+    /// Creates a redirect rule, using `301 Moved Permanently` unless another code is set.
+    #[must_use]
+    pub fn redirect(pattern: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            action: RewriteAction::Redirect,
+            code: ResponseCode::MovedPermanently,
+            ..Self::rewrite(pattern, target)
+        }
+    }
+
+    /// Sets the status code.
+    #[must_use]
+    pub const fn code(mut self, code: ResponseCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Applies the rule even if a file exists at the requested path, which otherwise takes precedence.
+    #[must_use]
+    pub const fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    /// Get the target for the given request path, if it matches.
+    #[must_use]
+    pub fn apply(&self, path: &str) -> Option<String> {
+        let (path, query) = path
+            .split_once('?')
+            .map_or((path, None), |(path, query)| (path, Some(query)));
+        let captures = glob::captures(&self.pattern, path)?;
+        let mut target = glob::substitute(&self.target, &captures);
+        if let Some(query) = query.filter(|_| !target.contains('?')) {
+            target.push('?');
+            target.push_str(query);
+        }
+        Some(target)
+    }
+}
+
+/// Find the first rule that applies to the request path, and its target.
+///
+/// `exists` tells whether a file exists at a path, which shadows rules that are not forced.
+pub fn find<'r>(
+    rules: impl IntoIterator<Item = &'r RewriteRule>,
+    path: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Option<(&'r RewriteRule, String)> {
+    let file = path.split_once('?').map_or(path, |(file, _)| file);
+    let mut shadowed = None;
+    rules.into_iter().find_map(|rule| {
+        let target = rule.apply(path)?;
+        if !rule.force && *shadowed.get_or_insert_with(|| exists(file)) {
+            return None;
+        }
+        Some((rule, target))
+    })
+}
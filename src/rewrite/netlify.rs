@@ -0,0 +1,143 @@
+//! Netlify-style `_redirects` files.
+//!
+//! Each line is `from to [status][!]`, where `from` may contain `:placeholder` segments and a
+//! trailing `*` splat, referenced in `to` as `:placeholder` and `:splat`. The status defaults to
+//! `301`; `200` and other non-redirect codes rewrite, and `!` forces the rule even if a file
+//! exists at the requested path. Placeholders match like `*`, possibly spanning several segments.
+//! Query parameter matching, conditions and proxying to absolute URLs are not supported, and
+//! such rules are skipped with a warning.
+
+use super::RewriteRule;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+/// Name of the file, in the document root.
+const FILE_NAME: &str = "_redirects";
+
+/// Modification time and rules of a `_redirects` file.
+type Cached = (SystemTime, Rc<[RewriteRule]>);
+
+/// Rules of the `_redirects` files of document roots, reloaded when they change.
+#[derive(Debug, Clone, Default)]
+pub struct RedirectsFiles {
+    /// Parsed rules and modification time, by document root.
+    cache: RefCell<HashMap<PathBuf, Cached>>,
+}
+
+impl RedirectsFiles {
+    /// Get the rules of the `_redirects` file in `root`, (re)loading it if it has changed.
+    pub fn rules(&self, root: &Path) -> Rc<[RewriteRule]> {
+        let path = root.join(FILE_NAME);
+        let mut cache = self.cache.borrow_mut();
+        let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            cache.remove(root);
+            return Rc::new([]);
+        };
+        if let Some((cached, rules)) = cache.get(root)
+            && *cached == modified
+        {
+            return Rc::clone(rules);
+        }
+        let rules: Rc<[RewriteRule]> = match fs::read_to_string(&path) {
+            Ok(content) => parse(&content, &path).into(),
+            Err(e) => {
+                eprintln!("Error while reading {}: {e}", path.display());
+                Rc::new([])
+            }
+        };
+        println!("Loaded {} rules from {}", rules.len(), path.display());
+        cache.insert(root.to_path_buf(), (modified, Rc::clone(&rules)));
+        rules
+    }
+}
+
+/// Parse the content of a `_redirects` file, printing a warning for each invalid rule.
+fn parse(content: &str, path: &Path) -> Vec<RewriteRule> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                return None;
+            }
+            parse_line(line)
+                .inspect_err(|e| {
+                    eprintln!("Skipping {}:{}: {e}", path.display(), index + 1);
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// Parse a single rule.
+fn parse_line(line: &str) -> Result<RewriteRule, String> {
+    let fields: Vec<_> = line.split_whitespace().collect();
+    let (from, to, status) = match fields[..] {
+        [from, to] => (from, to, None),
+        [from, to, status] => (from, to, Some(status)),
+        [_, _, ..] => return Err("query parameters and conditions are not supported".into()),
+        _ => return Err("expected `from to [status]`".into()),
+    };
+    if !from.starts_with('/') {
+        return Err("only paths are supported in `from`".into());
+    }
+    if to.contains('=') && !to.starts_with('/') && !to.contains("://") {
+        return Err("query parameters are not supported".into());
+    }
+
+    let (status, force) = status.map_or(("301", false), |status| {
+        status
+            .strip_suffix('!')
+            .map_or((status, false), |status| (status, true))
+    });
+    let code = status
+        .parse::<u16>()
+        .ok()
+        .filter(|code| (100..=599).contains(code))
+        .ok_or_else(|| format!("invalid status `{status}`"))?;
+
+    // Turn placeholders and the splat into captures
+    let mut names = Vec::new();
+    let pattern: Vec<_> = from
+        .split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                names.push(name);
+                "*"
+            } else if segment == "*" {
+                names.push("splat");
+                "*"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    let pattern = pattern.join("/");
+    let mut target = to.replace('$', "$$");
+    // Longest names first, so that `:id` does not clobber `:identifier`
+    let mut order: Vec<_> = (0..names.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(names[index].len()));
+    for index in order {
+        target = target.replace(
+            &format!(":{}", names[index]),
+            &format!("${{{}}}", index + 1),
+        );
+    }
+
+    let rule = if (300..400).contains(&code) {
+        RewriteRule::redirect(pattern, target)
+    } else if to.contains("://") {
+        return Err("proxying to absolute URLs is not supported".into());
+    } else {
+        RewriteRule::rewrite(pattern, target)
+    };
+    let rule = rule.code(code.into());
+    Ok(if force { rule.force() } else { rule })
+}
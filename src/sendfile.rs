@@ -0,0 +1,80 @@
+//! Zero-copy transmission of files with `sendfile`.
+
+use compio::{fs::File, net::TcpStream};
+use std::{
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    os::fd::{AsRawFd, RawFd},
+};
+
+/// Maximum number of bytes per `sendfile` call, so that other connections get their turn.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Sets `O_NONBLOCK` on a socket, restoring its previous flags when dropped.
+struct NonBlocking {
+    /// The socket.
+    fd: RawFd,
+    /// Flags of the socket before.
+    flags: libc::c_int,
+}
+
+impl NonBlocking {
+    /// Sets `O_NONBLOCK` on the socket.
+    fn set(fd: RawFd) -> IoResult<Self> {
+        // SAFETY: `fd` is a valid descriptor borrowed from a live socket
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(IoError::last_os_error());
+        }
+        Ok(Self { fd, flags })
+    }
+}
+
+impl Drop for NonBlocking {
+    fn drop(&mut self) {
+        // SAFETY: the socket outlives this guard
+        unsafe { libc::fcntl(self.fd, libc::F_SETFL, self.flags) };
+    }
+}
+
+/// Send `file[*position..end]` to the socket without copying it through user space, advancing
+/// `position` as bytes are sent, even if sending fails.
+///
+/// Stops before `end` if the file shrank, or right away if `sendfile` does not support this
+/// file, so that the rest can be sent through a buffer.
+pub async fn send_file(
+    socket: &TcpStream,
+    file: &File,
+    position: &mut u64,
+    end: u64,
+) -> IoResult<()> {
+    let poll = socket.to_poll_fd()?;
+    // `sendfile` must not block the whole runtime on slow clients
+    let _non_blocking = NonBlocking::set(socket.as_raw_fd())?;
+    let start = *position;
+    let mut offset = libc::off_t::try_from(start).map_err(IoError::other)?;
+    while *position < end {
+        poll.write_ready().await?;
+        #[allow(clippy::cast_possible_truncation, reason = "At most CHUNK_SIZE")]
+        let count = (end - *position).min(CHUNK_SIZE) as usize;
+        // SAFETY: both descriptors are valid, and `offset` is a valid pointer
+        let sent =
+            unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &raw mut offset, count) };
+        match usize::try_from(sent) {
+            Ok(0) => break,
+            Ok(sent) => *position += sent as u64,
+            Err(_) => {
+                let e = IoError::last_os_error();
+                match (e.kind(), e.raw_os_error()) {
+                    (ErrorKind::WouldBlock | ErrorKind::Interrupted, _) => {}
+                    (_, Some(libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP))
+                        if *position == start =>
+                    {
+                        break;
+                    }
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,10 @@
+//! Detection of protocols other than HTTP on the server port.
+
+/// A fatal `handshake_failure` TLS alert record, so that TLS clients fail fast with a clear error.
+pub const TLS_ALERT: [u8; 7] = [0x15, 0x03, 0x01, 0x00, 0x02, 0x02, 0x28];
+
+/// Whether the first bytes of a connection are a TLS `ClientHello`: a handshake record of
+/// TLS 1.x starting with a `ClientHello` message.
+pub const fn is_tls_client_hello(bytes: &[u8]) -> bool {
+    matches!(bytes, [0x16, 0x03, _, _, _, 0x01, ..])
+}
@@ -0,0 +1,164 @@
+//! Server-Sent Events (`text/event-stream`).
+//!
+//! Wrap a [`Stream`] of [`Event`]s into an [`EventStream`], and convert it into a [`Response`]
+//! from a custom [`Handler`](super::Handler). The connection stays open until the source ends.
+
+use super::{Request, Response, ResponseBody, ResponseCode};
+use compio::time::sleep;
+use futures_core::Stream;
+use std::{
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Default interval of keep-alive comments.
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A single server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    /// Event ID, which the client sends back as `Last-Event-ID` when reconnecting.
+    pub id: Option<String>,
+    /// Event type.
+    pub event: Option<String>,
+    /// Event data, possibly spanning several lines.
+    pub data: String,
+    /// Reconnection time to advise to the client.
+    pub retry: Option<Duration>,
+}
+
+impl Event {
+    /// Creates an event with the given data.
+    #[must_use]
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the event ID.
+    #[must_use]
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the event type.
+    #[must_use]
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Sets the reconnection time.
+    #[must_use]
+    pub const fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Serializes the event in the `text/event-stream` format.
+    #[must_use]
+    pub fn encode(&self) -> String {
+        // Field values must not contain line breaks
+        let single_line = |s: &str| s.replace(['\r', '\n'], "");
+        let mut encoded = String::new();
+        if let Some(id) = &self.id {
+            let _ = writeln!(encoded, "id: {}", single_line(id).replace('\0', ""));
+        }
+        if let Some(event) = &self.event {
+            let _ = writeln!(encoded, "event: {}", single_line(event));
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(encoded, "retry: {}", retry.as_millis());
+        }
+        for line in self.data.lines() {
+            let _ = writeln!(encoded, "data: {line}");
+        }
+        if self.data.is_empty() {
+            encoded.push_str("data:\n");
+        }
+        encoded.push('\n');
+        encoded
+    }
+}
+
+/// Get the `Last-Event-ID` header of a reconnecting client, to resume the stream after it.
+#[must_use]
+pub fn last_event_id<'a>(request: &Request<'a>) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("Last-Event-ID"))
+        .map(|(_, value)| *value)
+}
+
+/// A stream of serialized events, with keep-alive comments sent while the source is idle.
+pub struct EventStream<S> {
+    /// The event source.
+    source: Pin<Box<S>>,
+    /// Interval of keep-alive comments.
+    keep_alive: Duration,
+    /// Timer of the next keep-alive comment.
+    timer: Pin<Box<dyn Future<Output = ()>>>,
+}
+
+impl<S: Stream<Item = Event> + 'static> EventStream<S> {
+    /// Creates an event stream from the given source.
+    #[must_use]
+    pub fn new(source: S) -> Self {
+        Self {
+            source: Box::pin(source),
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            timer: Box::pin(sleep(DEFAULT_KEEP_ALIVE)),
+        }
+    }
+
+    /// Sets the interval of keep-alive comments, which stop proxies from closing idle connections.
+    #[must_use]
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = interval;
+        self.timer = Box::pin(sleep(interval));
+        self
+    }
+}
+
+impl<S: Stream<Item = Event>> Stream for EventStream<S> {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.source.as_mut().poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                self.timer = Box::pin(sleep(self.keep_alive));
+                Poll::Ready(Some(event.encode().into_bytes()))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                if self.timer.as_mut().poll(cx).is_ready() {
+                    self.timer = Box::pin(sleep(self.keep_alive));
+                    Poll::Ready(Some(b":\n\n".to_vec()))
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+impl<S: Stream<Item = Event> + 'static> From<EventStream<S>> for Response {
+    fn from(stream: EventStream<S>) -> Self {
+        Self {
+            code: ResponseCode::Ok,
+            headers: Vec::new(),
+            body: ResponseBody::Stream(Box::pin(stream)),
+            // Events go out as soon as they are produced
+            chunk_size: Some(0),
+        }
+        .with_header("Content-Type", "text/event-stream")
+        .with_header("Cache-Control", "no-cache")
+    }
+}
@@ -0,0 +1,383 @@
+//! Pluggable storage backends for the files being served.
+
+use super::{ResponseBody, memory};
+use compio::{driver::ToSharedFd, fs::File, runtime::spawn_blocking};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    io::{Error as IoError, Result as IoResult},
+    path::{Path, PathBuf},
+    pin::Pin,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum number of entries of a [`MetadataCache`].
+const CACHE_CAPACITY: usize = 4096;
+
+/// Type of the entries of a [`MetadataCache`].
+type MetadataEntry = (PathBuf, (Instant, Option<Metadata>));
+
+/// The future returned by [`Storage`] methods.
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = IoResult<T>> + 'a>>;
+
+/// Metadata of an entry of a [`Storage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Size in bytes, or zero for directories.
+    pub size: u64,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// Last modification time, if known.
+    pub modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    /// Get a weak entity tag derived from the modification time and size, if the time is known.
+    ///
+    /// There is none for times at the epoch, which are left by storages not tracking them, as
+    /// files of the same size would share it.
+    #[must_use]
+    pub fn etag(&self) -> Option<String> {
+        let modified = self
+            .modified?
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .filter(|modified| !modified.is_zero())?;
+        Some(format!("W/\"{:x}-{:x}\"", modified.as_nanos(), self.size))
+    }
+}
+
+/// A cache of the [`Metadata`] of paths, including missing ones, so that repeated requests for
+/// the same files skip redundant filesystem calls.
+///
+/// Entries expire after a time to live, so that changes to files are noticed in due time.
+/// A zero time to live, the default, disables the cache. Entries count towards the memory
+/// budget of the server.
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    /// How long entries stay valid.
+    ttl: Duration,
+    /// Metadata by path, `None` for missing paths, and when it was read.
+    entries: RefCell<HashMap<PathBuf, (Instant, Option<Metadata>)>>,
+}
+
+impl MetadataCache {
+    /// Creates a cache keeping entries for the given time.
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RefCell::default(),
+        }
+    }
+
+    /// Get the metadata of a path from the cache, or from the storage if it is not cached or
+    /// has expired. Returns `None` if the path does not exist or cannot be accessed.
+    pub async fn metadata(&self, storage: &dyn Storage, path: &Path) -> Option<Metadata> {
+        if self.ttl.is_zero() {
+            return storage.metadata(path).await.ok();
+        }
+        let now = Instant::now();
+        if let Some(&(read, metadata)) = self.entries.borrow().get(path)
+            && now.duration_since(read) < self.ttl
+        {
+            return metadata;
+        }
+        let metadata = storage.metadata(path).await.ok();
+        let mut entries = self.entries.borrow_mut();
+        if let Some(entry) = entries.get_mut(path) {
+            *entry = (now, metadata);
+            return metadata;
+        }
+        let cost = entry_cost(path);
+        if entries.len() >= CACHE_CAPACITY || !memory::reserve(cost) {
+            entries.retain(|path, (read, _)| {
+                let fresh = now.duration_since(*read) < self.ttl;
+                if !fresh {
+                    memory::release(entry_cost(path));
+                }
+                fresh
+            });
+            if entries.len() >= CACHE_CAPACITY || !memory::reserve(cost) {
+                memory::release(entries.keys().map(|path| entry_cost(path)).sum());
+                entries.clear();
+                if !memory::reserve(cost) {
+                    return metadata;
+                }
+            }
+        }
+        entries.insert(path.to_path_buf(), (now, metadata));
+        metadata
+    }
+
+    /// Forget the metadata of a path, once it is known to have changed.
+    pub fn invalidate(&self, path: &Path) {
+        if self.entries.borrow_mut().remove(path).is_some() {
+            memory::release(entry_cost(path));
+        }
+    }
+}
+
+impl Clone for MetadataCache {
+    /// Creates an empty cache with the same time to live, as entries are accounted for once.
+    fn clone(&self) -> Self {
+        Self::new(self.ttl)
+    }
+}
+
+impl Drop for MetadataCache {
+    fn drop(&mut self) {
+        memory::release(
+            self.entries
+                .get_mut()
+                .keys()
+                .map(|path| entry_cost(path))
+                .sum(),
+        );
+    }
+}
+
+/// Get the approximate memory held by an entry of a [`MetadataCache`] for the path.
+fn entry_cost(path: &Path) -> usize {
+    size_of::<MetadataEntry>() + path.as_os_str().len()
+}
+
+/// A cache of open files of the local filesystem, so that hot files are not reopened for every
+/// request.
+///
+/// Files are reopened when the modification time or size of their path changes, and the least
+/// recently used ones are closed to stay within a budget of file descriptors. A zero budget, the
+/// default, disables the cache.
+#[derive(Debug, Clone, Default)]
+pub struct FileCache {
+    /// Maximum number of open files.
+    budget: usize,
+    /// Open files by path.
+    files: RefCell<HashMap<PathBuf, OpenFile>>,
+    /// Number of uses so far, to find the least recently used file.
+    uses: Cell<u64>,
+}
+
+/// An open file of a [`FileCache`].
+#[derive(Debug, Clone)]
+struct OpenFile {
+    /// The file.
+    file: File,
+    /// Modification time of the file when it was opened.
+    modified: Option<SystemTime>,
+    /// Size of the file when it was opened.
+    size: u64,
+    /// When the file was last used.
+    used: u64,
+}
+
+/// The error of opening a file whose size is no longer the one of its metadata, as responses
+/// are sized from the metadata before the file is opened.
+#[derive(Debug, Clone, Copy)]
+pub struct FileChanged;
+
+impl FileChanged {
+    /// Whether an IO error is a [`FileChanged`], so that the metadata of the file should be
+    /// read again.
+    #[must_use]
+    pub fn is(error: &IoError) -> bool {
+        error
+            .get_ref()
+            .is_some_and(<dyn Error + Send + Sync>::is::<Self>)
+    }
+}
+
+impl fmt::Display for FileChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("file changed since its metadata was read")
+    }
+}
+
+impl Error for FileChanged {}
+
+impl FileCache {
+    /// Creates a cache keeping at most `budget` files open.
+    #[must_use]
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            ..Self::default()
+        }
+    }
+
+    /// Open the bytes `start..end` of a local file with the given metadata as a response body,
+    /// reusing the open file if its modification time and size are still the ones of the
+    /// metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the file cannot be opened, or a [`FileChanged`] if its size is
+    /// no longer the one of the metadata, as the response was sized for it.
+    pub async fn open(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        start: u64,
+        end: u64,
+    ) -> IoResult<ResponseBody> {
+        if self.budget == 0 {
+            let file = open_sized(path, metadata.size).await?;
+            return Ok(file_body(file, start, end));
+        }
+        let uses = self.uses.get() + 1;
+        self.uses.set(uses);
+        if let Some(file) = self.reuse(path, metadata, uses)? {
+            return Ok(file_body(file, start, end));
+        }
+        let file = open_sized(path, metadata.size).await?;
+        let mut files = self.files.borrow_mut();
+        if files.len() >= self.budget && !files.contains_key(path) {
+            let least_recent = files
+                .iter()
+                .min_by_key(|(_, open)| open.used)
+                .map(|(path, _)| path.clone());
+            if let Some(least_recent) = least_recent {
+                files.remove(&least_recent);
+            }
+        }
+        let open = OpenFile {
+            file: file.clone(),
+            modified: metadata.modified,
+            size: metadata.size,
+            used: uses,
+        };
+        files.insert(path.to_path_buf(), open);
+        Ok(file_body(file, start, end))
+    }
+
+    /// Get the open file of a path if it was opened with the same metadata, marking it used.
+    fn reuse(&self, path: &Path, metadata: &Metadata, uses: u64) -> IoResult<Option<File>> {
+        let mut files = self.files.borrow_mut();
+        let Some(open) = files
+            .get_mut(path)
+            .filter(|open| open.modified == metadata.modified && open.size == metadata.size)
+        else {
+            return Ok(None);
+        };
+        // The metadata was just read, or is cached, so only the size of the open file is
+        // checked, as it may have been edited in place, with a stat that does not block
+        if file_size(&open.file)? != metadata.size {
+            files.remove(path);
+            return Err(IoError::other(FileChanged));
+        }
+        open.used = uses;
+        Ok(Some(open.file.clone()))
+    }
+}
+
+/// An entry of a directory listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// Name of the entry, without its directory.
+    pub name: String,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+}
+
+/// Where the files being served are read from.
+///
+/// Paths are the document root joined with the request path, so that implementations other than
+/// [`LocalFs`], like object stores, can treat the root as a prefix or bucket.
+pub trait Storage: fmt::Debug {
+    /// Get the metadata of an entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the entry does not exist or cannot be accessed.
+    fn metadata<'a>(&'a self, path: &'a Path) -> StorageFuture<'a, Metadata>;
+
+    /// Open the bytes `start..end` of a file as a response body.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the file does not exist or cannot be read.
+    fn open<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> StorageFuture<'a, ResponseBody>;
+
+    /// List the entries of a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IoError`] if the directory does not exist or cannot be read.
+    fn list<'a>(&'a self, path: &'a Path) -> StorageFuture<'a, Vec<DirEntry>>;
+}
+
+/// The local filesystem, the default [`Storage`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalFs;
+
+impl Storage for LocalFs {
+    fn metadata<'a>(&'a self, path: &'a Path) -> StorageFuture<'a, Metadata> {
+        let path = PathBuf::from(path);
+        Box::pin(async move {
+            // The statx of compio does not ask for timestamps, so they would all be the epoch
+            let metadata = spawn_blocking(move || std::fs::metadata(path))
+                .await
+                .unwrap_or_else(|_| Err(IoError::other("metadata query panicked")))?;
+            Ok(Metadata {
+                size: if metadata.is_dir() { 0 } else { metadata.len() },
+                is_dir: metadata.is_dir(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .filter(|&modified| modified != UNIX_EPOCH),
+            })
+        })
+    }
+
+    fn open<'a>(&'a self, path: &'a Path, start: u64, end: u64) -> StorageFuture<'a, ResponseBody> {
+        Box::pin(async move { Ok(file_body(File::open(path).await?, start, end)) })
+    }
+
+    fn list<'a>(&'a self, path: &'a Path) -> StorageFuture<'a, Vec<DirEntry>> {
+        let path = PathBuf::from(path);
+        Box::pin(async move {
+            // There is no asynchronous directory listing, so it happens on the blocking pool
+            spawn_blocking(move || {
+                std::fs::read_dir(path)?
+                    .map(|entry| {
+                        let entry = entry?;
+                        Ok(DirEntry {
+                            name: entry.file_name().to_string_lossy().into_owned(),
+                            is_dir: entry.file_type()?.is_dir(),
+                        })
+                    })
+                    .collect()
+            })
+            .await
+            .unwrap_or_else(|_| Err(IoError::other("directory listing panicked")))
+        })
+    }
+}
+
+/// Open a local file, checking that it is still `size` bytes long.
+async fn open_sized(path: &Path, size: u64) -> IoResult<File> {
+    let file = File::open(path).await?;
+    if file_size(&file)? != size {
+        return Err(IoError::other(FileChanged));
+    }
+    Ok(file)
+}
+
+/// Get the size of an open file.
+fn file_size(file: &File) -> IoResult<u64> {
+    // A stat of an open file does not block, unlike the one of its path
+    Ok(file.to_shared_fd().metadata()?.len())
+}
+
+/// Get the body for the bytes `start..end` of a file.
+const fn file_body(file: File, start: u64, end: u64) -> ResponseBody {
+    // The whole file and its prefixes are the same to write
+    if start == 0 {
+        ResponseBody::File { file, size: end }
+    } else {
+        ResponseBody::PartialFile { file, start, end }
+    }
+}
@@ -0,0 +1,153 @@
+//! Bandwidth throttling of responses.
+//!
+//! Writes go out at full speed, and the writer then waits until the bytes written fit the rate
+//! of the connection, so that transfers average out to the rate without splitting writes. The
+//! global rate is shared in turns instead: writes are split into quanta, each waiting for a slot,
+//! so that active transfers get equal shares.
+
+use super::ServerConfig;
+use compio::{
+    BufResult,
+    buf::{IntoInner, IoBuf},
+    io::AsyncWrite,
+    time::sleep,
+};
+use std::{
+    cell::Cell,
+    io::Result as IoResult,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Largest write made in a single turn of a [`GlobalThrottle`].
+const QUANTUM: usize = 16 * 1024;
+/// Idle time of a [`GlobalThrottle`] that may be made up for with a burst.
+const BURST: Duration = Duration::from_millis(100);
+
+/// A token bucket, limiting transfers to a rate in bytes per second.
+#[derive(Debug)]
+pub struct Bucket {
+    /// Rate tokens are added at, in bytes per second.
+    rate: u64,
+    /// Bytes that may be transferred right away, negative when in debt.
+    tokens: f64,
+    /// When tokens were last added.
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Creates a full bucket for the given rate, in bytes per second.
+    pub fn new(rate: u64) -> Self {
+        let mut bucket = Self {
+            rate: rate.max(1),
+            tokens: 0.0,
+            updated: Instant::now(),
+        };
+        bucket.tokens = bucket.burst();
+        bucket
+    }
+
+    /// Maximum number of tokens, allowing a tenth of a second of transfer at once.
+    #[allow(clippy::cast_precision_loss, reason = "Rates are approximate")]
+    fn burst(&self) -> f64 {
+        self.rate as f64 / 10.0
+    }
+
+    /// Takes the tokens for `bytes` transferred, waiting until the rate is respected.
+    #[allow(clippy::cast_precision_loss, reason = "Rates are approximate")]
+    pub async fn take(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = elapsed
+            .mul_add(self.rate as f64, self.tokens)
+            .min(self.burst());
+        self.updated = now;
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            sleep(Duration::from_secs_f64(-self.tokens / self.rate as f64)).await;
+        }
+    }
+}
+
+/// Outbound bandwidth shared by all connections of a server, handed out in turns.
+#[derive(Debug)]
+pub struct GlobalThrottle {
+    /// Rate shared by all connections, in bytes per second.
+    rate: u64,
+    /// When the last reserved turn ends.
+    next: Cell<Instant>,
+}
+
+impl GlobalThrottle {
+    /// Creates a budget of `rate` bytes per second shared by all connections.
+    #[must_use]
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            next: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Waits for a turn to send `bytes`, after those reserved before.
+    #[allow(clippy::cast_precision_loss, reason = "Rates are approximate")]
+    async fn reserve(&self, bytes: usize) {
+        let now = Instant::now();
+        let next = self.next.get();
+        let start = now
+            .checked_sub(BURST)
+            .map_or(next, |earliest| next.max(earliest));
+        let duration = Duration::from_secs_f64(bytes as f64 / self.rate as f64);
+        self.next.set(start + duration);
+        if start > now {
+            sleep(start - now).await;
+        }
+    }
+}
+
+/// A writer limited to the rates of a connection and of the server, if any.
+#[derive(Debug)]
+pub struct Throttled<W> {
+    /// The underlying writer.
+    inner: W,
+    /// The bucket limiting writes of the connection, if any.
+    bucket: Option<Bucket>,
+    /// The bandwidth shared with other connections, if limited.
+    global: Option<Rc<GlobalThrottle>>,
+}
+
+impl<W> Throttled<W> {
+    /// Limits `inner` to the rates of the configuration, if any.
+    pub fn new(inner: W, config: &ServerConfig) -> Self {
+        Self {
+            inner,
+            bucket: config.throttle.map(Bucket::new),
+            global: config.global_throttle.clone(),
+        }
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for Throttled<W> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let result = match &self.global {
+            Some(global) => {
+                let len = buf.buf_len().min(QUANTUM);
+                global.reserve(len).await;
+                let BufResult(result, slice) = self.inner.write(buf.slice(..len)).await;
+                BufResult(result, slice.into_inner())
+            }
+            None => self.inner.write(buf).await,
+        };
+        if let (Ok(written), Some(bucket)) = (&result.0, &mut self.bucket) {
+            bucket.take(*written).await;
+        }
+        result
+    }
+
+    async fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> IoResult<()> {
+        self.inner.shutdown().await
+    }
+}
@@ -0,0 +1,165 @@
+//! Per-phase timings of requests, to locate bottlenecks in the I/O pipeline without external
+//! profilers.
+//!
+//! Timings are summed per thread like the buffer pool, and each timed request is also emitted
+//! as a `tracing` event with the `tracing` feature. Only HTTP/1.1 connections are timed, as the
+//! streams of HTTP/2 connections share their transfers.
+
+use std::{
+    cell::Cell,
+    fmt,
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    /// Timings recorded on this thread.
+    static TIMINGS: Cell<Timings> = Cell::new(Timings::default());
+}
+
+/// A phase of handling a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// From the accepted connection to the parsed request.
+    Parse,
+    /// From the parsed request to the response being ready to write its first byte.
+    FirstByte,
+    /// From the first byte of the response to the closed connection.
+    Transfer,
+}
+
+/// Aggregated durations of one phase of requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Number of requests timed.
+    pub count: u64,
+    /// Total duration.
+    pub total: Duration,
+    /// Longest duration.
+    pub max: Duration,
+}
+
+impl PhaseTimings {
+    /// Get the mean duration, zero if no requests were timed.
+    #[must_use]
+    pub fn mean(&self) -> Duration {
+        u32::try_from(self.count)
+            .ok()
+            .and_then(|count| self.total.checked_div(count))
+            .unwrap_or_default()
+    }
+
+    /// Adds a duration.
+    fn add(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+}
+
+/// Durations of the phases of requests, as recorded when enabled by
+/// [`HTTPServerBuilder::timings`](crate::HTTPServerBuilder::timings).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// From the accepted connection to the parsed request, including reading it.
+    pub parse: PhaseTimings,
+    /// From the parsed request to the response being ready to write its first byte.
+    pub first_byte: PhaseTimings,
+    /// From the first byte of the response to the closed connection.
+    pub transfer: PhaseTimings,
+    /// Number of transfers the client aborted, among the timed ones.
+    pub aborted: u64,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, phase) in [
+            ("parse", self.parse),
+            ("first byte", self.first_byte),
+            ("transfer", self.transfer),
+        ] {
+            writeln!(
+                f,
+                "{name:<10} {:>8} requests, mean {:>9.1?}, max {:>9.1?}",
+                phase.count,
+                phase.mean(),
+                phase.max
+            )?;
+        }
+        writeln!(f, "{:<10} {:>8} requests", "aborted", self.aborted)
+    }
+}
+
+/// Get the timings recorded on the current thread.
+#[must_use]
+pub fn timings() -> Timings {
+    TIMINGS.get()
+}
+
+/// Timer of the phases of a request, recording nothing unless enabled.
+#[derive(Debug)]
+pub struct Timer {
+    /// When the current phase started, or `None` if disabled.
+    since: Option<Instant>,
+    /// Durations of the phases ended so far, by [`Phase`].
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    laps: [Duration; 3],
+}
+
+impl Timer {
+    /// Starts timing the first phase of a request, if enabled.
+    pub fn start(enabled: bool) -> Self {
+        Self {
+            since: enabled.then(Instant::now),
+            laps: [Duration::ZERO; 3],
+        }
+    }
+
+    /// Ends a phase, recording its duration and starting the next one.
+    pub fn lap(&mut self, phase: Phase) {
+        let Some(since) = &mut self.since else {
+            return;
+        };
+        let now = Instant::now();
+        let duration = now - *since;
+        *since = now;
+        self.laps[phase as usize] = duration;
+        let mut timings = TIMINGS.get();
+        match phase {
+            Phase::Parse => timings.parse.add(duration),
+            Phase::FirstByte => timings.first_byte.add(duration),
+            Phase::Transfer => timings.transfer.add(duration),
+        }
+        TIMINGS.set(timings);
+    }
+
+    /// Counts the transfer of the request as aborted by the client.
+    pub fn abort(&self) {
+        if self.since.is_some() {
+            let mut timings = TIMINGS.get();
+            timings.aborted += 1;
+            TIMINGS.set(timings);
+        }
+    }
+
+    /// Ends timing a request, emitting its timings as a `tracing` event with the `tracing`
+    /// feature.
+    #[cfg_attr(
+        not(feature = "tracing"),
+        allow(clippy::unused_self, clippy::missing_const_for_fn, unused_variables)
+    )]
+    pub fn finish(self, method: &str, path: &str) {
+        #[cfg(feature = "tracing")]
+        if self.since.is_some() {
+            let [parse, first_byte, transfer] = self.laps.map(|lap| lap.as_micros());
+            tracing::debug!(
+                target: "nanoserve::timing",
+                method,
+                path,
+                parse_us = parse,
+                first_byte_us = first_byte,
+                transfer_us = transfer,
+                "request timed"
+            );
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Parsing of `application/x-www-form-urlencoded` pairs, like `page=2&sort=name`, as found in
+//! query strings and form bodies.
+//!
+//! With the `serde` feature, pairs can also be deserialized into structs whose fields are
+//! strings, numbers, booleans, unit enums or options of these.
+
+#[cfg(feature = "serde")]
+mod de;
+
+#[cfg(feature = "serde")]
+pub use de::deserialize;
+use std::borrow::Cow;
+
+/// Parse urlencoded pairs, decoding `+` as a space and percent-encoded bytes, replacing invalid
+/// UTF-8. Empty pairs are skipped, and names without `=` have an empty value.
+#[must_use]
+pub fn parse(s: &str) -> Vec<(Cow<'_, str>, Cow<'_, str>)> {
+    s.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+/// Decode `+` as a space and percent-encoded bytes, keeping a lone `%` as is.
+fn decode(s: &str) -> Cow<'_, str> {
+    if !s.contains(['+', '%']) {
+        return Cow::Borrowed(s);
+    }
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        if let Some(escaped) = escaped {
+            bytes.push(escaped);
+            rest = &tail[2..];
+        } else {
+            bytes.push(if byte == b'+' { b' ' } else { byte });
+            rest = tail;
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&bytes).into_owned())
+}
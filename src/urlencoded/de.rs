@@ -0,0 +1,108 @@
+//! Deserialization of urlencoded pairs with the `serde` feature.
+
+use serde_core::{
+    de::{
+        self, DeserializeOwned, Deserializer, IntoDeserializer, Visitor,
+        value::{Error, MapDeserializer},
+    },
+    forward_to_deserialize_any,
+};
+use std::borrow::Cow;
+
+/// Deserialize urlencoded pairs into a struct or map. Repeated names are rejected by structs as
+/// duplicate fields, and override earlier values in maps.
+///
+/// # Errors
+///
+/// Returns an [`Error`] if a field is missing or a value cannot be parsed as the type of its
+/// field.
+pub fn deserialize<T: DeserializeOwned>(
+    pairs: Vec<(Cow<'_, str>, Cow<'_, str>)>,
+) -> Result<T, Error> {
+    T::deserialize(MapDeserializer::new(
+        pairs
+            .into_iter()
+            .map(|(name, value)| (Part(name), Part(value))),
+    ))
+}
+
+/// A decoded name or value, parsed as the type it is deserialized into.
+struct Part<'a>(Cow<'a, str>);
+
+impl IntoDeserializer<'_, Error> for Part<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Implement the deserialization of a primitive by parsing the part.
+macro_rules! parse {
+    ($($method:ident => $visit:ident),* $(,)?) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0.parse() {
+                Ok(value) => visitor.$visit(value),
+                Err(_) => Err(de::Error::invalid_value(
+                    de::Unexpected::Str(&self.0),
+                    &visitor,
+                )),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Part<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.0
+            .into_owned()
+            .into_deserializer()
+            .deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0
+            .into_owned()
+            .into_deserializer()
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    parse! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
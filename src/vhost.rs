@@ -0,0 +1,43 @@
+//! Name-based virtual hosts.
+
+use super::{BasicAuth, Request};
+use std::path::PathBuf;
+
+/// Settings of a virtual host, selected by the `Host` header of requests.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualHost {
+    /// Document root of the host.
+    pub root: PathBuf,
+    /// Basic authentication for the host, overriding the server-wide configuration.
+    pub auth: Option<BasicAuth>,
+}
+
+impl VirtualHost {
+    /// Creates a virtual host serving files from the given document root.
+    #[must_use]
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            auth: None,
+        }
+    }
+
+    /// Requires Basic authentication for this host.
+    #[must_use]
+    pub fn auth(mut self, auth: BasicAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+}
+
+/// Get the host name of a request, from the `Host` header without port, in lowercase.
+#[must_use]
+pub fn host_name(request: &Request<'_>) -> Option<String> {
+    let host = request.host()?;
+    // Strip the port, taking care of IPv6 literals like `[::1]:8080`
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    Some(name.trim_end_matches('.').to_ascii_lowercase())
+}
@@ -0,0 +1,213 @@
+//! Outbound webhook notifications.
+
+use super::{Request, ResponseCode};
+use compio::{
+    BufResult,
+    io::{AsyncRead, AsyncWriteExt},
+    net::TcpStream,
+    runtime::spawn,
+};
+use std::{
+    cell::Cell,
+    fmt::Write,
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// An event that can trigger a [`Webhook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    /// The server started accepting connections.
+    Started,
+    /// A request was answered with a `5xx` status.
+    ServerError,
+    /// Too many requests were answered with `404 Not Found` in a short time.
+    NotFound,
+}
+
+impl WebhookEvent {
+    /// Name of the event, as used in payloads.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::ServerError => "server-error",
+            Self::NotFound => "not-found",
+        }
+    }
+}
+
+/// A URL to `POST` a JSON payload to when some events occur.
+///
+/// Payloads look like `{"event":"server-error","time":1700000000,"status":502,"method":"GET","path":"/api"}`.
+/// Only plain `http://` URLs are supported, so services requiring HTTPS need a relay.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    /// Authority of the URL.
+    authority: String,
+    /// Path of the URL.
+    path: String,
+    /// Events to notify of.
+    events: Vec<WebhookEvent>,
+    /// Number of `404 Not Found` responses within `not_found_window` that triggers the hook.
+    not_found_threshold: u32,
+    /// Window for `not_found_threshold`.
+    not_found_window: Duration,
+    /// `404 Not Found` responses in the current window, and when it started.
+    not_found: Cell<(u32, Option<Instant>)>,
+}
+
+impl Webhook {
+    /// Creates a webhook posting to the given `http://` URL on all events, with a default
+    /// threshold of 100 `404 Not Found` responses per minute.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the URL is not a `http://` URL.
+    pub fn new(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("unsupported webhook URL `{url}`, expected http://"))?;
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, "/"), |index| rest.split_at(index));
+        if authority.is_empty() {
+            return Err(format!("missing host in webhook URL `{url}`"));
+        }
+        let authority = if authority
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| !port.contains(']'))
+        {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(Self {
+            authority,
+            path: path.to_string(),
+            events: vec![
+                WebhookEvent::Started,
+                WebhookEvent::ServerError,
+                WebhookEvent::NotFound,
+            ],
+            not_found_threshold: 100,
+            not_found_window: Duration::from_mins(1),
+            not_found: Cell::new((0, None)),
+        })
+    }
+
+    /// Only notify of the given events.
+    #[must_use]
+    pub fn events(mut self, events: impl IntoIterator<Item = WebhookEvent>) -> Self {
+        self.events = events.into_iter().collect();
+        self
+    }
+
+    /// Sets how many `404 Not Found` responses within `window` trigger [`WebhookEvent::NotFound`].
+    #[must_use]
+    pub const fn not_found_threshold(mut self, count: u32, window: Duration) -> Self {
+        self.not_found_threshold = count;
+        self.not_found_window = window;
+        self
+    }
+
+    /// Notifies of the server having started listening on `addr`.
+    pub fn started(&self, addr: SocketAddr) {
+        if self.events.contains(&WebhookEvent::Started) {
+            self.send(
+                WebhookEvent::Started,
+                &format!(r#""address":{}"#, json_string(&addr.to_string())),
+            );
+        }
+    }
+
+    /// Notifies of a response, if it is an error of interest.
+    pub fn responded(&self, request: &Request<'_>, code: ResponseCode) {
+        let status = code.as_u16();
+        if status >= 500 && self.events.contains(&WebhookEvent::ServerError) {
+            let fields = format!(
+                r#""status":{status},"method":{},"path":{}"#,
+                json_string(request.method),
+                json_string(request.path)
+            );
+            self.send(WebhookEvent::ServerError, &fields);
+        } else if code == ResponseCode::NotFound && self.events.contains(&WebhookEvent::NotFound) {
+            let now = Instant::now();
+            let (count, start) = match self.not_found.get() {
+                (count, Some(start)) if now - start < self.not_found_window => (count + 1, start),
+                _ => (1, now),
+            };
+            if count >= self.not_found_threshold {
+                self.not_found.set((0, None));
+                let fields = format!(
+                    r#""count":{count},"window":{},"path":{}"#,
+                    self.not_found_window.as_secs(),
+                    json_string(request.path)
+                );
+                self.send(WebhookEvent::NotFound, &fields);
+            } else {
+                self.not_found.set((count, Some(start)));
+            }
+        }
+    }
+
+    /// Posts the payload of an event in the background, with the given extra JSON fields.
+    fn send(&self, event: WebhookEvent, fields: &str) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let payload = format!(r#"{{"event":"{}","time":{time},{fields}}}"#, event.name());
+        let (authority, path) = (self.authority.clone(), self.path.clone());
+        spawn(async move {
+            if let Err(e) = post(&authority, &path, payload).await {
+                eprintln!("Error while calling webhook at {authority}{path}: {e}");
+            }
+        })
+        .detach();
+    }
+}
+
+/// Post a JSON payload, and check that the response is successful.
+async fn post(authority: &str, path: &str, payload: String) -> IoResult<()> {
+    let mut stream = TcpStream::connect(authority).await?;
+    let mut request = format!(
+        "POST {path} HTTP/1.0\r\nHost: {authority}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        payload.len()
+    );
+    request.push_str(&payload);
+    stream.write_all(request).await.0?;
+    let BufResult(result, buffer) = stream.read(Vec::with_capacity(1024)).await;
+    result?;
+    let status = buffer
+        .split(|&b| b == b' ')
+        .nth(1)
+        .and_then(|status| std::str::from_utf8(status).ok()?.parse::<u16>().ok())
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "invalid response"))?;
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(IoError::other(format!("status {status}")))
+    }
+}
+
+/// Encode a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,9 @@
+#!/bin/bash
+# Grow a file served by `nanoserve --metadata-cache 60000` from the current directory, so that
+# its cached metadata has the old size
+# Expect 200 with the new content both times, as the metadata is read again rather than 500
+echo short > cached-edit.txt
+trap 'rm -f cached-edit.txt' EXIT
+curl -s -w "%{http_code}\n" http://127.0.0.1:8080/cached-edit.txt
+echo "a longer line" > cached-edit.txt
+curl -s -w "%{http_code}\n" http://127.0.0.1:8080/cached-edit.txt
@@ -0,0 +1,12 @@
+#!/bin/bash
+# Edit a file served by `nanoserve` from the current directory without changing its size, and
+# revalidate it with the entity tag it had before the edit
+# Expect 200 with the new content, as the tag changes with the modification time
+echo old1 > etag-edit.txt
+trap 'rm -f etag-edit.txt' EXIT
+etag=$(curl -sI http://127.0.0.1:8080/etag-edit.txt | tr -d '\r' | sed -n 's/^ETag: //Ip')
+echo "Before the edit: ${etag:-no ETag}"
+# Leave time for coarse timestamps to tick
+sleep 1
+echo new2 > etag-edit.txt
+curl -s -w "%{http_code}\n" -H "If-None-Match: $etag" http://127.0.0.1:8080/etag-edit.txt
@@ -0,0 +1,25 @@
+#!/bin/bash
+# Send HEAD with ranges to `nanoserve` serving the current directory, and print each response
+# Expect the head of the response to GET without a body: 206 with Content-Range and the length of
+# the slice, or 416 with `Content-Range: bytes */size`, like for suffix ranges on empty files
+: > head-range-empty.txt
+trap 'rm -f head-range-empty.txt' EXIT
+request() {
+    # The server reads the request at once, so it must be sent in a single write
+    printf -v data "HEAD /%s HTTP/1.1\r\nHost: 127.0.0.1\r\nRange: %s\r\n\r\n" "$1" "$2"
+    exec 3<>/dev/tcp/127.0.0.1/8080
+    echo -n "$data" >&3
+    # Everything after the blank line would be a body
+    tr -d '\r' <&3 | grep -i -e "^HTTP/" -e "^Content-Range" -e "^Content-Length" -e "^$" -A 1 \
+        | grep -v -e "^--$" -e "^Date" -e "^ETag" -e "^Accept-Ranges" -e "^Server"
+    exec 3<&-
+}
+
+for range in "bytes=0-3" "bytes=-4" "bytes=-100000" "bytes=100000-" "bytes=3-0"; do
+    echo "Cargo.toml with $range:"
+    request Cargo.toml "$range"
+done
+for range in "bytes=-4" "bytes=0-"; do
+    echo "Empty file with $range:"
+    request head-range-empty.txt "$range"
+done
@@ -0,0 +1,18 @@
+#!/bin/bash
+# Send targets with NUL bytes, control characters and other dangerous escapes
+# Expect 400 for all but the backslash, which is 404 on Unix where it is a literal character, and
+# 400 on Windows where it separates paths
+request() {
+    # The server reads the request at once, so it must be sent in a single write
+    printf -v data "GET %s HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n" "$1"
+    exec 3<>/dev/tcp/127.0.0.1/8080
+    echo -n "$data" >&3
+    head -n 1 <&3
+    exec 3<&-
+}
+
+for target in '/Cargo.toml%00' '/src%00/lib.rs' '/Cargo%0A.toml' '/..%2FCargo.toml' '/src%2F..%2F..%2Fetc' \
+    '/%FF' "/Cargo$(printf '\001').toml" '/src%5Clib.rs'; do
+    echo -n "$target: "
+    request "$target"
+done
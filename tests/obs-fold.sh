@@ -0,0 +1,16 @@
+#!/bin/bash
+# Send a header value folded onto a second line, and a fold right after the request line
+# Expect 400 for both from `nanoserve`, and 200 then 400 from `nanoserve --unfold-headers`
+request() {
+    # The server reads the request at once, so it must be sent in a single write
+    printf -v data "$1"
+    exec 3<>/dev/tcp/127.0.0.1/8080
+    echo -n "$data" >&3
+    head -n 1 <&3
+    exec 3<&-
+}
+
+echo -n "Folded header value: "
+request 'GET /Cargo.toml HTTP/1.1\r\nHost: 127.0.0.1\r\nX-Folded: first\r\n  second\r\n\r\n'
+echo -n "Fold after request line: "
+request 'GET /Cargo.toml HTTP/1.1\r\n  Host: 127.0.0.1\r\n\r\n'
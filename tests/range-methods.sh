@@ -0,0 +1,14 @@
+#!/bin/bash
+# Send the same Range with different methods, expecting the status and Content-Range of each:
+# GET gets 206 with the slice, HEAD the same head without a body, and POST ignores the range
+for method in GET HEAD POST; do
+    echo "$method:"
+    if [ "$method" = HEAD ]; then
+        request=(--head)
+    else
+        request=(-X "$method")
+    fi
+    curl -s "${request[@]}" -H "Range: bytes=0-3" -D - -o /dev/null \
+        -w "Body bytes received: %{size_download}\n" http://127.0.0.1:8080/Cargo.toml \
+        | grep -i -e "^HTTP/" -e "^Content-Range" -e "^Content-Length" -e "^Body"
+done
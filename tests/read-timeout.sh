@@ -0,0 +1,23 @@
+#!/bin/bash
+# Send a request head in parts, then stop partway through a head, a body, and before a request
+# Expect 200, then 408, 408 and nothing after 2 seconds each from `nanoserve --read-timeout 2`
+request() {
+    exec 3<>/dev/tcp/127.0.0.1/8080
+    for part in "$@"; do
+        printf -v data "$part"
+        echo -n "$data" >&3
+        sleep 0.5
+    done
+    head -n 1 <&3
+    exec 3<&-
+}
+
+echo -n "Head in parts: "
+request 'GET /Cargo.toml HTTP/1.1\r\n' 'Host: 127.0.0.1\r\n' '\r\n'
+echo -n "Partial head: "
+request 'GET /Cargo.toml HTTP/1.1\r\nHost: 127.0.0.1\r\n'
+echo -n "Partial body: "
+request 'POST /Cargo.toml HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 10\r\n\r\nabc'
+echo -n "Idle: "
+request
+echo
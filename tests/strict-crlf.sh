@@ -0,0 +1,18 @@
+#!/bin/bash
+# Send requests with bare LF line endings, and a bare CR ending the request line
+# Expect 200 for all from `nanoserve`, and 400 for all from `nanoserve --strict-crlf`
+request() {
+    # The server reads the request at once, so it must be sent in a single write
+    printf -v data "$1"
+    exec 3<>/dev/tcp/127.0.0.1/8080
+    echo -n "$data" >&3
+    head -n 1 <&3
+    exec 3<&-
+}
+
+echo -n "Bare LF: "
+request 'GET /Cargo.toml HTTP/1.1\nHost: 127.0.0.1\n\n'
+echo -n "CRLF then bare LF: "
+request 'GET /Cargo.toml HTTP/1.1\r\nHost: 127.0.0.1\n\r\n'
+echo -n "Bare CR: "
+request 'GET /Cargo.toml HTTP/1.1\rHost: 127.0.0.1\r\n\r\n'